@@ -0,0 +1,85 @@
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+
+use formats::{self, FlareError, FlareOptions, FlareReport, Format, ListedItem};
+use sink::{DirSink, Sink};
+
+///A file that has been opened as one of the supported formats
+///
+///Nothing is read until it's asked for, so it's cheap to open an archive just to read one item.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+    ///Where the format starts inside of the file
+    offset: u64,
+    format: Format,
+    options: FlareOptions,
+}
+
+impl Archive {
+    ///Opens the file as the first format that it was detected as, with the default options
+    ///Gives None if it isn't any of the supported formats
+    pub fn open(path: &Path) -> Result<Option<Archive>, FlareError> {
+        Ok(formats::guess_format(path)?.first().map(|&format| {
+            Archive::open_with(path, format, FlareOptions::default())
+        }))
+    }
+
+    ///Opens the file as the given format, flaring with the given options
+    ///The format isn't checked until something is read
+    pub fn open_with(path: &Path, format: Format, options: FlareOptions) -> Archive {
+        Archive::open_at(path, 0, format, options)
+    }
+
+    ///Opens the format that starts offset bytes into the file, like one that was carved out
+    ///Everything inside of it is read as if the format was its own file
+    pub fn open_at(path: &Path, offset: u64, format: Format, options: FlareOptions) -> Archive {
+        Archive {
+            path: path.to_path_buf(),
+            offset,
+            format,
+            options,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    ///Describes every item inside of the archive
+    pub fn entries(&self) -> Result<Vec<ListedItem>, FlareError> {
+        formats::list_file(&self.path, self.offset, self.format, &self.options)
+    }
+
+    ///Gives a reader for the flared contents of the item with the given name
+    ///Errors that happen while reading can be turned back into a FlareError with FlareError::from()
+    pub fn entry(&self, name: &str) -> Result<Box<dyn Read>, FlareError> {
+        formats::entry_reader(&self.path, self.offset, self.format, name, &self.options)
+    }
+
+    ///Checks every item that the options let through against its stored checksum
+    ///Gives the names of the items that didn't match or couldn't be read
+    pub fn verify(&self) -> Result<Vec<String>, FlareError> {
+        formats::verify_file(&self.path, self.offset, self.format, &self.options)
+    }
+
+    ///Flares every item that the options let through into save_folder
+    pub fn extract(&self, save_folder: &Path) -> Result<FlareReport, FlareError> {
+        self.extract_to(&mut DirSink::new(save_folder.to_path_buf()))
+    }
+
+    ///Flares every item that the options let through into the sink
+    ///The sink isn't finished, so more can be saved into it afterwards
+    pub fn extract_to(&self, sink: &mut dyn Sink) -> Result<FlareReport, FlareError> {
+        formats::flare_file(&self.path, self.offset, sink, self.format, &self.options)
+    }
+}
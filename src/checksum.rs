@@ -0,0 +1,86 @@
+//!Checksums that formats use to validate their data
+use std::io::{Result as IOResult};
+use std::io::prelude::*;
+
+//The largest prime smaller than 2^16
+const ADLER_MOD: u32 = 65521;
+
+//The most bytes that can be summed before the sums could overflow a u32
+const ADLER_NMAX: usize = 5552;
+
+/// A running Adler-32 checksum, as used by zlib and the XP3 `adlr` chunk
+///
+/// Bytes can be fed in any number of pieces and will give the same hash as feeding them all at once
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub fn new() -> Adler32 {
+        Adler32 {
+            a: 1,
+            b: 0,
+        }
+    }
+
+    /// Adds more bytes to the checksum
+    pub fn update(&mut self, bytes: &[u8]) {
+        //Only take the modulo once in a while since it's the slowest part
+        for block in bytes.chunks(ADLER_NMAX) {
+            for &byte in block {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    /// Gives the checksum of all the bytes seen so far
+    pub fn hash(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Adler32 {
+        Adler32::new()
+    }
+}
+
+/// Passes along everything that's read from the inner reader, adding it to a checksum on the way
+pub struct Adler32Reader<R: Read> {
+    inner: R,
+    adler: Adler32,
+}
+
+impl <R: Read> Adler32Reader<R> {
+    pub fn new(inner: R) -> Adler32Reader<R> {
+        Adler32Reader {
+            inner,
+            adler: Adler32::new(),
+        }
+    }
+
+    /// Gives the checksum of everything that has been read so far
+    pub fn hash(&self) -> u32 {
+        self.adler.hash()
+    }
+}
+
+impl <R: Read> Read for Adler32Reader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        let read = self.inner.read(buffer)?;
+        self.adler.update(&buffer[..read]);
+        Ok(read)
+    }
+}
+
+/// Computes the Adler-32 checksum of all of the bytes in one go
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(bytes);
+    adler.hash()
+}
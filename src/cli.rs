@@ -0,0 +1,497 @@
+//!Parses the command line into the command to run and its settings
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use binaryflare::{ChecksumPolicy, FlareOptions, Format, OverwritePolicy, SymlinkPolicy,
+    TextEncoding};
+use binaryflare::formats::{crypt, simple_crypt};
+
+///Everything worked
+pub const EXIT_OK: i32 = 0;
+///Some of the files couldn't be flared, listed or packed
+pub const EXIT_ERRORS: i32 = 1;
+///The arguments didn't make sense
+pub const EXIT_USAGE: i32 = 2;
+///Some flared items didn't match their stored checksum
+pub const EXIT_BAD_CHECKSUM: i32 = 3;
+///None of the given files were a supported format
+pub const EXIT_UNSUPPORTED: i32 = 4;
+
+pub const HELP: &str = "\
+Flares binary files into all of the files hidden inside of them
+
+Usage:
+    binaryflare [extract] [options] file_path...
+    binaryflare list [options] file_path...
+    binaryflare identify [options] file_path...
+    binaryflare carve [options] file_path...
+    binaryflare verify [options] file_path...
+    binaryflare pack [options] folder archive_path
+    binaryflare encrypt-text [options] text_path encrypted_path
+    binaryflare help
+
+Commands:
+    extract     Flares every file, then flares everything that came out of them (the default)
+    list        Prints every item inside of the files without flaring anything
+    identify    Prints every format that each file could be, most confident first
+    carve       Searches all of each file for formats hidden anywhere inside and prints where they are
+    verify      Checks every item against its stored checksum without saving anything
+    pack        Packs every file inside of folder into a new XP3 archive
+    encrypt-text
+                Encrypts a UTF-8 or UTF-16 text file the way KiriKiri's simple crypt does, so an
+                edited script can be packed again
+
+Options:
+    -o, --output <dir>          Where flared files and the report are saved (default: out)
+    -s, --sink <sink>           Where extract saves the flared files: dir (into the output folder),
+                                tar:<path> or zip:<path>, where a path of - is stdout (default: dir)
+                                Files in a tar or zip are flared again from a copy in
+                                <output>/.spool
+    -r, --recurse <n>           How many folders down a given folder is read (default: 0)
+        --symlinks <policy>     What to do with symlinks inside of a given folder: skip or follow
+                                (default: skip)
+    -d, --depth <n>             How many times flared files get flared again (default: 16)
+    -j, --threads <n>           How many files are flared at the same time (default: one per core)
+    -f, --format <name>         Only use this format, can be given more than once
+                                (xp3, pe, tlg, psb, tjs or simple-crypt)
+        --overwrite <policy>    What to do with existing files: replace, skip, rename or fail
+                                (default: replace)
+        --bad-checksum <policy> What to do with items that fail their checksum: report, fail or
+                                delete (default: report)
+        --crypt <filter>        Decrypts every item with xor-hash, xor-key:<hex byte> or
+                                xor-position:<hex bytes>
+        --names <path>          A list of hashes and the real names they stand for, one per line
+                                split by a tab or comma, for archives that only store hashes
+        --text-encoding <name>  How decrypted text is saved: utf8 or utf16 (default: utf8)
+        --text-mode <mode>      The simple crypt mode that encrypt-text uses: 0, 1 or 2 (default: 1)
+        --include <glob>        Only flares the items whose names match, can be given more than once
+        --exclude <glob>        Never flares the items whose names match, can be given more than once
+        --carve                 Makes extract search all of each file for formats and flare every one
+                                that's found, each into a folder named by its offset
+        --report <format>       How the extract report is saved: text, json, jsonl or none
+                                (default: text)
+        --report-file <path>    Where the report is saved (default: a file in the output folder
+                                named by when the run finished)
+        --json                  Prints list, identify and carve as JSON
+    -q, --quiet                 Only prints errors
+    -v, --verbose               Also prints every flared file
+    -h, --help                  Prints this help
+
+Exit codes:
+    0   Everything worked
+    1   Some of the files couldn't be flared, listed or packed
+    2   The arguments didn't make sense
+    3   Some items didn't match their stored checksum (for extract only with --bad-checksum fail)
+    4   None of the files were a supported format
+";
+
+///How many times flared files get flared again if --depth isn't given
+pub const DEFAULT_DEPTH: usize = 16;
+
+///The options that are followed by a value
+const VALUE_OPTIONS: &[&str] = &[
+    "-o", "--output",
+    "-s", "--sink",
+    "-r", "--recurse",
+    "--symlinks",
+    "-d", "--depth",
+    "-j", "--threads",
+    "-f", "--format",
+    "--overwrite",
+    "--bad-checksum",
+    "--crypt",
+    "--names",
+    "--text-encoding",
+    "--text-mode",
+    "--include",
+    "--exclude",
+    "--report",
+    "--report-file",
+];
+
+///What the command line asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Extract,
+    List,
+    Identify,
+    Carve,
+    Verify,
+    Pack,
+    EncryptText,
+    Help,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "extract" => Some(Command::Extract),
+            //The old switches still work as commands
+            "list" | "--list" => Some(Command::List),
+            "identify" => Some(Command::Identify),
+            "carve" => Some(Command::Carve),
+            "verify" => Some(Command::Verify),
+            "pack" | "--pack" => Some(Command::Pack),
+            "encrypt-text" => Some(Command::EncryptText),
+            "help" => Some(Command::Help),
+            _ => None,
+        }
+    }
+}
+
+///Where extract saves the flared files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkChoice {
+    ///Into the output folder
+    Dir,
+    ///Into a tar at the path, or stdout if it's -
+    Tar(PathBuf),
+    ///Into a zip at the path, or stdout if it's -
+    Zip(PathBuf),
+}
+
+impl SinkChoice {
+    ///Checks if the flared files are being written to stdout, so nothing else can be
+    pub fn is_stdout(&self) -> bool {
+        match *self {
+            SinkChoice::Tar(ref path) | SinkChoice::Zip(ref path) => path == Path::new("-"),
+            SinkChoice::Dir => false,
+        }
+    }
+}
+
+///How much gets printed while running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+///How the report of an extract run gets saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    JsonLines,
+    None,
+}
+
+///Everything that was given on the command line
+#[derive(Debug)]
+pub struct Cli {
+    pub command: Command,
+    pub output: PathBuf,
+    pub sink: SinkChoice,
+    ///How many folders down a given folder is read, 0 only reads the files right inside of it
+    pub recurse: usize,
+    pub symlinks: SymlinkPolicy,
+    ///How many times flared files get flared again
+    pub depth: usize,
+    pub threads: Option<usize>,
+    ///Empty means that every format can be used
+    pub formats: Vec<Format>,
+    pub verbosity: Verbosity,
+    pub report: ReportFormat,
+    ///Where the report is saved instead of a file named by the time in the output folder
+    pub report_file: Option<PathBuf>,
+    ///Search all of each file for formats instead of just detecting the file
+    pub carve: bool,
+    pub json: bool,
+    ///The mode that encrypt-text uses
+    pub text_mode: simple_crypt::Mode,
+    pub options: FlareOptions,
+    ///Everything that wasn't an option, in order
+    pub paths: Vec<String>,
+}
+
+impl Cli {
+    ///Checks if the format can be used
+    pub fn allows(&self, format: Format) -> bool {
+        self.formats.is_empty() || self.formats.contains(&format)
+    }
+}
+
+///Reads the arguments (without the executable path)
+///Gives a message for the user if they don't make sense
+pub fn parse(args: &[String]) -> Result<Cli, String> {
+    let (command, mut args) = match args.first().and_then(|arg| Command::from_name(arg)) {
+        Some(command) => (command, &args[1..]),
+        None => (Command::Extract, args),
+    };
+
+    let mut cli = Cli {
+        command,
+        output: PathBuf::from("out"),
+        sink: SinkChoice::Dir,
+        recurse: 0,
+        symlinks: SymlinkPolicy::Skip,
+        depth: DEFAULT_DEPTH,
+        threads: None,
+        formats: Vec::new(),
+        verbosity: Verbosity::Normal,
+        report: ReportFormat::Text,
+        report_file: None,
+        carve: false,
+        json: false,
+        text_mode: simple_crypt::Mode::SwapBits,
+        options: FlareOptions::default(),
+        paths: Vec::new(),
+    };
+
+    while let Some(arg) = args.first() {
+        args = &args[1..];
+        //Everything after -- is a path, even if it looks like an option
+        if arg == "--" {
+            cli.paths.extend(args.iter().cloned());
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            cli.paths.push(arg.clone());
+            continue;
+        }
+
+        //These options don't have a value
+        match arg.as_str() {
+            "-h" | "--help" => {
+                cli.command = Command::Help;
+                continue;
+            },
+            "-q" | "--quiet" => {
+                cli.verbosity = Verbosity::Quiet;
+                continue;
+            },
+            "-v" | "--verbose" => {
+                cli.verbosity = Verbosity::Verbose;
+                continue;
+            },
+            "--json" => {
+                cli.json = true;
+                continue;
+            },
+            "--carve" => {
+                cli.carve = true;
+                continue;
+            },
+            _ => (),
+        }
+
+        if !VALUE_OPTIONS.contains(&arg.as_str()) {
+            return Err(format!("{} isn't a known option", arg));
+        }
+        let value = match args.first() {
+            Some(value) => value.as_str(),
+            None => return Err(format!("{} needs a value", arg)),
+        };
+        args = &args[1..];
+        match arg.as_str() {
+            "-o" | "--output" => cli.output = PathBuf::from(value),
+            "-s" | "--sink" => cli.sink = match value.find(':') {
+                _ if value == "dir" => SinkChoice::Dir,
+                Some(split) if &value[..split] == "tar" && split + 1 < value.len() => {
+                    SinkChoice::Tar(PathBuf::from(&value[split + 1..]))
+                },
+                Some(split) if &value[..split] == "zip" && split + 1 < value.len() => {
+                    SinkChoice::Zip(PathBuf::from(&value[split + 1..]))
+                },
+                _ => return Err(format!("{} needs to be one of dir, tar:<path> or zip:<path>", arg)),
+            },
+            "-r" | "--recurse" => cli.recurse = parse_number(arg, value)?,
+            "--symlinks" => cli.symlinks = match value {
+                "skip" => SymlinkPolicy::Skip,
+                "follow" => SymlinkPolicy::Follow,
+                _ => return Err(format!("{} needs to be one of skip or follow", arg)),
+            },
+            "-d" | "--depth" => cli.depth = parse_number(arg, value)?,
+            "-j" | "--threads" => {
+                let threads = parse_number(arg, value)?;
+                if threads == 0 {
+                    return Err(format!("{} needs to be at least 1", arg));
+                }
+                cli.threads = Some(threads);
+            },
+            "-f" | "--format" => match Format::from_name(value) {
+                Some(format) => cli.formats.push(format),
+                None => {
+                    let names: Vec<&str> = Format::ALL.iter().map(|format| format.name()).collect();
+                    return Err(format!("{} needs to be one of {}", arg, names.join(", ")));
+                },
+            },
+            "--overwrite" => cli.options.overwrite = match value {
+                "replace" => OverwritePolicy::Replace,
+                "skip" => OverwritePolicy::Skip,
+                "rename" => OverwritePolicy::Rename,
+                "fail" => OverwritePolicy::Fail,
+                _ => return Err(format!("{} needs to be one of replace, skip, rename or fail", arg)),
+            },
+            "--bad-checksum" => cli.options.bad_checksum = match value {
+                "report" => ChecksumPolicy::Report,
+                "fail" => ChecksumPolicy::Fail,
+                "delete" => ChecksumPolicy::Delete,
+                _ => return Err(format!("{} needs to be one of report, fail or delete", arg)),
+            },
+            "--crypt" => cli.options.crypt = match crypt::from_name(value) {
+                Some(crypt) => Some(crypt),
+                None => return Err(format!("{} needs to be one of xor-hash, xor-key:<hex byte> or \
+                    xor-position:<hex bytes>", arg)),
+            },
+            "--names" => match fs::read_to_string(value) {
+                Ok(list) => if cli.options.names.extend(&list) == 0 {
+                    return Err(format!("{} doesn't have any hashes and names", value));
+                },
+                Err(err) => return Err(format!("{} couldn't be read: {}", value, err)),
+            },
+            "--text-encoding" => cli.options.text_encoding = match value {
+                "utf8" => TextEncoding::Utf8,
+                "utf16" => TextEncoding::Utf16,
+                _ => return Err(format!("{} needs to be one of utf8 or utf16", arg)),
+            },
+            "--text-mode" => cli.text_mode = match value.parse().ok()
+                .and_then(simple_crypt::Mode::from_byte) {
+                Some(mode) => mode,
+                None => return Err(format!("{} needs to be one of 0, 1 or 2", arg)),
+            },
+            "--include" | "--exclude" => {
+                let added = if arg == "--include" {
+                    cli.options.filter.include(value)
+                } else {
+                    cli.options.filter.exclude(value)
+                };
+                if let Err(err) = added {
+                    return Err(format!("{} isn't a valid glob pattern: {}", value, err));
+                }
+            },
+            "--report" => cli.report = match value {
+                "text" => ReportFormat::Text,
+                "json" => ReportFormat::Json,
+                "jsonl" => ReportFormat::JsonLines,
+                "none" => ReportFormat::None,
+                _ => return Err(format!("{} needs to be one of text, json, jsonl or none", arg)),
+            },
+            "--report-file" => cli.report_file = Some(PathBuf::from(value)),
+            _ => unreachable!("every option with a value needs to be handled"),
+        }
+    }
+
+    //Archives are written as they go, so nothing can be taken back out of them
+    if cli.sink != SinkChoice::Dir && cli.options.bad_checksum == ChecksumPolicy::Delete {
+        return Err(String::from("--bad-checksum delete only works with the dir sink"));
+    }
+
+    match cli.command {
+        Command::Help => (),
+        Command::Pack if cli.paths.len() != 2 => {
+            return Err(String::from("pack needs a folder and an archive path"));
+        },
+        Command::EncryptText if cli.paths.len() != 2 => {
+            return Err(String::from("encrypt-text needs a text path and an encrypted path"));
+        },
+        _ if cli.paths.is_empty() => {
+            return Err(String::from("A file or folder needs to be supplied"));
+        },
+        _ => (),
+    }
+
+    Ok(cli)
+}
+
+fn parse_number(arg: &str, value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("{} needs a number, not {}", arg, value))
+}
+
+///Picks the exit code once a command has gone through every file, where bad checksums win over
+/// any other failure
+pub fn exit_code(bad_checksums: bool, failed: bool, any_supported: bool) -> i32 {
+    if bad_checksums {
+        EXIT_BAD_CHECKSUM
+    } else if failed {
+        EXIT_ERRORS
+    } else if !any_supported {
+        EXIT_UNSUPPORTED
+    } else {
+        EXIT_OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Cli, String> {
+        let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn uses_the_defaults() {
+        let cli = parse_args(&["data.xp3"]).unwrap();
+        assert_eq!(cli.command, Command::Extract);
+        assert_eq!(cli.paths, ["data.xp3"]);
+        assert_eq!(cli.output, PathBuf::from("out"));
+        assert_eq!(cli.sink, SinkChoice::Dir);
+        assert_eq!(cli.recurse, 0);
+        assert_eq!(cli.depth, DEFAULT_DEPTH);
+        assert_eq!(cli.depth, 16);
+        assert_eq!(cli.threads, None);
+        assert_eq!(cli.report, ReportFormat::Text);
+        assert_eq!(cli.report_file, None);
+
+        let args = ["list", "-r", "3", "--depth", "0", "-s", "tar:-", "--report-file", "run.json",
+            "data.xp3"];
+        let cli = parse_args(&args).unwrap();
+        assert_eq!(cli.command, Command::List);
+        assert_eq!(cli.recurse, 3);
+        assert_eq!(cli.depth, 0);
+        assert_eq!(cli.sink, SinkChoice::Tar(PathBuf::from("-")));
+        assert!(cli.sink.is_stdout());
+        assert_eq!(cli.report_file, Some(PathBuf::from("run.json")));
+    }
+
+    #[test]
+    fn refuses_bad_flags() {
+        for args in [
+            &["--nope", "data.xp3"][..],
+            &["data.xp3", "-o"],
+            &["-d", "deep", "data.xp3"],
+            &["-j", "0", "data.xp3"],
+            &["-s", "tar:", "data.xp3"],
+            &["-s", "rar:out.rar", "data.xp3"],
+            &["-f", "rar", "data.xp3"],
+            &["--overwrite", "maybe", "data.xp3"],
+            &["--report", "xml", "data.xp3"],
+            &["--include", "[", "data.xp3"],
+            &["--text-mode", "3", "data.xp3"],
+            &[],
+            &["pack", "folder"],
+            &["encrypt-text", "text.txt"],
+        ] {
+            assert!(parse_args(args).is_err(), "{:?} was parsed", args);
+        }
+
+        //Everything after -- is a path
+        let cli = parse_args(&["--", "--nope"]).unwrap();
+        assert_eq!(cli.paths, ["--nope"]);
+    }
+
+    #[test]
+    fn only_deletes_bad_checksums_from_folders() {
+        for sink in ["tar:out.tar", "zip:out.zip", "zip:-"] {
+            let args = ["-s", sink, "--bad-checksum", "delete", "data.xp3"];
+            let err = parse_args(&args).unwrap_err();
+            assert!(err.contains("dir"));
+        }
+        let cli = parse_args(&["-s", "dir", "--bad-checksum", "delete", "data.xp3"]).unwrap();
+        assert_eq!(cli.options.bad_checksum, ChecksumPolicy::Delete);
+    }
+
+    #[test]
+    fn picks_exit_codes() {
+        assert_eq!(exit_code(false, false, true), EXIT_OK);
+        assert_eq!(exit_code(false, true, true), EXIT_ERRORS);
+        assert_eq!(exit_code(true, true, true), EXIT_BAD_CHECKSUM);
+        assert_eq!(exit_code(false, false, false), EXIT_UNSUPPORTED);
+        assert_eq!(exit_code(false, true, false), EXIT_ERRORS);
+        assert_eq!([EXIT_OK, EXIT_ERRORS, EXIT_USAGE, EXIT_BAD_CHECKSUM, EXIT_UNSUPPORTED],
+            [0, 1, 2, 3, 4]);
+    }
+}
@@ -1,67 +1,147 @@
 ///These are all file utility functions
-use std::fs::{DirBuilder, File};
+use std::collections::{HashSet};
+use std::fs::{self, DirBuilder, File};
+use std::io::{self, Result as IOResult};
 use std::path::{Path, PathBuf};
-use std::process::{self};
+
+use sha2::{Digest, Sha256};
 
 //Gets all of the files that are in a directory
-pub fn all_files(path: &Path, recurse: bool) -> Vec<PathBuf> {
-    path.read_dir().unwrap().flat_map(|entry| {
-        let path = entry.expect("Failed to read a directory").path();
+//Gives the error of the first directory that couldn't be read
+pub fn all_files(path: &Path, recurse: bool) -> IOResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in path.read_dir()? {
+        let path = entry?.path();
         if path.is_dir() && recurse {
-            all_files(&path, true)
+            files.extend(all_files(&path, true)?);
         } else {
-            vec![path]
+            files.push(path);
         }
-    }).collect()
-}
-
-//Makes the given path (old) relative to the (new_base) directory
-pub fn make_relative(old: &PathBuf, new_base: &PathBuf) -> PathBuf {
-    let last = new_base.components().last().unwrap();
-    let mut new_path = new_base.clone();
-
-    //Get rid of the parts of the old path that don't match the last
-    new_path.push(old.components()
-        .skip_while(|part| part != &last)
-        //Since last == current_part we need to skip past current_part
-        //If we don't, we will end up with a nested folder with a duplicate name as last
-        .skip(1)
-        .map(|part| part.as_os_str())
-        .collect::<PathBuf>()
-    );
-
-    new_path
+    }
+    Ok(files)
 }
 
 //Creates a file for writing given the path
-pub fn make_file(path: &PathBuf) -> File {
+pub fn make_file(path: &Path) -> IOResult<File> {
     let parent = path.parent().unwrap();
     //Only create the parent directory if we need to
     if !parent.is_dir() {
         DirBuilder::new()
             .recursive(true)
-            .create(parent)
-            .unwrap();
+            .create(parent)?;
     }
 
-    File::create(path).unwrap()
+    File::create(path)
 }
 
-/// Gets the file name of a file and converts it to a String
-pub fn file_stem(file: &PathBuf) -> String {
-    if let Some(name) = file.file_stem() {
-        if let Some(string) = name.to_str() {
-            return String::from(string);
+/// What to do with symlinks while walking a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Pretend that they aren't there
+    #[default]
+    Skip,
+    /// Walk into the folders and take the files that they point to
+    Follow,
+}
+
+/// Finds every file inside of root, going at most max_depth folders down (None means no limit)
+/// A max_depth of 0 only gives the files directly inside of root. The files are sorted.
+///
+/// Every folder is only walked once, so a symlink that loops back into a folder that was already
+/// walked is skipped. Anything that can't be read is given back with its error instead of
+/// stopping the walk.
+pub fn walk_files(root: &Path, max_depth: Option<usize>, symlinks: SymlinkPolicy)
+-> (Vec<PathBuf>, Vec<(PathBuf, io::Error)>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut visited = HashSet::new();
+    walk_folder(root, 0, max_depth, symlinks, &mut visited, &mut files, &mut errors);
+
+    files.sort();
+    (files, errors)
+}
+
+fn walk_folder(folder: &Path, depth: usize, max_depth: Option<usize>, symlinks: SymlinkPolicy,
+    visited: &mut HashSet<PathBuf>, files: &mut Vec<PathBuf>, errors: &mut Vec<(PathBuf, io::Error)>) {
+    //The real path is what tells us if we've been here before
+    match folder.canonicalize() {
+        Ok(real_path) => if !visited.insert(real_path) {
+            errors.push((folder.to_path_buf(), io::Error::other("it leads back to a folder that was already read")));
+            return;
+        },
+        Err(err) => {
+            errors.push((folder.to_path_buf(), err));
+            return;
+        },
+    }
+
+    let entries = match folder.read_dir() {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push((folder.to_path_buf(), err));
+            return;
+        },
+    };
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => paths.push(entry.path()),
+            Err(err) => errors.push((folder.to_path_buf(), err)),
         }
     }
+    paths.sort();
+
+    for path in paths {
+        let is_symlink = fs::symlink_metadata(&path)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink && symlinks == SymlinkPolicy::Skip {
+            continue;
+        }
+
+        //This follows the symlink to whatever it points to
+        if path.is_dir() {
+            if max_depth.is_none_or(|max_depth| depth < max_depth) {
+                walk_folder(&path, depth + 1, max_depth, symlinks, visited, files, errors);
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+/// Hashes the whole file with SHA-256
+/// Gives the size of the file with the hash as lowercase hex
+pub fn hash_file(path: &Path) -> IOResult<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)?;
+
+    let hash = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok((size, hash))
+}
+
+/// Finds a path next to the given one that isn't used yet, as told by exists
+/// A number is added to the file stem, like `name (1).ext`
+pub fn unused_path<F: FnMut(&Path) -> bool>(path: &Path, mut exists: F) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..).map(|number| path.with_file_name(format!("{} ({}){}", stem, number, extension)))
+        .find(|new_path| !exists(new_path))
+        .unwrap()
+}
 
-    // The conversion failed or it didn't have a valid file name
-    println!("{} must have a valid file name", file.display());
-    process::exit(-2);
+/// Gets the file name of a file without its extension and converts it to a String
+/// Gives None if there isn't a file name, like for `..` or `/`
+pub fn file_stem(file: &Path) -> Option<String> {
+    // Names that aren't valid unicode are still better than nothing
+    file.file_stem().map(|name| name.to_string_lossy().into_owned())
 }
 
 /// Gets the file extension of a file and converts it to a String
-pub fn extension(file: &PathBuf) -> String {
+pub fn extension(file: &Path) -> String {
     if let Some(ext) = file.extension() {
         if let Some(string) = ext.to_str() {
             return String::from(string);
@@ -71,3 +151,80 @@ pub fn extension(file: &PathBuf) -> String {
     // Fallback for a file without an extension
     String::new()
 }
+
+/// Turns the name of a file from inside of an archive into a path inside of save_folder
+///
+/// Archive names can't be trusted, so:
+/// - Both `\` and `/` are treated as separators
+/// - Absolute roots, empty and `.` parts are dropped
+/// - `..` parts are rewritten to `__` so nothing can be written outside of save_folder
+/// - Characters and names that the host can't use are replaced with `_`
+///
+/// Gives the path along with whether the name had to be rewritten. Only changing the separators
+/// doesn't count as a rewrite.
+pub fn member_path(save_folder: &Path, name: &str) -> (PathBuf, bool) {
+    let mut path = save_folder.to_path_buf();
+    let mut normalized = Vec::new();
+
+    for part in name.split(['/', '\\']) {
+        if part.is_empty() || part == "." {
+            continue;
+        }
+
+        let part = if part == ".." {
+            String::from("__")
+        } else {
+            sanitize_part(part)
+        };
+        path.push(&part);
+        normalized.push(part);
+    }
+
+    if normalized.is_empty() {
+        normalized.push(String::from("_"));
+        path.push("_");
+    }
+
+    let rewritten = normalized.join("/") != name.replace('\\', "/");
+    (path, rewritten)
+}
+
+/// Replaces everything in a single part of a path that can't be used on this host
+fn sanitize_part(part: &str) -> String {
+    let mut part: String = part.chars().map(|c| {
+        if is_illegal_char(c) {
+            '_'
+        } else {
+            c
+        }
+    }).collect();
+
+    if cfg!(windows) {
+        // Windows silently drops trailing dots and spaces
+        if part.ends_with('.') || part.ends_with(' ') {
+            part.push('_');
+        }
+
+        // The device names are reserved no matter what extension they have
+        let stem = part.split('.').next().unwrap_or("").to_ascii_uppercase();
+        let reserved = match stem.as_str() {
+            "CON" | "PRN" | "AUX" | "NUL" => true,
+            _ => (stem.starts_with("COM") || stem.starts_with("LPT")) && stem.len() == 4 &&
+                stem.as_bytes()[3].is_ascii_digit() && stem.as_bytes()[3] != b'0',
+        };
+        if reserved {
+            part.insert(0, '_');
+        }
+    }
+
+    part
+}
+
+/// Checks if a character can never be in a file name on this host
+fn is_illegal_char(c: char) -> bool {
+    if cfg!(windows) {
+        c < ' ' || "<>:\"|?*".contains(c)
+    } else {
+        c == '\0'
+    }
+}
@@ -0,0 +1,83 @@
+use std::cmp;
+use std::io::{Result as IOResult, SeekFrom};
+use std::path::{Path};
+
+use stream::{FileStream, OffsetStream, Stream};
+use super::{Format};
+use super::signature::{SIGNATURES};
+
+//How much of the file is searched at a time
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+///Magic bytes turn up by chance in big files, so a hit needs more than the magic to be kept
+pub const MIN_CARVE_CONFIDENCE: u8 = 80;
+
+///A format that was found somewhere inside of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CarveHit {
+    ///Where the format starts inside of the file
+    pub offset: u64,
+    pub format: Format,
+    ///From MIN_CARVE_CONFIDENCE to 100
+    pub confidence: u8,
+}
+
+///Searches the whole file for every registered signature, no matter where it is
+///
+///Every place where the magic bytes of a format are found gets validated as if the format started
+///there, and only the ones with at least MIN_CARVE_CONFIDENCE are kept. Hits are sorted by their offset.
+pub fn carve(file: &Path) -> IOResult<Vec<CarveHit>> {
+    let mut stream = FileStream::open(file, true)?;
+    let len = stream.len();
+    //Chunks overlap by this much so that magic split between two of them is still found
+    let overlap = SIGNATURES.iter()
+        .flat_map(|signature| signature.magic)
+        .map(|magic| magic.bytes.len())
+        .max()
+        .unwrap_or(1) - 1;
+
+    let mut hits = Vec::new();
+    let mut chunk_start = 0_u64;
+    while chunk_start < len {
+        let chunk_size = cmp::min(len - chunk_start, (CHUNK_SIZE + overlap) as u64) as usize;
+        stream.seek(SeekFrom::Start(chunk_start))?;
+        let chunk = stream.read_exact(chunk_size)?;
+
+        //Magic found in the overlap gets found again with the next chunk
+        let last_start = if chunk_start + (chunk_size as u64) < len {
+            CHUNK_SIZE
+        } else {
+            chunk_size
+        };
+        for start in 0..last_start {
+            for signature in SIGNATURES {
+                for magic in signature.magic.iter().filter(|magic| magic.carve) {
+                    let found = chunk.get(start..start + magic.bytes.len()) == Some(magic.bytes);
+                    let found_at = chunk_start + start as u64;
+                    if !found || found_at < magic.offset {
+                        continue;
+                    }
+
+                    let offset = found_at - magic.offset;
+                    let confidence = match OffsetStream::new(&mut stream, offset) {
+                        Ok(mut format_stream) => (signature.validate)(&mut format_stream),
+                        Err(_) => 0,
+                    };
+                    let hit = CarveHit {
+                        offset,
+                        format: signature.format,
+                        confidence,
+                    };
+                    if confidence >= MIN_CARVE_CONFIDENCE && !hits.contains(&hit) {
+                        hits.push(hit);
+                    }
+                }
+            }
+        }
+
+        chunk_start += CHUNK_SIZE as u64;
+    }
+
+    hits.sort_by_key(|hit| hit.offset);
+    Ok(hits)
+}
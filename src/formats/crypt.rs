@@ -0,0 +1,93 @@
+use std::fmt::{Debug};
+use std::sync::{Arc};
+
+/// Decrypts the data of an item after it has been decompressed
+///
+/// Many commercial KiriKiri games encrypt their archives with their own scheme, so a filter can be
+/// picked for each run.
+pub trait CryptFilter: Debug + Send + Sync {
+    /// Decrypts the buffer in place
+    ///
+    /// offset is where the buffer will be written in the flared file.
+    /// file_hash is the hash that was stored for the item in the archive.
+    fn decrypt(&self, buffer: &mut [u8], offset: u64, file_hash: u32);
+}
+
+/// XORs every byte with the lowest byte of the item's hash
+#[derive(Debug)]
+pub struct XorHash;
+
+impl CryptFilter for XorHash {
+    fn decrypt(&self, buffer: &mut [u8], _offset: u64, file_hash: u32) {
+        let key = file_hash as u8;
+        for byte in buffer.iter_mut() {
+            *byte ^= key;
+        }
+    }
+}
+
+/// XORs every byte with the same key byte
+#[derive(Debug)]
+pub struct XorKey {
+    key: u8,
+}
+
+impl CryptFilter for XorKey {
+    fn decrypt(&self, buffer: &mut [u8], _offset: u64, _file_hash: u32) {
+        for byte in buffer.iter_mut() {
+            *byte ^= self.key;
+        }
+    }
+}
+
+/// XORs every byte with a key that repeats along the whole file
+/// The key byte to use depends on the byte's position in the flared file, not the segment
+#[derive(Debug)]
+pub struct XorPosition {
+    key: Vec<u8>,
+}
+
+impl CryptFilter for XorPosition {
+    fn decrypt(&self, buffer: &mut [u8], offset: u64, _file_hash: u32) {
+        let key_len = self.key.len() as u64;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte ^= self.key[((offset + i as u64) % key_len) as usize];
+        }
+    }
+}
+
+/// Creates one of the built-in filters from its name
+///
+/// The names are:
+/// - `xor-hash`
+/// - `xor-key:<hex byte>`
+/// - `xor-position:<hex bytes>`
+pub fn from_name(name: &str) -> Option<Arc<dyn CryptFilter>> {
+    let (kind, key) = match name.find(':') {
+        Some(split) => (&name[..split], Some(&name[split + 1..])),
+        None => (name, None),
+    };
+
+    match (kind, key.map(parse_hex)) {
+        ("xor-hash", None) => Some(Arc::new(XorHash)),
+        ("xor-key", Some(Some(ref key))) if key.len() == 1 => Some(Arc::new(XorKey {
+            key: key[0],
+        })),
+        ("xor-position", Some(Some(key))) => Some(Arc::new(XorPosition {
+            key,
+        })),
+        _ => None,
+    }
+}
+
+/// Parses a string of hex digit pairs into bytes
+/// Gives None if it's empty or isn't all hex
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| {
+        hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok())
+    }).collect()
+}
@@ -0,0 +1,98 @@
+use std::error::{Error};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+use std::path::{PathBuf};
+
+/// Everything that can go wrong while a converter is flaring or listing a file
+///
+/// Offsets are from the start of whichever stream was being read when it went wrong. For an
+/// archive index that is the decompressed index, not the file.
+#[derive(Debug)]
+pub enum FlareError {
+    /// The stream ended before everything that was needed could be read
+    Truncated {
+        offset: u64,
+    },
+    /// A flag had a value that the format doesn't know what to do with
+    BadFlag {
+        what: &'static str,
+        flag: u64,
+        offset: u64,
+    },
+    /// The structure of the format didn't make sense
+    Malformed {
+        reason: String,
+        offset: u64,
+    },
+    /// Some compressed data couldn't be decompressed
+    Decompression {
+        offset: u64,
+        source: io::Error,
+    },
+    /// There wasn't an item with the name that was asked for
+    NotFound {
+        name: String,
+    },
+    /// A flared file would have been saved over a file that was already there
+    OutputExists {
+        path: PathBuf,
+    },
+    /// Reading or writing failed for any other reason
+    Io(io::Error),
+}
+
+impl FlareError {
+    /// Turns an error from reading at offset into the matching error
+    pub fn from_read(err: io::Error, offset: u64) -> FlareError {
+        if err.kind() == ErrorKind::UnexpectedEof {
+            FlareError::Truncated {
+                offset,
+            }
+        } else {
+            FlareError::Io(err)
+        }
+    }
+}
+
+impl Display for FlareError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            FlareError::Truncated { offset } => {
+                write!(f, "the stream ended early while reading at 0x{:x}", offset)
+            },
+            FlareError::BadFlag { what, flag, offset } => {
+                write!(f, "bad {} flag 0x{:x} at 0x{:x}", what, flag, offset)
+            },
+            FlareError::Malformed { ref reason, offset } => {
+                write!(f, "{} at 0x{:x}", reason, offset)
+            },
+            FlareError::Decompression { offset, ref source } => {
+                write!(f, "failed to decompress the data at 0x{:x}: {}", offset, source)
+            },
+            FlareError::NotFound { ref name } => write!(f, "there isn't an item named {}", name),
+            FlareError::OutputExists { ref path } => write!(f, "{} already exists", path.display()),
+            FlareError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for FlareError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FlareError::Decompression { ref source, .. } => Some(source),
+            FlareError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A FlareError that had to go through a Read is taken back out of the io::Error
+impl From<io::Error> for FlareError {
+    fn from(err: io::Error) -> FlareError {
+        if err.get_ref().is_some_and(|inner| inner.is::<FlareError>()) {
+            *err.into_inner().unwrap().downcast::<FlareError>().unwrap()
+        } else {
+            FlareError::Io(err)
+        }
+    }
+}
@@ -1,60 +1,488 @@
+pub mod crypt;
+mod carve;
+mod error;
+mod pe;
+mod png;
+mod psb;
+mod signature;
+pub mod simple_crypt;
+mod tjs;
+mod tlg;
 mod xp3;
+mod xp3_writer;
 
-use std::fs::{File};
+use std::borrow::{Cow};
+use std::collections::{BTreeMap};
+use std::io::{Result as IOResult};
 use std::io::prelude::*;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
 
 
+pub use self::carve::{carve, CarveHit, MIN_CARVE_CONFIDENCE};
+pub use self::error::{FlareError};
+pub use self::signature::{identify, Candidate, Magic, Signature, MIN_CONFIDENCE, SIGNATURES};
+pub use self::xp3_writer::{XP3ItemChunks, XP3Writer};
+
+use glob::{Pattern, PatternError};
+
+use self::crypt::{CryptFilter};
+use self::pe::{PEExecutable};
+use self::psb::{PSBContainer};
+use self::simple_crypt::{SimpleCryptText};
+use self::tjs::{TJS2Bytecode};
+use self::tlg::{TLGImage};
 use self::xp3::{XP3Archive};
-use stream::{ReadStream};
+use file_utils;
+use sink::{self, Sink};
+use stream::{FileStream, OffsetStream, Readable, Stream};
 
 /// Specifies how something can convert one file format into another
 trait Converter {
-    /// Check the given stream to see if the format is correct
+    /// Check the given stream to see how confident we can be that it's the format, from 0 to 100
+    /// 0 means that it isn't the format at all. Only the formats with magic bytes that matched
+    /// the file in the signature registry get checked.
     /// 
     /// The given stream could be in an odd state, so it is a good idea to reset it's state first
     /// thing.
-    fn is_correct_format<R: Read + Seek>(stream: &mut ReadStream<R>) -> bool;
+    fn confidence<S: Stream>(stream: &mut S) -> u8;
 
-    /// Gives a new initialized object of itself
-    fn new() -> Self;
+    /// Gives a new initialized object of itself that flares with the given options
+    fn new(options: FlareOptions) -> Self;
 
     /// The given stream will start at the beginning of the format
     /// It is assumed that if you are being called, the stream is the correct format.
-    /// Save every flared file into the save_folder.
-    fn flare<R: Read + Seek>(&mut self, stream: ReadStream<R>, save_folder: &PathBuf);
+    /// Save every flared file into the sink.
+    /// Every saved file needs to be in the report's entries, since nothing else looks at what
+    /// ended up in the sink.
+    /// The path for every flared file needs to come from file_utils::member_path() since names
+    /// inside of a file can't be trusted.
+    /// Anything that goes wrong is given back as an error instead of panicking, so that the other
+    /// files can still be flared.
+    fn flare<S: Stream + Read>(&mut self, stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError>;
+
+    /// Reads just enough of the stream to describe every item that flare() would create
+    /// Nothing should be written anywhere.
+    fn list<S: Stream>(&mut self, stream: S)
+    -> Result<Vec<ListedItem>, FlareError>;
+
+    /// Gives a reader for the flared contents of the item with the given name
+    /// Nothing should be written anywhere.
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError>;
+
+    /// Reads every item that flare() would create and checks it against its stored checksum
+    /// Gives the names of the items that didn't match or couldn't be read.
+    /// Nothing should be written anywhere.
+    fn verify<S: Stream + Read>(&mut self, stream: S)
+    -> Result<Vec<String>, FlareError>;
+}
+
+/// Options that change how every converter flares its files
+#[derive(Debug, Clone, Default)]
+pub struct FlareOptions {
+    /// What to do with a flared file that doesn't match its stored checksum
+    pub bad_checksum: ChecksumPolicy,
+    /// Decrypts the flared data, if it was encrypted
+    pub crypt: Option<Arc<dyn CryptFilter>>,
+    /// Picks which items get flared by their names
+    pub filter: NameFilter,
+    /// Real names for the items that an archive only stored hashes of
+    pub names: NameList,
+    /// What to do when a flared file would be saved over a file that's already there
+    pub overwrite: OverwritePolicy,
+    /// How text that had to be decrypted is saved
+    pub text_encoding: TextEncoding,
+}
+
+/// Picks items by matching their names against include and exclude glob patterns
+///
+/// An item is flared if it matches any include pattern (or there aren't any) and none of the
+/// exclude patterns. Names are always matched with `/` as the separator.
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    includes: Vec<Pattern>,
+    excludes: Vec<Pattern>,
+}
+
+impl NameFilter {
+    /// Adds a pattern that items must match to be flared
+    pub fn include(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.includes.push(NameFilter::make_pattern(pattern)?);
+        Ok(())
+    }
+
+    /// Adds a pattern that stops matching items from being flared
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.excludes.push(NameFilter::make_pattern(pattern)?);
+        Ok(())
+    }
+
+    /// Returns true if nothing will be filtered
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    /// Checks if the item with the given name should be flared
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.replace('\\', "/");
+        (self.includes.is_empty() || self.includes.iter().any(|pattern| pattern.matches(&name))) &&
+            !self.excludes.iter().any(|pattern| pattern.matches(&name))
+    }
+
+    /// A pattern for a folder (ending with a `/`) matches everything inside of it
+    fn make_pattern(pattern: &str) -> Result<Pattern, PatternError> {
+        if pattern.ends_with('/') {
+            Pattern::new(&format!("{}**", pattern))
+        } else {
+            Pattern::new(pattern)
+        }
+    }
+}
+
+/// Real names for the items that an archive only stores hashes of, like hashed XP3 archives
+///
+/// Every line of a name list is a hash, then a tab or comma and the name that it stands for.
+/// Hashes are matched without caring about case.
+#[derive(Debug, Clone, Default)]
+pub struct NameList {
+    //Shared since the options get cloned for every file and a name list can be big
+    names: Arc<BTreeMap<String, String>>,
+}
+
+impl NameList {
+    /// Adds every hash and name from the lines of a name list
+    /// Gives how many names were added. Lines without both a hash and a name are skipped.
+    pub fn extend(&mut self, list: &str) -> usize {
+        let mut added = 0;
+        for line in list.lines() {
+            let split = match line.find(['\t', ',']) {
+                Some(split) => split,
+                None => continue,
+            };
+            let (hash, name) = (line[..split].trim(), line[split + 1..].trim());
+            if !hash.is_empty() && !name.is_empty() {
+                self.insert(hash, name);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Adds the real name for a hash, replacing the name it had before
+    pub fn insert(&mut self, hash: &str, name: &str) {
+        Arc::make_mut(&mut self.names).insert(hash.to_lowercase(), String::from(name));
+    }
+
+    /// Gives the real name for the hash if it's in the list
+    pub fn get(&self, hash: &str) -> Option<&str> {
+        self.names.get(&hash.to_lowercase()).map(String::as_str)
+    }
+
+    /// Returns true if there aren't any names
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// What should happen when a flared file doesn't match the checksum that was stored for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    /// Keep the file but report it
+    #[default]
+    Report,
+    /// Keep the file and report it, but the whole run should fail at the end
+    Fail,
+    /// Report the file and delete it so that nothing uses the bad output
+    Delete,
+}
+
+/// What should happen when a flared file is about to be saved where a file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Replace the file that's there
+    #[default]
+    Replace,
+    /// Keep the file that's there and don't flare the item
+    Skip,
+    /// Save the flared file next to the one that's there with a number added to its name
+    Rename,
+    /// Stop flaring the file with an error
+    Fail,
+}
+
+/// How text that a converter decoded is saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    /// UTF-8 without a byte order mark
+    #[default]
+    Utf8,
+    /// UTF-16 little endian with a byte order mark, which every version of KiriKiri can read
+    Utf16,
+}
+
+/// Everything that a converter found out while flaring, besides the flared files themselves
+#[derive(Debug, Clone, Default)]
+pub struct FlareReport {
+    /// Every file that was saved and kept, in the order they were flared
+    pub entries: Vec<FlaredEntry>,
+    /// The flared files that didn't match their stored checksum
+    /// These will already be deleted if the ChecksumPolicy said so
+    pub bad_checksums: Vec<PathBuf>,
+    /// The names that had to be rewritten to be safe to save, with the path they were saved to
+    pub renamed: Vec<(String, PathBuf)>,
+    /// How many items matched the NameFilter and were flared
+    pub matched: usize,
+    /// How many items didn't match the NameFilter and were never read
+    pub skipped: usize,
+    /// The files that were already there and were kept by OverwritePolicy::Skip
+    pub existing: Vec<PathBuf>,
+    /// The items that couldn't be flared, with why, while the rest of the file still was
+    pub failed: Vec<(String, String)>,
+    /// Anything odd about the file that didn't stop it from being flared
+    pub warnings: Vec<String>,
+}
+
+impl FlareReport {
+    /// Adds everything from the other report into this one
+    pub fn merge(&mut self, other: FlareReport) {
+        self.entries.extend(other.entries);
+        self.bad_checksums.extend(other.bad_checksums);
+        self.renamed.extend(other.renamed);
+        self.matched += other.matched;
+        self.skipped += other.skipped;
+        self.existing.extend(other.existing);
+        self.failed.extend(other.failed);
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// A single file that a converter saved
+#[derive(Debug, Clone, Serialize)]
+pub struct FlaredEntry {
+    /// The name that the item had inside of the file, before it was made safe to save
+    pub name: String,
+    pub path: PathBuf,
+    /// The size of the saved file
+    pub size: u64,
+    /// Where the item's data starts inside of the file that was flared
+    pub offset: u64,
+    /// Anything else that the format knows about the item, by name
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Describes a single item inside of a file format without reading any of its data
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedItem {
+    pub name: String,
+    /// The size of the item once it's flared, or the size that it's stored as when that's only
+    /// known by decoding all of it
+    pub original_size: u64,
+    /// The size that the item takes up inside of the file
+    pub archive_size: u64,
+    /// Whether each of the item's segments are compressed, in order
+    pub compressed_segments: Vec<bool>,
+    pub protected: bool,
+    /// The checksum of the flared item, if the format keeps one
+    pub hash: Option<u32>,
 }
 
 /// This should only be available from guessing a format
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Format {
     XP3Archive,
+    PEExecutable,
+    TLGImage,
+    PSBContainer,
+    TJS2Bytecode,
+    SimpleCryptText,
+}
+
+impl Format {
+    /// Every format that can be guessed
+    pub const ALL: [Format; 6] = [
+        Format::XP3Archive,
+        Format::PEExecutable,
+        Format::TLGImage,
+        Format::PSBContainer,
+        Format::TJS2Bytecode,
+        Format::SimpleCryptText,
+    ];
+
+    /// The short name that the format can be picked by
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::XP3Archive => "xp3",
+            Format::PEExecutable => "pe",
+            Format::TLGImage => "tlg",
+            Format::PSBContainer => "psb",
+            Format::TJS2Bytecode => "tjs",
+            Format::SimpleCryptText => "simple-crypt",
+        }
+    }
+
+    /// Finds the format with the given short name
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL.iter().cloned().find(|format| format.name() == name)
+    }
 }
 
-/// Tries to guess the format of the file. If successful, will return an index that can then be
-/// used to flare the file. The roles are split like this because we shouldn't be doing any
-/// major error handling here.
+/// Tries to guess the format of the file. If successful, will return the formats that can then
+/// be used to flare the file, with the most confident one first. The roles are split like this
+/// because we shouldn't be doing any major error handling here.
 /// 
 /// Gives a vector of file formats because some file formats can be hidden inside on another.
 /// It will be empty if the file format is unsupported
-pub fn guess_format(file: &PathBuf) -> Vec<Format> {
-    let mut stream = ReadStream::new(File::open(file).unwrap(), true);
-    // Feed the stream to all of our supported formats to check for a correct format
-    [
-        (Format::XP3Archive, XP3Archive::is_correct_format(&mut stream)),
-    ].into_iter().filter_map(|&(format, is_correct_format)| {
-        if is_correct_format {
-            Some(format)
-        } else {
-            None
-        }
-    }).collect()
+pub fn guess_format(file: &Path) -> IOResult<Vec<Format>> {
+    Ok(identify(file)?.into_iter()
+        .filter(|candidate| candidate.confidence >= MIN_CONFIDENCE)
+        .map(|candidate| candidate.format)
+        .collect())
 }
 
-pub fn flare_file(file: &PathBuf, save_folder: &PathBuf, format: Format) {
-    let stream = ReadStream::new(File::open(file).unwrap(), true);
+/// Flares the file with the given format, saving everything into the sink
+/// The format starts offset bytes into the file, which is 0 unless it was carved out
+pub fn flare_file(file: &Path, offset: u64, sink: &mut dyn Sink, format: Format,
+    options: &FlareOptions)
+-> Result<FlareReport, FlareError> {
+    let stream = open_stream(file, offset)?;
     
+    let mut report = match format {
+        Format::XP3Archive => XP3Archive::new(options.clone()).flare(stream, sink),
+        Format::PEExecutable => PEExecutable::new(options.clone()).flare(stream, sink),
+        Format::TLGImage => TLGImage::new(options.clone()).flare(stream, sink),
+        Format::PSBContainer => PSBContainer::new(options.clone()).flare(stream, sink),
+        Format::TJS2Bytecode => TJS2Bytecode::new(options.clone()).flare(stream, sink),
+        Format::SimpleCryptText => SimpleCryptText::new(options.clone()).flare(stream, sink),
+    }?;
+
+    // The converter only knows where the items are from the start of its format
+    for entry in &mut report.entries {
+        entry.offset += offset;
+    }
+    Ok(report)
+}
+
+/// Lists all of the items that would be flared from the file with the given format
+pub fn list_file(file: &Path, offset: u64, format: Format, options: &FlareOptions)
+-> Result<Vec<ListedItem>, FlareError> {
+    let stream = open_stream(file, offset)?;
+
     match format {
-        Format::XP3Archive => XP3Archive::new(),
-    }.flare(stream, save_folder);
+        Format::XP3Archive => XP3Archive::new(options.clone()).list(stream),
+        Format::PEExecutable => PEExecutable::new(options.clone()).list(stream),
+        Format::TLGImage => TLGImage::new(options.clone()).list(stream),
+        Format::PSBContainer => PSBContainer::new(options.clone()).list(stream),
+        Format::TJS2Bytecode => TJS2Bytecode::new(options.clone()).list(stream),
+        Format::SimpleCryptText => SimpleCryptText::new(options.clone()).list(stream),
+    }
+}
+
+/// Checks every item in the file against its stored checksum without saving anything
+/// Gives the names of the items that didn't match or couldn't be read
+pub fn verify_file(file: &Path, offset: u64, format: Format, options: &FlareOptions)
+-> Result<Vec<String>, FlareError> {
+    let stream = open_stream(file, offset)?;
+
+    match format {
+        Format::XP3Archive => XP3Archive::new(options.clone()).verify(stream),
+        Format::PEExecutable => PEExecutable::new(options.clone()).verify(stream),
+        Format::TLGImage => TLGImage::new(options.clone()).verify(stream),
+        Format::PSBContainer => PSBContainer::new(options.clone()).verify(stream),
+        Format::TJS2Bytecode => TJS2Bytecode::new(options.clone()).verify(stream),
+        Format::SimpleCryptText => SimpleCryptText::new(options.clone()).verify(stream),
+    }
+}
+
+/// Opens a reader for the flared contents of the item with the given name
+/// The item is decompressed and decrypted as it's read, so nothing is written anywhere
+pub fn entry_reader(file: &Path, offset: u64, format: Format, name: &str, options: &FlareOptions)
+-> Result<Box<dyn Read>, FlareError> {
+    let stream = open_stream(file, offset)?;
+
+    match format {
+        Format::XP3Archive => XP3Archive::new(options.clone()).entry_reader(stream, name),
+        Format::PEExecutable => PEExecutable::new(options.clone()).entry_reader(stream, name),
+        Format::TLGImage => TLGImage::new(options.clone()).entry_reader(stream, name),
+        Format::PSBContainer => PSBContainer::new(options.clone()).entry_reader(stream, name),
+        Format::TJS2Bytecode => TJS2Bytecode::new(options.clone()).entry_reader(stream, name),
+        Format::SimpleCryptText => SimpleCryptText::new(options.clone()).entry_reader(stream, name),
+    }
+}
+
+/// Packs all of the files in folder into a new XP3 archive
+/// Returns how many files were packed
+pub fn pack_folder(folder: &Path, archive_path: &Path) -> IOResult<usize> {
+    xp3_writer::pack_folder(folder, archive_path)
+}
+
+/// Opens the file as a stream that starts offset bytes in, where the format begins
+fn open_stream(file: &Path, offset: u64) -> IOResult<OffsetStream<FileStream>> {
+    OffsetStream::new(FileStream::open(file, true)?, offset)
+}
+
+/// Picks the path inside of the sink that a flared item gets saved to, following the
+/// OverwritePolicy
+/// Gives the path with whether it had to be renamed, or None if the item should be skipped
+fn output_path(path: PathBuf, options: &FlareOptions, report: &mut FlareReport, sink: &mut dyn Sink)
+-> Result<Option<(PathBuf, bool)>, FlareError> {
+    if !sink.exists(&path) {
+        return Ok(Some((path, false)));
+    }
+
+    match options.overwrite {
+        OverwritePolicy::Replace => Ok(Some((path, false))),
+        OverwritePolicy::Skip => {
+            report.existing.push(sink.location(&path));
+            Ok(None)
+        },
+        OverwritePolicy::Rename => {
+            Ok(Some((file_utils::unused_path(&path, |new_path| sink.exists(new_path)), true)))
+        },
+        OverwritePolicy::Fail => Err(FlareError::OutputExists {
+            path: sink.location(&path),
+        }),
+    }
+}
+
+/// The items that couldn't be flared by name, with why
+type FailedItems = Vec<(String, String)>;
+
+/// Saves a single item at path, or adds it to report.failed if it can't be read or saved
+/// Gives whether it was saved. Only an error that stops the whole sink is given back.
+fn save_item(sink: &mut dyn Sink, path: &Path, name: &str, size: u64, data: &mut dyn Read,
+    report: &mut FlareReport) -> Result<bool, FlareError> {
+    let err = match sink.save(path, size, data) {
+        Ok(()) => return Ok(true),
+        Err(err) => err,
+    };
+    if sink::stops_sink(&err) {
+        return Err(FlareError::from(err));
+    }
+
+    //Whatever part of it was saved is no use to anyone
+    let _ = sink.remove(path);
+    report.failed.push((String::from(name), FlareError::from(err).to_string()));
+    Ok(false)
+}
+
+/// Reads a single Readable, giving the offset it was read from if it fails
+fn read_value<T: Readable, S: Stream>(stream: &mut S)
+-> Result<T::Out, FlareError> {
+    let offset = stream.pos();
+    stream.read::<T>().map_err(|err| FlareError::from_read(err, offset))
+}
+
+/// Reads size bytes from the stream
+/// A size that goes past the end of the stream is caught before anything gets allocated for it
+fn read_bytes<S: Stream>(stream: &mut S, size: u64) -> Result<Cow<'_, [u8]>, FlareError> {
+    let offset = stream.pos();
+    if size > stream.len().saturating_sub(offset) {
+        return Err(FlareError::Truncated {
+            offset,
+        });
+    }
+
+    stream.read_borrowed(size as usize).map_err(|err| FlareError::from_read(err, offset))
 }
@@ -0,0 +1,682 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::io::{Cursor, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+
+use super::{
+    output_path,
+    read_bytes,
+    read_value,
+    save_item,
+    Converter,
+    FailedItems,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    Format,
+    ListedItem,
+};
+use super::signature::{SIGNATURES};
+use file_utils;
+use sink::{Sink};
+use stream::{SliceStream, Stream, UTF16LE};
+
+//Notes taken from the Microsoft PE format documentation
+/*
+A PE file starts with an MS-DOS stub:
+1. "MZ" magic
+2. At 0x3c, a u32 offset to the PE signature "PE\0\0"
+The signature is followed by the COFF header (20 bytes), then the optional header, whose size is
+in the COFF header. The optional header ends with the data directories, which give the RVA and size
+of tables like the resources. The section table (40 bytes for each section) comes right after the
+optional header and maps every RVA to where its data is kept in the file.
+
+Anything after the last section's data isn't loaded by Windows. That's the overlay, which is where
+installers and game engines glue on their payloads (unless it's the certificate of a signed file).
+
+The resource directory is a tree with three levels: type, name, then language. Every entry has either
+an id or (with the high bit set) an offset to a length prefixed UTF-16 name. Every entry points at
+either another directory (with the high bit set) or a data entry that has the RVA and size of the
+resource.
+*/
+
+//Where the offset to the PE signature is kept inside of the MS-DOS stub
+const PE_OFFSET_POS: u64 = 0x3c;
+
+const PE_SIGNATURE: &[u8] = b"PE\0\0";
+
+//The optional header magic for 32 and 64 bit files, which puts the data directories in different
+// places
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+//The most sections that Windows will load
+const MAX_SECTIONS: u16 = 96;
+
+//The indexes of the data directories that are used
+const RESOURCE_DIRECTORY: usize = 2;
+const SECURITY_DIRECTORY: usize = 4;
+
+//The mask for resource entries that have a name or point at another directory
+const RESOURCE_HIGH_BIT: u32 = 1 << 31;
+
+//The most resources that are read, since directories can point at each other any number of times
+const MAX_RESOURCES: usize = 0x10000;
+
+//The resource types that are turned into something more useful than their raw data
+const RT_ICON: u32 = 3;
+const RT_GROUP_ICON: u32 = 14;
+const RT_VERSION: u32 = 16;
+
+//The signature of the VS_FIXEDFILEINFO inside of the version resource
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xfeef_04bd;
+
+pub struct PEExecutable {
+    options: FlareOptions,
+}
+
+impl Converter for PEExecutable {
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        let headers = match PEHeaders::read(stream) {
+            Ok(headers) => headers,
+            Err(_) => return 0,
+        };
+
+        //Section data that goes past the end means the file was cut short
+        let len = stream.len();
+        if headers.sections.iter().all(|section| section.raw_end() <= len) {
+            100
+        } else {
+            60
+        }
+    }
+
+    fn new(options: FlareOptions) -> PEExecutable {
+        PEExecutable {
+            options,
+        }
+    }
+
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let mut report = FlareReport::default();
+        let (items, failed) = read_items(&mut stream)?;
+        report.failed = failed;
+        let item_count = items.len();
+
+        let items: Vec<PEItem> = items.into_iter()
+            .filter(|item| self.options.filter.matches(&item.name))
+            .collect();
+        report.matched = items.len();
+        report.skipped = item_count - items.len();
+
+        for item in items {
+            let (path, rewritten) = file_utils::member_path(Path::new(""), &item.name);
+            let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+                Some(output) => output,
+                None => continue,
+            };
+            let location = sink.location(&path);
+            if rewritten || renamed {
+                report.renamed.push((item.name.clone(), location.clone()));
+            }
+
+            let saved = match item.data {
+                ItemData::Stored => {
+                    stream.seek(SeekFrom::Start(item.offset))?;
+                    save_item(sink, &path, &item.name, item.size,
+                        &mut Read::by_ref(&mut stream).take(item.size), &mut report)?
+                },
+                ItemData::Built(ref data) => {
+                    save_item(sink, &path, &item.name, item.size, &mut &data[..], &mut report)?
+                },
+            };
+            if !saved {
+                continue;
+            }
+
+            report.entries.push(FlaredEntry {
+                name: item.name,
+                path: location,
+                size: item.size,
+                offset: item.offset,
+                attributes: item.attributes,
+            });
+        }
+
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        Ok(read_items(&mut stream)?.0.into_iter().map(|item| {
+            ListedItem {
+                name: item.name,
+                original_size: item.size,
+                archive_size: item.size,
+                compressed_segments: vec![false],
+                protected: false,
+                hash: None,
+            }
+        }).collect())
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        //Nothing inside of an exe has its own checksum, but everything still has to be found
+        let (_, failed) = read_items(&mut stream)?;
+        Ok(failed.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        let item = match read_items(&mut stream)?.0.into_iter().find(|item| item.name == name) {
+            Some(item) => item,
+            None => return Err(FlareError::NotFound {
+                name: String::from(name),
+            }),
+        };
+
+        match item.data {
+            ItemData::Stored => {
+                stream.seek(SeekFrom::Start(item.offset))?;
+                Ok(Box::new(stream.take(item.size)))
+            },
+            ItemData::Built(data) => Ok(Box::new(Cursor::new(data))),
+        }
+    }
+}
+
+///Finds where the overlay of the exe starts, if the stream is an exe
+///The stream doesn't have to have an overlay, in which case this is the end of the stream
+pub fn overlay_start<S: Stream>(stream: &mut S) -> Option<u64> {
+    PEHeaders::read(stream).ok().map(|headers| headers.overlay_start())
+}
+
+///Everything from the headers that's needed to find the data inside of the exe
+struct PEHeaders {
+    sections: Vec<Section>,
+    //The RVA and size of every data directory
+    directories: Vec<(u32, u32)>,
+    size_of_headers: u32,
+}
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_offset: u32,
+    raw_size: u32,
+}
+
+impl Section {
+    ///Where the section's data ends inside of the file
+    fn raw_end(&self) -> u64 {
+        self.raw_offset as u64 + self.raw_size as u64
+    }
+}
+
+impl PEHeaders {
+    fn read<S: Stream>(stream: &mut S) -> Result<PEHeaders, FlareError> {
+        stream.little_endian(true);
+        stream.seek(SeekFrom::Start(0))?;
+        if &*read_bytes(stream, 2)? != b"MZ" {
+            return Err(FlareError::Malformed {
+                reason: String::from("there isn't an MZ header"),
+                offset: 0,
+            });
+        }
+
+        stream.seek(SeekFrom::Start(PE_OFFSET_POS))?;
+        let pe_offset = read_value::<u32, _>(stream)? as u64;
+        stream.seek(SeekFrom::Start(pe_offset))?;
+        if &*read_bytes(stream, PE_SIGNATURE.len() as u64)? != PE_SIGNATURE {
+            return Err(FlareError::Malformed {
+                reason: String::from("there isn't a PE signature"),
+                offset: pe_offset,
+            });
+        }
+
+        //The COFF header
+        stream.skip(2)?;
+        let section_count = read_value::<u16, _>(stream)?;
+        stream.skip(12)?;
+        let optional_size = read_value::<u16, _>(stream)?;
+        stream.skip(2)?;
+        if section_count > MAX_SECTIONS {
+            return Err(FlareError::Malformed {
+                reason: format!("there are {} sections", section_count),
+                offset: pe_offset,
+            });
+        }
+
+        let optional_offset = stream.pos();
+        let optional_header = read_bytes(stream, optional_size as u64)?.into_owned();
+        let mut optional = SliceStream::new(&optional_header[..], true);
+        let magic = read_value::<u16, _>(&mut optional)?;
+        let directories_pos = match magic {
+            PE32_MAGIC => 92,
+            PE32_PLUS_MAGIC => 108,
+            _ => return Err(FlareError::BadFlag {
+                what: "optional header magic",
+                flag: magic as u64,
+                offset: optional_offset,
+            }),
+        };
+        Stream::seek(&mut optional, SeekFrom::Start(60))?;
+        let size_of_headers = read_value::<u32, _>(&mut optional)?;
+        Stream::seek(&mut optional, SeekFrom::Start(directories_pos))?;
+        let directory_count = read_value::<u32, _>(&mut optional)?;
+        let mut directories = Vec::new();
+        //Only the directories that fit inside of the optional header are real
+        for _ in 0..directory_count {
+            match (read_value::<u32, _>(&mut optional), read_value::<u32, _>(&mut optional)) {
+                (Ok(rva), Ok(size)) => directories.push((rva, size)),
+                _ => break,
+            }
+        }
+
+        let mut sections = Vec::new();
+        for _ in 0..section_count {
+            //Skip the name
+            stream.skip(8)?;
+            let virtual_size = read_value::<u32, _>(stream)?;
+            let virtual_address = read_value::<u32, _>(stream)?;
+            let raw_size = read_value::<u32, _>(stream)?;
+            let raw_offset = read_value::<u32, _>(stream)?;
+            stream.skip(16)?;
+            sections.push(Section {
+                virtual_address,
+                virtual_size,
+                raw_offset,
+                raw_size,
+            });
+        }
+
+        Ok(PEHeaders {
+            sections,
+            directories,
+            size_of_headers,
+        })
+    }
+
+    ///Finds where the data at the RVA is kept inside of the file
+    fn file_offset(&self, rva: u32) -> Option<u64> {
+        self.sections.iter().find(|section| {
+            let size = section.virtual_size.max(section.raw_size);
+            rva >= section.virtual_address && ((rva - section.virtual_address) < size)
+        }).and_then(|section| {
+            let inside = rva - section.virtual_address;
+            //Anything past the raw data is only zeros in memory, so it isn't in the file
+            if inside < section.raw_size {
+                Some(section.raw_offset as u64 + inside as u64)
+            } else {
+                None
+            }
+        })
+    }
+
+    ///The end of everything that Windows loads, plus the certificate if it comes right after
+    fn overlay_start(&self) -> u64 {
+        let end = self.sections.iter()
+            .filter(|section| section.raw_size > 0)
+            .map(Section::raw_end)
+            .fold(self.size_of_headers as u64, u64::max);
+
+        //The certificate's address is an offset into the file instead of an RVA
+        match self.directories.get(SECURITY_DIRECTORY) {
+            Some(&(offset, size)) if offset as u64 == end && size > 0 => end + size as u64,
+            _ => end,
+        }
+    }
+}
+
+///A single file that can be flared out of the exe
+struct PEItem {
+    name: String,
+    //Where the data starts, or the first part of it for built items
+    offset: u64,
+    size: u64,
+    data: ItemData,
+    attributes: BTreeMap<String, String>,
+}
+
+enum ItemData {
+    //Copied straight out of the stream from the offset
+    Stored,
+    //Put together out of different parts of the exe
+    Built(Vec<u8>),
+}
+
+///The id or name of a resource's type, name or language
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ResourceId {
+    Id(u32),
+    Name(String),
+}
+
+impl Display for ResourceId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ResourceId::Id(id) => write!(f, "{}", id),
+            ResourceId::Name(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+struct Resource {
+    kind: ResourceId,
+    name: ResourceId,
+    language: ResourceId,
+    offset: u64,
+    size: u32,
+}
+
+impl Resource {
+    ///The name of the flared file, grouped into a folder for its type
+    fn item_name(&self, extension: &str) -> String {
+        let kind = match self.kind {
+            ResourceId::Id(id) => type_name(id).map_or_else(|| id.to_string(), String::from),
+            ResourceId::Name(ref name) => name.clone(),
+        };
+        format!("resources/{}/{}_{}.{}", kind, self.name, self.language, extension)
+    }
+
+    fn attributes(&self) -> BTreeMap<String, String> {
+        let mut attributes = BTreeMap::new();
+        attributes.insert(String::from("type"), self.kind.to_string());
+        attributes.insert(String::from("language"), self.language.to_string());
+        attributes
+    }
+}
+
+///The names of the standard resource types
+fn type_name(id: u32) -> Option<&'static str> {
+    Some(match id {
+        1 => "CURSOR",
+        2 => "BITMAP",
+        RT_ICON => "ICON",
+        4 => "MENU",
+        5 => "DIALOG",
+        6 => "STRING",
+        7 => "FONTDIR",
+        8 => "FONT",
+        9 => "ACCELERATOR",
+        10 => "RCDATA",
+        11 => "MESSAGETABLE",
+        12 => "GROUP_CURSOR",
+        RT_GROUP_ICON => "GROUP_ICON",
+        RT_VERSION => "VERSION",
+        17 => "DLGINCLUDE",
+        19 => "PLUGPLAY",
+        20 => "VXD",
+        21 => "ANICURSOR",
+        22 => "ANIICON",
+        23 => "HTML",
+        24 => "MANIFEST",
+        _ => return None,
+    })
+}
+
+///Finds every item that the exe can be flared into
+///Icons are put back together into .ico files, everything else is kept as it is
+///Resources that are broken are given back by name with why, so that the rest (and the overlay)
+/// can still be flared
+fn read_items<S: Stream>(stream: &mut S)
+-> Result<(Vec<PEItem>, FailedItems), FlareError> {
+    let headers = PEHeaders::read(stream)?;
+    let len = stream.len();
+    let mut failed = Vec::new();
+    let resources = match read_resources(stream, &headers, &mut failed) {
+        Ok(resources) => resources,
+        Err(err) => {
+            failed.push((String::from("resources"), err.to_string()));
+            Vec::new()
+        },
+    };
+
+    let mut items = Vec::new();
+    let mut grouped_icons = HashSet::new();
+    for resource in resources.iter().filter(|resource| resource.kind == ResourceId::Id(RT_GROUP_ICON)) {
+        let (icon, used) = match build_icon(stream, resource, &resources) {
+            Ok(built) => built,
+            Err(err) => {
+                failed.push((resource.item_name("ico"), err.to_string()));
+                continue;
+            },
+        };
+        let mut attributes = resource.attributes();
+        attributes.insert(String::from("images"), used.len().to_string());
+        grouped_icons.extend(used);
+        items.push(PEItem {
+            name: resource.item_name("ico"),
+            offset: resource.offset,
+            size: icon.len() as u64,
+            data: ItemData::Built(icon),
+            attributes,
+        });
+    }
+
+    for (index, resource) in resources.iter().enumerate() {
+        let extension = match resource.kind {
+            ResourceId::Id(RT_GROUP_ICON) => continue,
+            //Icons that are in a group were already saved with it
+            ResourceId::Id(RT_ICON) if grouped_icons.contains(&index) => continue,
+            ResourceId::Id(23) => "html",
+            ResourceId::Id(24) => "xml",
+            _ => "bin",
+        };
+
+        let mut attributes = resource.attributes();
+        if resource.kind == ResourceId::Id(RT_VERSION) {
+            stream.seek(SeekFrom::Start(resource.offset))?;
+            let data = read_bytes(stream, resource.size as u64)?;
+            version_attributes(&data, &mut attributes);
+        }
+        items.push(PEItem {
+            name: resource.item_name(extension),
+            offset: resource.offset,
+            size: resource.size as u64,
+            data: ItemData::Stored,
+            attributes,
+        });
+    }
+
+    //An overlay that another format starts with gets flared as that format instead
+    let overlay = headers.overlay_start();
+    if overlay < len {
+        stream.seek(SeekFrom::Start(overlay))?;
+        let head = read_bytes(stream, (len - overlay).min(64))?.into_owned();
+        let other_format = SIGNATURES.iter()
+            .filter(|signature| signature.format != Format::PEExecutable)
+            .flat_map(|signature| signature.magic)
+            .any(|magic| magic.carve && head.get(magic.offset as usize..)
+                .is_some_and(|head| head.starts_with(magic.bytes)));
+        if !other_format {
+            items.push(PEItem {
+                name: String::from("overlay.bin"),
+                offset: overlay,
+                size: len - overlay,
+                data: ItemData::Stored,
+                attributes: BTreeMap::new(),
+            });
+        }
+    }
+
+    Ok((items, failed))
+}
+
+///Reads every resource out of the resource directory, in the order that they're kept
+///Resources whose data isn't inside of the file are added to failed instead
+fn read_resources<S: Stream>(stream: &mut S, headers: &PEHeaders,
+    failed: &mut FailedItems) -> Result<Vec<Resource>, FlareError> {
+    let (rva, size) = match headers.directories.get(RESOURCE_DIRECTORY) {
+        Some(&(rva, size)) if rva != 0 && size != 0 => (rva, size),
+        _ => return Ok(Vec::new()),
+    };
+    let start = match headers.file_offset(rva) {
+        Some(start) => start,
+        None => return Err(FlareError::Malformed {
+            reason: format!("the resource directory at RVA 0x{:x} isn't inside of a section", rva),
+            offset: 0,
+        }),
+    };
+
+    stream.seek(SeekFrom::Start(start))?;
+    let directory = read_bytes(stream, size as u64)?.into_owned();
+    let mut directory = SliceStream::new(&directory[..], true);
+
+    //Every directory is only read once so that they can't loop or be shared
+    let mut visited = HashSet::new();
+    let mut resources = Vec::new();
+    for (kind, kind_offset) in read_directory(&mut directory, 0, true, &mut visited)? {
+        for (name, name_offset) in read_directory(&mut directory, kind_offset, true, &mut visited)? {
+            for (language, data_offset) in
+                read_directory(&mut directory, name_offset, false, &mut visited)? {
+                if resources.len() == MAX_RESOURCES {
+                    return Err(FlareError::Malformed {
+                        reason: format!("there are more than {} resources", MAX_RESOURCES),
+                        offset: start,
+                    });
+                }
+                Stream::seek(&mut directory, SeekFrom::Start(data_offset as u64))?;
+                let data_rva = read_value::<u32, _>(&mut directory)?;
+                let data_size = read_value::<u32, _>(&mut directory)?;
+                let resource = Resource {
+                    kind: kind.clone(),
+                    name: name.clone(),
+                    language,
+                    offset: 0,
+                    size: data_size,
+                };
+                match headers.file_offset(data_rva) {
+                    Some(offset) if offset + data_size as u64 <= stream.len() => {
+                        resources.push(Resource {
+                            offset,
+                            ..resource
+                        });
+                    },
+                    _ => failed.push((resource.item_name("bin"), FlareError::Malformed {
+                        reason: format!("the {} resource {} isn't inside of the file", kind, name),
+                        offset: start + data_offset as u64,
+                    }.to_string())),
+                }
+            }
+        }
+    }
+
+    Ok(resources)
+}
+
+///Reads the entries of the resource directory at offset, with where each entry points
+///Entries that don't point at a subdirectory when they should (or the other way) are skipped
+///It's malformed for a directory to be in visited already, which it gets added to
+fn read_directory(directory: &mut SliceStream<&[u8]>, offset: u32, subdirectories: bool,
+    visited: &mut HashSet<u32>) -> Result<Vec<(ResourceId, u32)>, FlareError> {
+    if !visited.insert(offset) {
+        return Err(FlareError::Malformed {
+            reason: format!("the resource directory at 0x{:x} is used more than once", offset),
+            offset: offset as u64,
+        });
+    }
+
+    //Skip the characteristics, time stamp and version
+    Stream::seek(directory, SeekFrom::Start(offset as u64 + 12))?;
+    let count = read_value::<u16, _>(directory)? as u32 + read_value::<u16, _>(directory)? as u32;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let id = read_value::<u32, _>(directory)?;
+        let target = read_value::<u32, _>(directory)?;
+        if (target & RESOURCE_HIGH_BIT != 0) != subdirectories {
+            continue;
+        }
+        entries.push((id, target & !RESOURCE_HIGH_BIT));
+    }
+
+    entries.into_iter().map(|(id, target)| {
+        let id = if id & RESOURCE_HIGH_BIT != 0 {
+            Stream::seek(directory, SeekFrom::Start((id & !RESOURCE_HIGH_BIT) as u64))?;
+            let name_len = read_value::<u16, _>(directory)?;
+            let name_offset = Stream::pos(directory);
+            ResourceId::Name(directory.read_with_len::<UTF16LE>(name_len as usize)
+                .map_err(|err| FlareError::from_read(err, name_offset))?)
+        } else {
+            ResourceId::Id(id)
+        };
+        Ok((id, target))
+    }).collect()
+}
+
+///Puts an icon group back together into a .ico file
+///Gives the .ico with the indexes of the icon resources that were used in it
+fn build_icon<S: Stream>(stream: &mut S, group: &Resource, resources: &[Resource])
+-> Result<(Vec<u8>, Vec<usize>), FlareError> {
+    stream.seek(SeekFrom::Start(group.offset))?;
+    let group_data = read_bytes(stream, group.size as u64)?.into_owned();
+    let mut group_stream = SliceStream::new(&group_data[..], true);
+    Stream::skip(&mut group_stream, 4)?;
+    let count = read_value::<u16, _>(&mut group_stream)?;
+
+    //Both have the same 6 byte header, then the .ico has 16 byte entries instead of 14
+    let mut header = group_data[..6].to_vec();
+    let mut images = Vec::new();
+    let mut used = Vec::new();
+    let mut image_offset = 6 + 16 * count as u32;
+    for _ in 0..count {
+        let entry = group_stream.read_slice(12)
+            .map_err(|err| FlareError::from_read(err, group.offset))?;
+        let id = read_value::<u16, _>(&mut group_stream)? as u32;
+
+        //An icon in the same language is the best, but any language will do
+        let icon = resources.iter().enumerate()
+            .filter(|(_, resource)| {
+                resource.kind == ResourceId::Id(RT_ICON) && resource.name == ResourceId::Id(id)
+            })
+            .min_by_key(|(_, resource)| resource.language != group.language);
+        let (index, icon) = match icon {
+            Some(icon) => icon,
+            None => return Err(FlareError::Malformed {
+                reason: format!("the icon group {} needs icon {} which isn't there", group.name, id),
+                offset: group.offset,
+            }),
+        };
+        used.push(index);
+
+        stream.seek(SeekFrom::Start(icon.offset))?;
+        let image = read_bytes(stream, icon.size as u64)?;
+        //The size in the group can be wrong, so it's always taken from the icon itself
+        header.extend_from_slice(&entry[..8]);
+        header.extend_from_slice(&icon.size.to_le_bytes());
+        header.extend_from_slice(&image_offset.to_le_bytes());
+        image_offset = image_offset.saturating_add(icon.size);
+        images.extend_from_slice(&image);
+    }
+
+    header.extend(images);
+    Ok((header, used))
+}
+
+///Adds the file and product versions from the version resource
+///Nothing is added if the resource doesn't have them
+fn version_attributes(data: &[u8], attributes: &mut BTreeMap<String, String>) {
+    //The fixed info comes after the lengths, type and the "VS_VERSION_INFO" key, lined up to 4 bytes
+    let fields: Vec<u32> = match data.get(40..64) {
+        Some(fixed) => fixed.chunks(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+        None => return,
+    };
+    if fields[0] != FIXED_FILE_INFO_SIGNATURE {
+        return;
+    }
+
+    //The signature is followed by the structure version, then each version is split in two
+    let version = |most: u32, least: u32| {
+        format!("{}.{}.{}.{}", most >> 16, most & 0xffff, least >> 16, least & 0xffff)
+    };
+    attributes.insert(String::from("file_version"), version(fields[2], fields[3]));
+    attributes.insert(String::from("product_version"), version(fields[4], fields[5]));
+}
@@ -0,0 +1,52 @@
+use std::io::prelude::*;
+
+use flate2::{Compression, Crc};
+use flate2::write::{ZlibEncoder};
+
+
+pub const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+//8 bits for each channel of RGBA
+const BIT_DEPTH: u8 = 8;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+///Encodes an image into a PNG with alpha
+///The pixels are RGBA, one row after another from the top
+pub fn encode_rgba(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(pixels.len(), width as usize * height as usize * 4);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    //No compression, filter or interlace methods other than the defaults
+    header.extend_from_slice(&[BIT_DEPTH, COLOR_TYPE_RGBA, 0, 0, 0]);
+
+    //Every row starts with the filter it used, which is always none
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    if width > 0 {
+        for row in pixels.chunks(width as usize * 4) {
+            encoder.write_all(&[0]).expect("Writing to memory can't fail");
+            encoder.write_all(row).expect("Writing to memory can't fail");
+        }
+    }
+    let data = encoder.finish().expect("Writing to memory can't fail");
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+///Writes the chunk with its length and CRC
+fn write_chunk(png: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(name);
+    png.extend_from_slice(data);
+
+    //The CRC covers the name and the data
+    let mut crc = Crc::new();
+    crc.update(name);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
@@ -0,0 +1,737 @@
+use std::collections::{BTreeMap};
+use std::io::{Cursor, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+use flate2::read::{ZlibDecoder};
+use serde_json::{self, Map, Number, Value};
+
+use super::{
+    output_path,
+    png,
+    read_bytes,
+    read_value,
+    save_item,
+    tlg,
+    Converter,
+    FailedItems,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    ListedItem,
+};
+use file_utils;
+use sink::{Sink};
+use stream::{SliceStream, Stream};
+
+//Notes taken from FreeMote and the E-mote files of KiriKiri Z games
+/*
+A PSB file is a tree of values, like JSON, kept in a binary form:
+1. "PSB\0" magic
+2. u16 version (2 to 4) and u16 that says whether the header is encrypted
+3. u32 offsets (from the start of the file) to the header's end, the name trie, the string offsets,
+   the string data, the resource offsets, the resource lengths, the resource data and the root value
+4. A u32 checksum from version 3 on, and the offsets, lengths and data of the extra resources
+   from version 4 on
+
+Every value starts with a type byte. Numbers, strings and resources have the number of bytes they
+use in their type byte. Arrays of unsigned ints (which is how all of the offsets and name indexes
+are kept) have the number of bytes for their count in the type byte, then a byte for the size of
+each entry. Lists are an array of offsets followed by their values, and objects are an array of
+name indexes, an array of offsets, then their values.
+
+The names of object keys are kept in a trie made of three arrays. Every name is found by walking
+from its end up to the root, and each step gives the byte before.
+
+The whole file is often zlib compressed behind "mdf\0" and the u32 size it decompresses to.
+
+.pimg files are layered images. Their root has a "layers" list and the image of each layer is a
+resource under a key like "<layer_id>.tlg".
+*/
+
+pub const PSB_HEADER: &[u8] = b"PSB\0";
+pub const MDF_HEADER: &[u8] = b"mdf\0";
+
+const MIN_VERSION: u16 = 2;
+const MAX_VERSION: u16 = 4;
+
+//The type bytes of the values in the tree
+const TYPE_NONE: u8 = 0x00;
+const TYPE_NULL: u8 = 0x01;
+const TYPE_FALSE: u8 = 0x02;
+const TYPE_TRUE: u8 = 0x03;
+const TYPE_NUMBER_0: u8 = 0x04;
+const TYPE_NUMBER_8: u8 = 0x0c;
+const TYPE_ARRAY_1: u8 = 0x0d;
+const TYPE_ARRAY_8: u8 = 0x14;
+const TYPE_STRING_1: u8 = 0x15;
+const TYPE_STRING_4: u8 = 0x18;
+const TYPE_RESOURCE_1: u8 = 0x19;
+const TYPE_RESOURCE_4: u8 = 0x1c;
+const TYPE_FLOAT_0: u8 = 0x1d;
+const TYPE_FLOAT: u8 = 0x1e;
+const TYPE_DOUBLE: u8 = 0x1f;
+const TYPE_LIST: u8 = 0x20;
+const TYPE_OBJECTS: u8 = 0x21;
+const TYPE_EXTRA_1: u8 = 0x22;
+const TYPE_EXTRA_4: u8 = 0x25;
+
+//How deep the tree can go and how many values it can have, so offsets that loop back can't
+// go on forever
+const MAX_DEPTH: usize = 256;
+const MAX_VALUES: usize = 1 << 22;
+//How many bytes all of the strings in the tree can add up to, since every value can use the same
+// string
+const MAX_STRING_BYTES: usize = 1 << 28;
+
+//The biggest file that an mdf will be decompressed into
+const MAX_DECOMPRESSED_SIZE: u64 = 1 << 31;
+
+//How resources are written in the tree, the same as FreeMote so the JSON can be used with it
+const RESOURCE_PREFIX: &str = "#resource#";
+const EXTRA_PREFIX: &str = "#resource@";
+
+const TREE_NAME: &str = "tree.json";
+
+pub struct PSBContainer {
+    options: FlareOptions,
+}
+
+impl Converter for PSBContainer {
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        stream.little_endian(true);
+        if stream.seek(SeekFrom::Start(0)).is_err() {
+            return 0;
+        }
+        let magic = match read_bytes(stream, PSB_HEADER.len() as u64) {
+            Ok(magic) => magic.into_owned(),
+            Err(_) => return 0,
+        };
+
+        if magic == MDF_HEADER {
+            //The start of the zlib data is enough to see the PSB header
+            if stream.skip(4).is_err() {
+                return 0;
+            }
+            let size = stream.len().saturating_sub(stream.pos()).min(64);
+            let start = match read_bytes(stream, size) {
+                Ok(start) => start.into_owned(),
+                Err(_) => return 0,
+            };
+            let mut magic = [0; 4];
+            match ZlibDecoder::new(&start[..]).read_exact(&mut magic) {
+                Ok(_) if magic == PSB_HEADER => 100,
+                Ok(_) => 0,
+                Err(_) => 60,
+            }
+        } else if magic == PSB_HEADER {
+            match Header::read(stream) {
+                Ok(_) => 100,
+                Err(_) => 60,
+            }
+        } else {
+            0
+        }
+    }
+
+    fn new(options: FlareOptions) -> PSBContainer {
+        PSBContainer {
+            options,
+        }
+    }
+
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let mut report = FlareReport::default();
+        let (items, failed) = read_items(&mut stream)?;
+        report.failed = failed;
+        let item_count = items.len();
+
+        let items: Vec<PSBItem> = items.into_iter()
+            .filter(|item| self.options.filter.matches(&item.name))
+            .collect();
+        report.matched = items.len();
+        report.skipped = item_count - items.len();
+
+        for item in items {
+            let (path, rewritten) = file_utils::member_path(Path::new(""), &item.name);
+            let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+                Some(output) => output,
+                None => continue,
+            };
+            let location = sink.location(&path);
+            if rewritten || renamed {
+                report.renamed.push((item.name.clone(), location.clone()));
+            }
+
+            let saved = match item.data {
+                ItemData::Stored => {
+                    stream.seek(SeekFrom::Start(item.offset))?;
+                    let mut data = Read::by_ref(&mut stream).take(item.size);
+                    save_item(sink, &path, &item.name, item.size, &mut data, &mut report)?
+                },
+                ItemData::Built(ref data) => {
+                    save_item(sink, &path, &item.name, item.size, &mut &data[..], &mut report)?
+                },
+            };
+            if !saved {
+                continue;
+            }
+
+            report.entries.push(FlaredEntry {
+                name: item.name,
+                path: location,
+                size: item.size,
+                offset: item.offset,
+                attributes: item.attributes,
+            });
+        }
+
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        Ok(read_items(&mut stream)?.0.into_iter().map(|item| {
+            ListedItem {
+                name: item.name,
+                original_size: item.size,
+                archive_size: item.size,
+                compressed_segments: vec![false],
+                protected: false,
+                hash: None,
+            }
+        }).collect())
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        //Only the version 3 header has a checksum and it's never checked by the engine either, so
+        // only the layers that can't be decoded are bad
+        let (_, failed) = read_items(&mut stream)?;
+        Ok(failed.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        let item = match read_items(&mut stream)?.0.into_iter().find(|item| item.name == name) {
+            Some(item) => item,
+            None => return Err(FlareError::NotFound {
+                name: String::from(name),
+            }),
+        };
+
+        match item.data {
+            ItemData::Stored => {
+                stream.seek(SeekFrom::Start(item.offset))?;
+                Ok(Box::new(stream.take(item.size)))
+            },
+            ItemData::Built(data) => Ok(Box::new(Cursor::new(data))),
+        }
+    }
+}
+
+struct PSBItem {
+    name: String,
+    //Where the data starts, which is 0 for anything that was built
+    offset: u64,
+    size: u64,
+    data: ItemData,
+    attributes: BTreeMap<String, String>,
+}
+
+enum ItemData {
+    //Copied straight out of the stream from the offset
+    Stored,
+    //Made from the tree or decompressed
+    Built(Vec<u8>),
+}
+
+///Gives the tree, every resource and the image of every layer, along with the layers that
+/// couldn't be decoded
+///A compressed file is decompressed into memory first
+fn read_items<S: Stream>(stream: &mut S) -> Result<(Vec<PSBItem>, FailedItems), FlareError> {
+    stream.little_endian(true);
+    stream.seek(SeekFrom::Start(0))?;
+    if *read_bytes(stream, MDF_HEADER.len() as u64)? != *MDF_HEADER {
+        return read_psb_items(stream);
+    }
+
+    let size = read_value::<u32, _>(stream)? as u64;
+    let offset = stream.pos();
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(FlareError::Malformed {
+            reason: format!("the compressed PSB can't be {} bytes", size),
+            offset,
+        });
+    }
+    let compressed_size = stream.len() - offset;
+    let compressed = read_bytes(stream, compressed_size)?;
+    let mut data = Vec::new();
+    ZlibDecoder::new(&compressed[..]).take(size).read_to_end(&mut data)
+        .map_err(|source| FlareError::Decompression {
+            offset,
+            source,
+        })?;
+
+    //Nothing can be copied out of the file itself
+    let (items, failed) = read_psb_items(&mut SliceStream::new(&data[..], true))?;
+    let items = items.into_iter().map(|item| {
+        let data = match item.data {
+            ItemData::Stored => {
+                let start = item.offset as usize;
+                ItemData::Built(data[start..start + item.size as usize].to_vec())
+            },
+            built => built,
+        };
+        PSBItem {
+            offset: 0,
+            data,
+            ..item
+        }
+    }).collect();
+    Ok((items, failed))
+}
+
+fn read_psb_items<S: Stream>(stream: &mut S) -> Result<(Vec<PSBItem>, FailedItems), FlareError> {
+    let header = Header::read(stream)?;
+    let mut reader = TreeReader::new(stream, &header)?;
+    let root = reader.read_tree(header.entries, 0)?;
+    let resources = read_resources(stream, header.resources)?;
+    let extras = match header.extras {
+        Some(extras) => read_resources(stream, extras)?,
+        None => Vec::new(),
+    };
+
+    let tree = serde_json::to_vec_pretty(&root).expect("A tree of values can always be written");
+    let mut attributes = BTreeMap::new();
+    attributes.insert(String::from("version"), header.version.to_string());
+    if let Some(id) = root.get("id").and_then(Value::as_str) {
+        attributes.insert(String::from("id"), String::from(id));
+    }
+    let mut items = vec![PSBItem {
+        name: String::from(TREE_NAME),
+        offset: 0,
+        size: tree.len() as u64,
+        data: ItemData::Built(tree),
+        attributes,
+    }];
+
+    for (folder, resources) in &[("resources", &resources), ("extra", &extras)] {
+        for (index, &(offset, size)) in resources.iter().enumerate() {
+            stream.seek(SeekFrom::Start(offset))?;
+            let extension = resource_extension(&read_bytes(stream, size.min(16))?);
+            let mut attributes = BTreeMap::new();
+            attributes.insert(String::from("index"), index.to_string());
+            items.push(PSBItem {
+                name: format!("{}/{}.{}", folder, index, extension),
+                offset,
+                size,
+                data: ItemData::Stored,
+                attributes,
+            });
+        }
+    }
+
+    let mut failed = Vec::new();
+    items.extend(read_layers(stream, &root, &resources, &mut failed)?);
+    Ok((items, failed))
+}
+
+///Guesses the extension of a resource from its first bytes
+fn resource_extension(start: &[u8]) -> &'static str {
+    if start.starts_with(tlg::TLG0_HEADER) || start.starts_with(tlg::TLG5_HEADER) ||
+        start.starts_with(tlg::TLG6_HEADER) {
+        "tlg"
+    } else if start.starts_with(png::SIGNATURE) {
+        "png"
+    } else if start.starts_with(PSB_HEADER) || start.starts_with(MDF_HEADER) {
+        "psb"
+    } else {
+        "bin"
+    }
+}
+
+///Decodes the image of every layer of a .pimg into a PNG
+///Anything that isn't a .pimg doesn't have any layers, and a layer that can't be decoded is put
+/// in failed instead
+fn read_layers<S: Stream>(stream: &mut S, root: &Value, resources: &[(u64, u64)],
+failed: &mut FailedItems) -> Result<Vec<PSBItem>, FlareError> {
+    let (root, layers) = match (root.as_object(), root.get("layers").and_then(Value::as_array)) {
+        (Some(root), Some(layers)) => (root, layers),
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut items = Vec::new();
+    for layer in layers.iter().filter_map(Value::as_object) {
+        let id = match layer.get("layer_id").and_then(Value::as_i64) {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        //The image is under the layer id with the image's extension
+        let resource = root.iter().find(|&(key, _)| key.split('.').next() == Some(&id))
+            .and_then(|(_, value)| value.as_str())
+            .and_then(|value| value.strip_prefix(RESOURCE_PREFIX))
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| resources.get(index));
+        let (offset, size) = match resource {
+            Some(&resource) => resource,
+            None => continue,
+        };
+
+        let name = match layer.get("name").and_then(Value::as_str) {
+            Some(name) if !name.is_empty() => format!("layers/{}_{}.png", id, name),
+            _ => format!("layers/{}.png", id),
+        };
+        stream.seek(SeekFrom::Start(offset))?;
+        let data = read_bytes(stream, size)?;
+        let image = match resource_extension(&data) {
+            "tlg" => match tlg::decode_png(&data) {
+                Ok(image) => image,
+                Err(err) => {
+                    failed.push((name, err.to_string()));
+                    continue;
+                },
+            },
+            "png" => data.into_owned(),
+            _ => continue,
+        };
+
+        let mut attributes = BTreeMap::new();
+        for (key, value) in layer {
+            match *value {
+                Value::String(ref value) => attributes.insert(key.clone(), value.clone()),
+                Value::Number(_) | Value::Bool(_) => attributes.insert(key.clone(), value.to_string()),
+                _ => None,
+            };
+        }
+        items.push(PSBItem {
+            name,
+            offset,
+            size: image.len() as u64,
+            data: ItemData::Built(image),
+            attributes,
+        });
+    }
+
+    Ok(items)
+}
+
+///Reads the offsets and lengths of the resources, which are given as the offsets of those two
+/// arrays then the offset that the resource offsets are from
+fn read_resources<S: Stream>(stream: &mut S, (offsets, lengths, data): (u64, u64, u64))
+-> Result<Vec<(u64, u64)>, FlareError> {
+    stream.seek(SeekFrom::Start(offsets))?;
+    let offsets = read_array(stream)?;
+    stream.seek(SeekFrom::Start(lengths))?;
+    let lengths = read_array(stream)?;
+    if offsets.len() != lengths.len() {
+        return Err(FlareError::Malformed {
+            reason: format!("there are {} resource offsets but {} lengths", offsets.len(),
+                lengths.len()),
+            offset: data,
+        });
+    }
+
+    let len = stream.len();
+    offsets.into_iter().zip(lengths).map(|(offset, size)| {
+        let offset = offset_from(data, offset, data)?;
+        if offset.saturating_add(size) > len {
+            return Err(FlareError::Truncated {
+                offset,
+            });
+        }
+        Ok((offset, size))
+    }).collect()
+}
+
+struct Header {
+    version: u16,
+    names: u64,
+    strings: u64,
+    strings_data: u64,
+    //The offsets of the offsets, lengths and data of the resources
+    resources: (u64, u64, u64),
+    extras: Option<(u64, u64, u64)>,
+    entries: u64,
+}
+
+impl Header {
+    fn read<S: Stream>(stream: &mut S) -> Result<Header, FlareError> {
+        stream.little_endian(true);
+        stream.seek(SeekFrom::Start(0))?;
+        if *read_bytes(stream, PSB_HEADER.len() as u64)? != *PSB_HEADER {
+            return Err(FlareError::Malformed {
+                reason: String::from("there isn't a PSB header"),
+                offset: 0,
+            });
+        }
+
+        let version_offset = stream.pos();
+        let version = read_value::<u16, _>(stream)?;
+        if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+            return Err(FlareError::BadFlag {
+                what: "PSB version",
+                flag: version as u64,
+                offset: version_offset,
+            });
+        }
+        let encrypted = read_value::<u16, _>(stream)?;
+        if encrypted != 0 {
+            return Err(FlareError::BadFlag {
+                what: "PSB header encryption",
+                flag: encrypted as u64,
+                offset: version_offset + 2,
+            });
+        }
+
+        //Where the header ends isn't needed
+        stream.skip(4)?;
+        let mut offsets = [0; 7];
+        for offset in &mut offsets {
+            *offset = read_value::<u32, _>(stream)? as u64;
+        }
+        let extras = if version >= 4 {
+            //Skip the checksum
+            stream.skip(4)?;
+            let offsets = read_value::<u32, _>(stream)? as u64;
+            let lengths = read_value::<u32, _>(stream)? as u64;
+            let data = read_value::<u32, _>(stream)? as u64;
+            Some((offsets, lengths, data))
+        } else {
+            None
+        };
+
+        let len = stream.len();
+        if let Some(&offset) = offsets.iter().find(|&&offset| offset > len) {
+            return Err(FlareError::Truncated {
+                offset,
+            });
+        }
+        let [names, strings, strings_data, resource_offsets, resource_lengths, resource_data,
+            entries] = offsets;
+
+        Ok(Header {
+            version,
+            names,
+            strings,
+            strings_data,
+            resources: (resource_offsets, resource_lengths, resource_data),
+            extras,
+            entries,
+        })
+    }
+}
+
+///Reads an unsigned int that's size bytes long
+fn read_uint<S: Stream>(stream: &mut S, size: u8) -> Result<u64, FlareError> {
+    let bytes = read_bytes(stream, size as u64)?;
+    Ok(bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64))
+}
+
+///Reads an array of unsigned ints, which is how every list of offsets or indexes is kept
+fn read_array<S: Stream>(stream: &mut S) -> Result<Vec<u64>, FlareError> {
+    let offset = stream.pos();
+    let kind = read_value::<u8, _>(stream)?;
+    if !(TYPE_ARRAY_1..=TYPE_ARRAY_8).contains(&kind) {
+        return Err(FlareError::BadFlag {
+            what: "PSB array type",
+            flag: kind as u64,
+            offset,
+        });
+    }
+    let count = read_uint(stream, kind - TYPE_ARRAY_1 + 1)?;
+
+    let size_offset = stream.pos();
+    let size = read_value::<u8, _>(stream)?.wrapping_sub(TYPE_ARRAY_1 - 1);
+    if !(1..=8).contains(&size) {
+        return Err(FlareError::BadFlag {
+            what: "PSB array entry size",
+            flag: size as u64,
+            offset: size_offset,
+        });
+    }
+    let data = read_bytes(stream, count.saturating_mul(size as u64))?;
+    Ok(data.chunks(size as usize).map(|entry| {
+        entry.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64)
+    }).collect())
+}
+
+///Reads the values of the tree with the names and strings that they use
+struct TreeReader<'a, S: 'a + Stream> {
+    stream: &'a mut S,
+    names: Vec<String>,
+    strings: Vec<u64>,
+    strings_data: u64,
+    value_count: usize,
+    string_bytes: usize,
+}
+
+impl <'a, S: Stream> TreeReader<'a, S> {
+    fn new(stream: &'a mut S, header: &Header) -> Result<TreeReader<'a, S>, FlareError> {
+        stream.seek(SeekFrom::Start(header.names))?;
+        let names = read_names(stream)?;
+        stream.seek(SeekFrom::Start(header.strings))?;
+        let strings = read_array(stream)?;
+
+        Ok(TreeReader {
+            stream,
+            names,
+            strings,
+            strings_data: header.strings_data,
+            value_count: 0,
+            string_bytes: 0,
+        })
+    }
+
+    fn read_tree(&mut self, offset: u64, depth: usize) -> Result<Value, FlareError> {
+        self.value_count += 1;
+        if depth > MAX_DEPTH || self.value_count > MAX_VALUES {
+            return Err(FlareError::Malformed {
+                reason: String::from("the tree is too big, or its offsets loop"),
+                offset,
+            });
+        }
+
+        self.stream.seek(SeekFrom::Start(offset))?;
+        let kind = read_value::<u8, _>(self.stream)?;
+        Ok(match kind {
+            TYPE_NONE | TYPE_NULL => Value::Null,
+            TYPE_FALSE => Value::Bool(false),
+            TYPE_TRUE => Value::Bool(true),
+            TYPE_NUMBER_0..=TYPE_NUMBER_8 => {
+                //Negative numbers only use as many bytes as they need, so the sign is spread up
+                let size = kind - TYPE_NUMBER_0;
+                let shift = 64 - size as u32 * 8;
+                let value = read_uint(self.stream, size)?;
+                let value = if size == 0 { 0 } else { ((value << shift) as i64) >> shift };
+                Value::from(value)
+            },
+            TYPE_ARRAY_1..=TYPE_ARRAY_8 => {
+                self.stream.seek(SeekFrom::Start(offset))?;
+                Value::from(read_array(self.stream)?)
+            },
+            TYPE_STRING_1..=TYPE_STRING_4 => {
+                let index = read_uint(self.stream, kind - TYPE_STRING_1 + 1)?;
+                Value::String(self.read_string(index, offset)?)
+            },
+            TYPE_RESOURCE_1..=TYPE_RESOURCE_4 => {
+                let index = read_uint(self.stream, kind - TYPE_RESOURCE_1 + 1)?;
+                Value::String(format!("{}{}", RESOURCE_PREFIX, index))
+            },
+            TYPE_EXTRA_1..=TYPE_EXTRA_4 => {
+                let index = read_uint(self.stream, kind - TYPE_EXTRA_1 + 1)?;
+                Value::String(format!("{}{}", EXTRA_PREFIX, index))
+            },
+            TYPE_FLOAT_0 => float(0.0),
+            TYPE_FLOAT => float(f32::from_bits(read_value::<u32, _>(self.stream)?) as f64),
+            TYPE_DOUBLE => float(f64::from_bits(read_value::<u64, _>(self.stream)?)),
+            TYPE_LIST => {
+                let offsets = read_array(self.stream)?;
+                let start = self.stream.pos();
+                let mut values = Vec::with_capacity(offsets.len());
+                for value_offset in offsets {
+                    let value_offset = offset_from(start, value_offset, offset)?;
+                    values.push(self.read_tree(value_offset, depth + 1)?);
+                }
+                Value::Array(values)
+            },
+            TYPE_OBJECTS => {
+                let names = read_array(self.stream)?;
+                let offsets = read_array(self.stream)?;
+                let start = self.stream.pos();
+                let mut values = Map::new();
+                for (name, value_offset) in names.into_iter().zip(offsets) {
+                    let name = match self.names.get(name as usize) {
+                        Some(name) => name.clone(),
+                        None => return Err(FlareError::Malformed {
+                            reason: format!("there isn't a name {}", name),
+                            offset,
+                        }),
+                    };
+                    let value_offset = offset_from(start, value_offset, offset)?;
+                    let value = self.read_tree(value_offset, depth + 1)?;
+                    values.insert(name, value);
+                }
+                Value::Object(values)
+            },
+            _ => return Err(FlareError::BadFlag {
+                what: "PSB value type",
+                flag: kind as u64,
+                offset,
+            }),
+        })
+    }
+
+    ///Reads the null terminated string with the index
+    fn read_string(&mut self, index: u64, offset: u64) -> Result<String, FlareError> {
+        let string_offset = match self.strings.get(index as usize) {
+            Some(&string_offset) => offset_from(self.strings_data, string_offset, offset)?,
+            None => return Err(FlareError::Malformed {
+                reason: format!("there isn't a string {}", index),
+                offset,
+            }),
+        };
+
+        self.stream.seek(SeekFrom::Start(string_offset))?;
+        let mut string = Vec::new();
+        loop {
+            match read_value::<u8, _>(self.stream)? {
+                0 => break,
+                byte => string.push(byte),
+            }
+            self.string_bytes += 1;
+            if self.string_bytes > MAX_STRING_BYTES {
+                return Err(FlareError::Malformed {
+                    reason: String::from("the strings of the tree are too big"),
+                    offset,
+                });
+            }
+        }
+        Ok(String::from_utf8_lossy(&string).into_owned())
+    }
+}
+
+///Adds an offset onto the one that it's from, which is malformed if it can't be anywhere in a file
+///at is where the offset was read, for the error
+fn offset_from(start: u64, offset: u64, at: u64) -> Result<u64, FlareError> {
+    start.checked_add(offset).ok_or_else(|| FlareError::Malformed {
+        reason: format!("the offset {} is past the end of any file", offset),
+        offset: at,
+    })
+}
+
+///JSON doesn't have NaN or infinity, so those are left out
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+///Reads every name out of the trie
+fn read_names<S: Stream>(stream: &mut S) -> Result<Vec<String>, FlareError> {
+    let offset = stream.pos();
+    let charset = read_array(stream)?;
+    let nodes = read_array(stream)?;
+    let ends = read_array(stream)?;
+    let malformed = || FlareError::Malformed {
+        reason: String::from("the name trie points outside of itself"),
+        offset,
+    };
+
+    ends.iter().map(|&end| {
+        //Each node is the one that comes after its parent's charset entry by its byte
+        let mut name = Vec::new();
+        let mut node = *nodes.get(end as usize).ok_or_else(malformed)?;
+        while node != 0 {
+            if name.len() > nodes.len() {
+                return Err(malformed());
+            }
+            let parent = *nodes.get(node as usize).ok_or_else(malformed)?;
+            let base = *charset.get(parent as usize).ok_or_else(malformed)?;
+            name.push(node.wrapping_sub(base) as u8);
+            node = parent;
+        }
+        name.reverse();
+        Ok(String::from_utf8_lossy(&name).into_owned())
+    }).collect()
+}
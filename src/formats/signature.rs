@@ -0,0 +1,203 @@
+use std::cmp;
+use std::io::{Result as IOResult};
+use std::path::{Path};
+
+use file_utils;
+use stream::{FileStream, OffsetStream, Stream};
+use super::{Converter, Format};
+use super::pe::{PEExecutable};
+use super::psb::{self, PSBContainer};
+use super::simple_crypt::{self, SimpleCryptText};
+use super::tjs::{self, TJS2Bytecode};
+use super::tlg::{self, TLGImage};
+use super::xp3::{self, XP3Archive};
+
+//How much of the start of a file is read to look for magic bytes
+const HEAD_SIZE: u64 = 4096;
+
+//How much more confident a matching extension makes a detection
+const EXTENSION_BONUS: u8 = 10;
+
+///Formats below this confidence aren't guessed, but identify() still gives them
+pub const MIN_CONFIDENCE: u8 = 50;
+
+///Bytes that a format always has at the same offset
+#[derive(Debug)]
+pub struct Magic {
+    pub offset: u64,
+    pub bytes: &'static [u8],
+    ///Whether finding the bytes partway into a file means the format starts there
+    ///The MZ of an exe doesn't, since anything hidden in it is somewhere after the bytes
+    pub carve: bool,
+}
+
+///Everything needed to find out if a file is one of the formats
+///
+///The magic bytes are checked against the head of the file first, since that's cheap. Only the
+///formats that have matching magic get validated, which gives how confident the format is in
+///the file.
+pub struct Signature {
+    pub format: Format,
+    ///The file needs to have any one of these
+    pub magic: &'static [Magic],
+    ///Extensions that the format usually has, without the dot
+    pub extensions: &'static [&'static str],
+    ///Gives a confidence from 0 to 100 for the whole stream, where 0 means it isn't the format
+    pub validate: fn(&mut OffsetStream<&mut FileStream>) -> u8,
+}
+
+///Every format that can be detected
+pub static SIGNATURES: &[Signature] = &[
+    Signature {
+        format: Format::XP3Archive,
+        magic: &[
+            Magic {
+                offset: 0,
+                bytes: xp3::HEADER,
+                carve: true,
+            },
+            //The archive can be hidden after an exe
+            Magic {
+                offset: 0,
+                bytes: b"MZ",
+                carve: false,
+            },
+        ],
+        extensions: &["xp3", "exe"],
+        validate: confidence::<XP3Archive>,
+    },
+    Signature {
+        format: Format::PEExecutable,
+        magic: &[
+            Magic {
+                offset: 0,
+                bytes: b"MZ",
+                carve: true,
+            },
+        ],
+        extensions: &["exe", "dll"],
+        validate: confidence::<PEExecutable>,
+    },
+    Signature {
+        format: Format::TLGImage,
+        magic: &[
+            Magic {
+                offset: 0,
+                bytes: tlg::TLG0_HEADER,
+                carve: true,
+            },
+            Magic {
+                offset: 0,
+                bytes: tlg::TLG5_HEADER,
+                carve: true,
+            },
+            Magic {
+                offset: 0,
+                bytes: tlg::TLG6_HEADER,
+                carve: true,
+            },
+        ],
+        extensions: &["tlg"],
+        validate: confidence::<TLGImage>,
+    },
+    Signature {
+        format: Format::PSBContainer,
+        magic: &[
+            Magic {
+                offset: 0,
+                bytes: psb::PSB_HEADER,
+                carve: true,
+            },
+            //Most PSBs are compressed
+            Magic {
+                offset: 0,
+                bytes: psb::MDF_HEADER,
+                carve: true,
+            },
+        ],
+        extensions: &["psb", "pimg", "scn", "mtn"],
+        validate: confidence::<PSBContainer>,
+    },
+    Signature {
+        format: Format::TJS2Bytecode,
+        magic: &[
+            Magic {
+                offset: 0,
+                bytes: tjs::HEADER,
+                carve: true,
+            },
+        ],
+        extensions: &["tjs"],
+        validate: confidence::<TJS2Bytecode>,
+    },
+    Signature {
+        format: Format::SimpleCryptText,
+        magic: &[
+            //Two bytes turn up everywhere, so they aren't carved
+            Magic {
+                offset: 0,
+                bytes: simple_crypt::HEADER,
+                carve: false,
+            },
+        ],
+        extensions: &["ks", "tjs", "txt", "csv"],
+        validate: confidence::<SimpleCryptText>,
+    },
+];
+
+///Lets a converter's confidence() be used for any file stream, wherever the format starts
+fn confidence<C: Converter>(stream: &mut OffsetStream<&mut FileStream>) -> u8 {
+    C::confidence(stream)
+}
+
+///A format that a file could be
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub format: Format,
+    ///From 1 to 100
+    pub confidence: u8,
+    ///Where the magic bytes that matched are
+    pub magic_offset: u64,
+    ///Whether the file has one of the format's usual extensions
+    pub extension_match: bool,
+}
+
+///Finds every format that the file could be, with the most confident one first
+///The start of the file is only read once for all of the formats
+pub fn identify(file: &Path) -> IOResult<Vec<Candidate>> {
+    let mut stream = FileStream::open(file, true)?;
+    let head_size = cmp::min(stream.len(), HEAD_SIZE) as usize;
+    let head = stream.read_exact(head_size)?;
+    let extension = file_utils::extension(file).to_lowercase();
+
+    let mut candidates: Vec<Candidate> = SIGNATURES.iter().filter_map(|signature| {
+        let magic = signature.magic.iter().find(|magic| {
+            let start = magic.offset as usize;
+            head.get(start..start + magic.bytes.len()) == Some(magic.bytes)
+        })?;
+
+        let confidence = match OffsetStream::new(&mut stream, 0) {
+            Ok(mut stream) => (signature.validate)(&mut stream),
+            Err(_) => 0,
+        };
+        if confidence == 0 {
+            return None;
+        }
+
+        let extension_match = signature.extensions.contains(&extension.as_str());
+        Some(Candidate {
+            format: signature.format,
+            confidence: if extension_match {
+                cmp::min(confidence.saturating_add(EXTENSION_BONUS), 100)
+            } else {
+                confidence
+            },
+            magic_offset: magic.offset,
+            extension_match,
+        })
+    }).collect();
+
+    //The order of the registry breaks ties so it's always the same
+    candidates.sort_by_key(|candidate| cmp::Reverse(candidate.confidence));
+    Ok(candidates)
+}
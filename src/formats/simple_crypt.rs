@@ -0,0 +1,275 @@
+//!KiriKiri's simple text encryption, which scripts are often saved with
+//!
+//!An encrypted text file starts with FE FE, the mode and the FF FE byte order mark, then has the
+//!UTF-16 text. Modes 0 and 1 scramble every character on its own (so they're undone by doing them
+//!again) and mode 2 compresses the text with zlib.
+use std::collections::{BTreeMap};
+use std::io::{Cursor, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+use flate2::{Compression};
+use flate2::read::{ZlibDecoder};
+use flate2::write::{ZlibEncoder};
+
+use super::{
+    output_path,
+    read_bytes,
+    Converter,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    ListedItem,
+    TextEncoding,
+};
+use file_utils;
+use sink::{Sink};
+use stream::{Stream};
+
+///The bytes that every encrypted text starts with, before the mode
+pub const HEADER: &[u8] = &[0xfe, 0xfe];
+
+//The UTF-16 little endian byte order mark, which comes after the mode
+const UTF16_BOM: &[u8] = &[0xff, 0xfe];
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+
+//The header, mode and byte order mark
+const PREFIX_SIZE: usize = 5;
+
+//The biggest text that compressed text will be decompressed into
+const MAX_TEXT_SIZE: u64 = 1 << 30;
+
+//The name of the only item that the text is flared into
+const TEXT_NAME: &str = "text.txt";
+
+///How the text was encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    ///Mode 0, which XORs every character above the control characters with its own low byte
+    Xor,
+    ///Mode 1, which swaps every odd bit of each character with the even bit next to it
+    SwapBits,
+    ///Mode 2, which compresses the text with zlib after its sizes
+    Compressed,
+}
+
+impl Mode {
+    ///Gives the mode with the byte that's stored after the header
+    pub fn from_byte(byte: u8) -> Option<Mode> {
+        match byte {
+            0 => Some(Mode::Xor),
+            1 => Some(Mode::SwapBits),
+            2 => Some(Mode::Compressed),
+            _ => None,
+        }
+    }
+
+    ///The byte that's stored after the header
+    pub fn byte(self) -> u8 {
+        match self {
+            Mode::Xor => 0,
+            Mode::SwapBits => 1,
+            Mode::Compressed => 2,
+        }
+    }
+}
+
+///Scrambles or unscrambles a single character, since both are the same for modes 0 and 1
+fn scramble(mode: Mode, character: u16) -> u16 {
+    match mode {
+        Mode::Xor if character >= 0x20 => character ^ (((character & 0xfe) << 8) ^ 1),
+        Mode::SwapBits => ((character & 0xaaaa) >> 1) | ((character & 0x5555) << 1),
+        _ => character,
+    }
+}
+
+///Decrypts the whole of an encrypted text, giving the mode it used and the text
+pub fn decode(data: &[u8]) -> Result<(Mode, String), FlareError> {
+    if data.len() < PREFIX_SIZE || &data[..2] != HEADER || &data[3..PREFIX_SIZE] != UTF16_BOM {
+        return Err(FlareError::Malformed {
+            reason: String::from("there isn't a simple crypt header"),
+            offset: 0,
+        });
+    }
+    let mode = Mode::from_byte(data[2]).ok_or(FlareError::BadFlag {
+        what: "simple crypt mode",
+        flag: data[2] as u64,
+        offset: 2,
+    })?;
+
+    let body = &data[PREFIX_SIZE..];
+    let utf16: Vec<u16> = if mode == Mode::Compressed {
+        if body.len() < 16 {
+            return Err(FlareError::Truncated {
+                offset: data.len() as u64,
+            });
+        }
+        let mut sizes = [0; 8];
+        sizes.copy_from_slice(&body[8..16]);
+        let size = u64::from_le_bytes(sizes);
+        if size > MAX_TEXT_SIZE {
+            return Err(FlareError::Malformed {
+                reason: format!("the text can't be {} bytes", size),
+                offset: PREFIX_SIZE as u64 + 8,
+            });
+        }
+
+        //The compressed size isn't needed since the zlib data goes to the end
+        let mut text = Vec::new();
+        ZlibDecoder::new(&body[16..]).take(size).read_to_end(&mut text)
+            .map_err(|source| FlareError::Decompression {
+                offset: PREFIX_SIZE as u64 + 16,
+                source,
+            })?;
+        text.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect()
+    } else {
+        body.chunks_exact(2).map(|pair| scramble(mode, u16::from_le_bytes([pair[0], pair[1]])))
+            .collect()
+    };
+
+    Ok((mode, String::from_utf16_lossy(&utf16)))
+}
+
+///Encrypts the text with the mode, so KiriKiri can read it the same as the original script
+pub fn encode(text: &str, mode: Mode) -> Vec<u8> {
+    let mut data = HEADER.to_vec();
+    data.push(mode.byte());
+    data.extend_from_slice(UTF16_BOM);
+
+    if mode == Mode::Compressed {
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&utf16).expect("Writing to memory can't fail");
+        let compressed = encoder.finish().expect("Writing to memory can't fail");
+        data.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+        data.extend_from_slice(&(utf16.len() as u64).to_le_bytes());
+        data.extend(compressed);
+    } else {
+        data.extend(text.encode_utf16().flat_map(|unit| scramble(mode, unit).to_le_bytes()));
+    }
+    data
+}
+
+///Reads plain text that's either UTF-16 with a byte order mark or UTF-8
+///This is how text is read back in before it's encrypted again
+pub fn plain_text(data: &[u8]) -> String {
+    if let Some(utf16) = data.strip_prefix(UTF16_BOM) {
+        let utf16: Vec<u16> = utf16.chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&utf16)
+    } else {
+        String::from_utf8_lossy(data.strip_prefix(UTF8_BOM).unwrap_or(data)).into_owned()
+    }
+}
+
+pub struct SimpleCryptText {
+    options: FlareOptions,
+}
+
+impl Converter for SimpleCryptText {
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        if stream.seek(SeekFrom::Start(0)).is_err() {
+            return 0;
+        }
+        //Two bytes are too easy to find by chance, so the mode and byte order mark are needed too
+        match read_bytes(stream, PREFIX_SIZE as u64) {
+            Ok(prefix) if &prefix[..2] == HEADER && Mode::from_byte(prefix[2]).is_some() &&
+                &prefix[3..] == UTF16_BOM => 100,
+            _ => 0,
+        }
+    }
+
+    fn new(options: FlareOptions) -> SimpleCryptText {
+        SimpleCryptText {
+            options,
+        }
+    }
+
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let mut report = FlareReport::default();
+        if !self.options.filter.matches(TEXT_NAME) {
+            report.skipped = 1;
+            return Ok(report);
+        }
+        report.matched = 1;
+
+        let (mode, text) = read_text(&mut stream, self.options.text_encoding)?;
+        let (path, _) = file_utils::member_path(Path::new(""), TEXT_NAME);
+        let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+            Some(output) => output,
+            None => return Ok(report),
+        };
+        let location = sink.location(&path);
+        if renamed {
+            report.renamed.push((String::from(TEXT_NAME), location.clone()));
+        }
+
+        sink.save(&path, text.len() as u64, &mut &text[..])?;
+        let mut attributes = BTreeMap::new();
+        attributes.insert(String::from("mode"), mode.byte().to_string());
+        attributes.insert(String::from("encoding"), String::from(match self.options.text_encoding {
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf16 => "utf-16",
+        }));
+        report.entries.push(FlaredEntry {
+            name: String::from(TEXT_NAME),
+            path: location,
+            size: text.len() as u64,
+            offset: 0,
+            attributes,
+        });
+
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        let (mode, text) = read_text(&mut stream, self.options.text_encoding)?;
+        Ok(vec![ListedItem {
+            name: String::from(TEXT_NAME),
+            original_size: text.len() as u64,
+            archive_size: stream.len(),
+            compressed_segments: vec![mode == Mode::Compressed],
+            protected: true,
+            hash: None,
+        }])
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        //There isn't a checksum, but compressed text still has to decompress
+        read_text(&mut stream, self.options.text_encoding)?;
+        Ok(Vec::new())
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        if name != TEXT_NAME {
+            return Err(FlareError::NotFound {
+                name: String::from(name),
+            });
+        }
+
+        Ok(Box::new(Cursor::new(read_text(&mut stream, self.options.text_encoding)?.1)))
+    }
+}
+
+///Decrypts the whole stream into the bytes of the text in the encoding
+fn read_text<S: Stream>(stream: &mut S, encoding: TextEncoding)
+-> Result<(Mode, Vec<u8>), FlareError> {
+    stream.seek(SeekFrom::Start(0))?;
+    let len = stream.len();
+    let (mode, text) = decode(&read_bytes(stream, len)?)?;
+
+    Ok((mode, match encoding {
+        TextEncoding::Utf8 => text.into_bytes(),
+        TextEncoding::Utf16 => {
+            let mut utf16 = UTF16_BOM.to_vec();
+            utf16.extend(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+            utf16
+        },
+    }))
+}
@@ -0,0 +1,738 @@
+use std::collections::{BTreeMap};
+use std::fmt::{Write as FmtWrite};
+use std::io::{Cursor, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+
+use super::{
+    output_path,
+    read_bytes,
+    read_value,
+    Converter,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    ListedItem,
+};
+use file_utils;
+use sink::{Sink};
+use stream::{Stream};
+
+//Notes taken from kirikiri tjsByteCodeLoader.cpp and tjsInterCodeGen.h
+/*
+Compiled TJS2 starts with "TJS2100\0" and the u32 size of the whole file. Everything is little endian.
+
+The "DATA" chunk (its u32 size counts its own 8 byte header) holds the constant pools, each as a u32
+count followed by its values, padded out to 4 bytes:
+bytes, i16s, i32s, i64s, f64s, strings (u32 length then UTF-16) and octets (u32 length then bytes)
+
+The "OBJS" chunk has the index of the top level object, the number of objects, then every object
+(function, class, property...) as "TJS2", a u32 size and:
+1. 12 i32s: parent, name (a string), context type, variable counts, frame size, argument counts,
+   property setter and getter and super class getter (objects, or -1)
+2. The code positions then source positions of the source map
+3. The code, as i16 words
+4. The constants of the object, as i16 pairs of type and index into the pools (or the objects)
+5. The super class getters and the properties (pairs of name and object)
+
+Every instruction is an opcode word followed by its operands. Registers are relative to the
+frame, constants are indexes into the object's constants and jumps are relative to the opcode.
+Calls have a variable number of arguments after the argument count.
+*/
+
+pub const HEADER: &[u8] = b"TJS2100\0";
+
+const DATA_TAG: &[u8] = b"DATA";
+const OBJECTS_TAG: &[u8] = b"OBJS";
+const OBJECT_TAG: &[u8] = b"TJS2";
+
+//The types of an object's constants
+const CONSTANT_VOID: i16 = 0;
+const CONSTANT_OBJECT: i16 = 1;
+const CONSTANT_INTER_OBJECT: i16 = 2;
+const CONSTANT_STRING: i16 = 3;
+const CONSTANT_OCTET: i16 = 4;
+const CONSTANT_REAL: i16 = 5;
+const CONSTANT_BYTE: i16 = 6;
+const CONSTANT_SHORT: i16 = 7;
+const CONSTANT_INTEGER: i16 = 8;
+const CONSTANT_LONG: i16 = 9;
+const CONSTANT_INTER_GENERATOR: i16 = 10;
+
+const CONTEXT_TOP_LEVEL: i32 = 0;
+
+//The argument counts of a call that mean something else
+const ARGUMENTS_OMITTED: i32 = -1;
+const ARGUMENTS_EXPANDED: i32 = -2;
+
+pub struct TJS2Bytecode {
+    options: FlareOptions,
+}
+
+impl Converter for TJS2Bytecode {
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        stream.little_endian(true);
+        if stream.seek(SeekFrom::Start(0)).is_err() {
+            return 0;
+        }
+        match read_bytes(stream, HEADER.len() as u64) {
+            Ok(header) if *header == *HEADER => {},
+            _ => return 0,
+        }
+
+        //A size that doesn't match means the file was cut short or has more glued on
+        let len = stream.len();
+        match read_value::<u32, _>(stream) {
+            Ok(size) if size as u64 == len => 100,
+            _ => 60,
+        }
+    }
+
+    fn new(options: FlareOptions) -> TJS2Bytecode {
+        TJS2Bytecode {
+            options,
+        }
+    }
+
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let mut report = FlareReport::default();
+        let listings = read_listings(&mut stream)?;
+        let listing_count = listings.len();
+
+        let listings: Vec<Listing> = listings.into_iter()
+            .filter(|listing| self.options.filter.matches(&listing.name))
+            .collect();
+        report.matched = listings.len();
+        report.skipped = listing_count - listings.len();
+
+        for listing in listings {
+            let (path, rewritten) = file_utils::member_path(Path::new(""), &listing.name);
+            let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+                Some(output) => output,
+                None => continue,
+            };
+            let location = sink.location(&path);
+            if rewritten || renamed {
+                report.renamed.push((listing.name.clone(), location.clone()));
+            }
+
+            let text = listing.text.as_bytes();
+            sink.save(&path, text.len() as u64, &mut &text[..])?;
+            report.entries.push(FlaredEntry {
+                name: listing.name,
+                path: location,
+                size: text.len() as u64,
+                offset: listing.offset,
+                attributes: listing.attributes,
+            });
+        }
+
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        Ok(read_listings(&mut stream)?.into_iter().map(|listing| {
+            ListedItem {
+                name: listing.name,
+                original_size: listing.text.len() as u64,
+                archive_size: listing.size,
+                compressed_segments: vec![false],
+                protected: false,
+                hash: None,
+            }
+        }).collect())
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        //There isn't a checksum, but every object still has to be readable
+        read_listings(&mut stream)?;
+        Ok(Vec::new())
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        match read_listings(&mut stream)?.into_iter().find(|listing| listing.name == name) {
+            Some(listing) => Ok(Box::new(Cursor::new(listing.text.into_bytes()))),
+            None => Err(FlareError::NotFound {
+                name: String::from(name),
+            }),
+        }
+    }
+}
+
+///The disassembly of a single object
+struct Listing {
+    name: String,
+    //Where the object is in the file and how big it is there
+    offset: u64,
+    size: u64,
+    text: String,
+    attributes: BTreeMap<String, String>,
+}
+
+///Reads every object and disassembles it
+fn read_listings<S: Stream>(stream: &mut S) -> Result<Vec<Listing>, FlareError> {
+    stream.little_endian(true);
+    stream.seek(SeekFrom::Start(0))?;
+    if *read_bytes(stream, HEADER.len() as u64)? != *HEADER {
+        return Err(FlareError::Malformed {
+            reason: String::from("there isn't a TJS2 bytecode header"),
+            offset: 0,
+        });
+    }
+    //The size of the file doesn't need to be right to read it
+    stream.skip(4)?;
+
+    let data_start = stream.pos();
+    expect_tag(stream, DATA_TAG)?;
+    let data_size = read_value::<u32, _>(stream)? as u64;
+    let pools = Pools::read(stream)?;
+
+    stream.seek(SeekFrom::Start(data_start + data_size))?;
+    expect_tag(stream, OBJECTS_TAG)?;
+    stream.skip(4)?;
+    let top_level = read_value::<u32, _>(stream)? as i32;
+    let object_count = read_value::<u32, _>(stream)?;
+    let mut objects = Vec::new();
+    for index in 0..object_count as usize {
+        objects.push(CodeObject::read(stream, &pools, index)?);
+    }
+
+    Ok(objects.iter().map(|object| {
+        let name = qualified_name(&objects, object.index);
+        let mut attributes = BTreeMap::new();
+        attributes.insert(String::from("context"), String::from(context_name(object.context)));
+        attributes.insert(String::from("parent"), object.parent.to_string());
+        if object.index as i32 == top_level {
+            attributes.insert(String::from("top_level"), String::from("true"));
+        }
+
+        Listing {
+            name: format!("{:04}_{}.txt", object.index, name),
+            offset: object.offset,
+            size: object.size,
+            text: object.disassemble(&objects, &name),
+            attributes,
+        }
+    }).collect())
+}
+
+fn expect_tag<S: Stream>(stream: &mut S, tag: &[u8]) -> Result<(), FlareError> {
+    let offset = stream.pos();
+    if *read_bytes(stream, tag.len() as u64)? != *tag {
+        return Err(FlareError::Malformed {
+            reason: format!("there isn't a {} tag", String::from_utf8_lossy(tag)),
+            offset,
+        });
+    }
+    Ok(())
+}
+
+///Reads count values that are each size bytes, skipping the padding to 4 bytes after them
+fn read_padded<S: Stream>(stream: &mut S, count: u32, size: u64) -> Result<Vec<u8>, FlareError> {
+    let data_size = count as u64 * size;
+    let data = read_bytes(stream, data_size)?.into_owned();
+    stream.skip((data_size.next_multiple_of(4) - data_size) as i64)?;
+    Ok(data)
+}
+
+///Reads a u32 count then that many little endian values that are each N bytes
+fn read_pool<S: Stream, const N: usize>(stream: &mut S) -> Result<Vec<[u8; N]>, FlareError> {
+    let count = read_value::<u32, _>(stream)?;
+    let data = read_padded(stream, count, N as u64)?;
+    Ok(data.chunks(N).map(|value| {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(value);
+        bytes
+    }).collect())
+}
+
+///Every constant of the file, which objects pick from by index
+struct Pools {
+    bytes: Vec<i8>,
+    shorts: Vec<i16>,
+    integers: Vec<i32>,
+    longs: Vec<i64>,
+    reals: Vec<f64>,
+    strings: Vec<String>,
+    octets: Vec<Vec<u8>>,
+}
+
+impl Pools {
+    fn read<S: Stream>(stream: &mut S) -> Result<Pools, FlareError> {
+        let bytes = read_pool::<_, 1>(stream)?.into_iter().map(i8::from_le_bytes).collect();
+        let shorts = read_pool::<_, 2>(stream)?.into_iter().map(i16::from_le_bytes).collect();
+        let integers = read_pool::<_, 4>(stream)?.into_iter().map(i32::from_le_bytes).collect();
+        let longs = read_pool::<_, 8>(stream)?.into_iter().map(i64::from_le_bytes).collect();
+        let reals = read_pool::<_, 8>(stream)?.into_iter().map(f64::from_le_bytes).collect();
+
+        let string_count = read_value::<u32, _>(stream)?;
+        let mut strings = Vec::new();
+        for _ in 0..string_count {
+            let len = read_value::<u32, _>(stream)?;
+            let utf16: Vec<u16> = read_padded(stream, len, 2)?.chunks(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            strings.push(String::from_utf16_lossy(&utf16));
+        }
+
+        let octet_count = read_value::<u32, _>(stream)?;
+        let mut octets = Vec::new();
+        for _ in 0..octet_count {
+            let len = read_value::<u32, _>(stream)?;
+            octets.push(read_padded(stream, len, 1)?);
+        }
+
+        Ok(Pools {
+            bytes,
+            shorts,
+            integers,
+            longs,
+            reals,
+            strings,
+            octets,
+        })
+    }
+}
+
+///A constant of an object, with its value taken out of the pools
+enum Constant {
+    Void,
+    Null,
+    //The index of another object, like a function or class
+    Object(i32),
+    String(String),
+    Octet(Vec<u8>),
+    Real(f64),
+    Integer(i64),
+}
+
+impl Constant {
+    fn describe(&self, objects: &[CodeObject]) -> String {
+        match *self {
+            Constant::Void => String::from("void"),
+            Constant::Null => String::from("null"),
+            Constant::Object(index) => format!("object {} ({})", index,
+                qualified_name(objects, index as usize)),
+            Constant::String(ref string) => format!("{:?}", string),
+            Constant::Octet(ref octet) => {
+                let hex: Vec<String> = octet.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("<% {} %>", hex.join(" "))
+            },
+            Constant::Real(real) => format!("{:?}", real),
+            Constant::Integer(integer) => integer.to_string(),
+        }
+    }
+}
+
+struct CodeObject {
+    index: usize,
+    offset: u64,
+    size: u64,
+    parent: i32,
+    name: Option<String>,
+    context: i32,
+    max_variables: i32,
+    max_frame: i32,
+    arguments: i32,
+    property_setter: i32,
+    property_getter: i32,
+    super_class_getter: i32,
+    code: Vec<i32>,
+    constants: Vec<Constant>,
+    properties: Vec<(String, i32)>,
+}
+
+impl CodeObject {
+    fn read<S: Stream>(stream: &mut S, pools: &Pools, index: usize)
+    -> Result<CodeObject, FlareError> {
+        let offset = stream.pos();
+        expect_tag(stream, OBJECT_TAG)?;
+        //Everything in the object is read in order, so its size isn't needed
+        stream.skip(4)?;
+
+        let mut header = [0; 12];
+        for field in &mut header {
+            *field = read_value::<u32, _>(stream)? as i32;
+        }
+        let [parent, name, context, max_variables, _variable_reserve, max_frame, arguments,
+            _unnamed_argument_base, _collapse_base, property_setter, property_getter,
+            super_class_getter] = header;
+
+        //The source map isn't needed without the source
+        let source_count = read_value::<u32, _>(stream)?;
+        read_padded(stream, source_count, 8)?;
+
+        let code_count = read_value::<u32, _>(stream)?;
+        let code = read_padded(stream, code_count, 2)?.chunks(2)
+            .map(|word| i16::from_le_bytes([word[0], word[1]]) as i32)
+            .collect();
+
+        let constants_offset = stream.pos();
+        let constant_count = read_value::<u32, _>(stream)?;
+        let constants = read_padded(stream, constant_count, 4)?.chunks(4).map(|pair| {
+            let kind = i16::from_le_bytes([pair[0], pair[1]]);
+            let index = i16::from_le_bytes([pair[2], pair[3]]);
+            let missing = || FlareError::Malformed {
+                reason: format!("there isn't a constant {} of type {}", index, kind),
+                offset: constants_offset,
+            };
+            let position = index as usize;
+            Ok(match kind {
+                CONSTANT_VOID => Constant::Void,
+                CONSTANT_OBJECT => Constant::Null,
+                CONSTANT_INTER_OBJECT | CONSTANT_INTER_GENERATOR => Constant::Object(index as i32),
+                CONSTANT_STRING => Constant::String(pools.strings.get(position).ok_or_else(missing)?
+                    .clone()),
+                CONSTANT_OCTET => Constant::Octet(pools.octets.get(position).ok_or_else(missing)?
+                    .clone()),
+                CONSTANT_REAL => Constant::Real(*pools.reals.get(position).ok_or_else(missing)?),
+                CONSTANT_BYTE => Constant::Integer(*pools.bytes.get(position).ok_or_else(missing)?
+                    as i64),
+                CONSTANT_SHORT => Constant::Integer(*pools.shorts.get(position)
+                    .ok_or_else(missing)? as i64),
+                CONSTANT_INTEGER => Constant::Integer(*pools.integers.get(position)
+                    .ok_or_else(missing)? as i64),
+                CONSTANT_LONG => Constant::Integer(*pools.longs.get(position)
+                    .ok_or_else(missing)?),
+                _ => return Err(FlareError::BadFlag {
+                    what: "TJS2 constant type",
+                    flag: kind as u16 as u64,
+                    offset: constants_offset,
+                }),
+            })
+        }).collect::<Result<Vec<Constant>, FlareError>>()?;
+
+        let getter_count = read_value::<u32, _>(stream)?;
+        read_padded(stream, getter_count, 4)?;
+
+        let property_count = read_value::<u32, _>(stream)?;
+        let properties = read_padded(stream, property_count, 8)?.chunks(8).map(|pair| {
+            let name = i32::from_le_bytes([pair[0], pair[1], pair[2], pair[3]]);
+            let object = i32::from_le_bytes([pair[4], pair[5], pair[6], pair[7]]);
+            (string_name(pools, name).unwrap_or_default(), object)
+        }).collect();
+
+        Ok(CodeObject {
+            index,
+            offset,
+            size: stream.pos() - offset,
+            parent,
+            name: string_name(pools, name),
+            context,
+            max_variables,
+            max_frame,
+            arguments,
+            property_setter,
+            property_getter,
+            super_class_getter,
+            code,
+            constants,
+            properties,
+        })
+    }
+
+    ///Writes a header describing the object, its constants, then every instruction
+    fn disassemble(&self, objects: &[CodeObject], name: &str) -> String {
+        let mut text = String::new();
+        let linked = |index: i32| if index < 0 {
+            String::from("none")
+        } else {
+            format!("{} ({})", index, qualified_name(objects, index as usize))
+        };
+        //Writing into a String can't fail
+        let _ = writeln!(text, "// object {}: {}", self.index, name);
+        let _ = writeln!(text, "// context: {}", context_name(self.context));
+        let _ = writeln!(text, "// parent: {}", linked(self.parent));
+        let _ = writeln!(text, "// arguments: {}, variables: {}, frame: {}", self.arguments,
+            self.max_variables, self.max_frame);
+        for &(label, index) in &[("property setter", self.property_setter),
+            ("property getter", self.property_getter),
+            ("super class getter", self.super_class_getter)] {
+            if index >= 0 {
+                let _ = writeln!(text, "// {}: {}", label, linked(index));
+            }
+        }
+        for &(ref property, index) in &self.properties {
+            let _ = writeln!(text, "// property {}: {}", property, linked(index));
+        }
+
+        if !self.constants.is_empty() {
+            let _ = writeln!(text, "\n// constants");
+            for (index, constant) in self.constants.iter().enumerate() {
+                let _ = writeln!(text, "*{} = {}", index, constant.describe(objects));
+            }
+        }
+
+        let _ = writeln!(text, "\n// code");
+        let mut address = 0;
+        while address < self.code.len() {
+            let (line, size) = self.instruction(address, objects);
+            let _ = writeln!(text, "{:08} {}", address, line);
+            address += size;
+        }
+        text
+    }
+
+    ///Disassembles the instruction at address, giving its text and how many words it uses
+    fn instruction(&self, address: usize, objects: &[CodeObject]) -> (String, usize) {
+        let code = &self.code[address..];
+        let opcode = code[0];
+        let (mnemonic, operands) = match opcode_info(opcode) {
+            Some(info) => info,
+            None => return (format!("dw {}", opcode), 1),
+        };
+
+        let mut parts = Vec::new();
+        let mut comments = Vec::new();
+        let mut size = 1;
+        for operand in operands {
+            let value = match code.get(size) {
+                Some(&value) => value,
+                None => return (format!("{} (cut short)", mnemonic), code.len()),
+            };
+            size += 1;
+            parts.push(match *operand {
+                Operand::Register => format!("%{}", value),
+                Operand::Constant => {
+                    if let Some(constant) = self.constants.get(value as usize) {
+                        comments.push(format!("*{} = {}", value, constant.describe(objects)));
+                    }
+                    format!("*{}", value)
+                },
+                Operand::Jump => format!("{:08}", address as i64 + value as i64),
+                Operand::Count => value.to_string(),
+                Operand::Arguments => {
+                    match value {
+                        ARGUMENTS_OMITTED => String::from("..."),
+                        ARGUMENTS_EXPANDED => {
+                            //Each argument has a type, where 1 and 2 are expanded arrays
+                            let count = code.get(size).cloned().unwrap_or(0).max(0) as usize;
+                            size += 1;
+                            let mut arguments = Vec::new();
+                            for _ in 0..count {
+                                let (kind, register) = match (code.get(size), code.get(size + 1)) {
+                                    (Some(&kind), Some(&register)) => (kind, register),
+                                    _ => break,
+                                };
+                                size += 2;
+                                arguments.push(match kind {
+                                    1 => format!("%{}*", register),
+                                    2 => String::from("*"),
+                                    _ => format!("%{}", register),
+                                });
+                            }
+                            format!("({})", arguments.join(", "))
+                        },
+                        count => {
+                            let count = count.max(0) as usize;
+                            let end = (size + count).min(code.len());
+                            let arguments: Vec<String> = code[size..end].iter()
+                                .map(|register| format!("%{}", register))
+                                .collect();
+                            size = end;
+                            format!("({})", arguments.join(", "))
+                        },
+                    }
+                },
+            });
+        }
+
+        let mut line = String::from(mnemonic);
+        if !parts.is_empty() {
+            line.push(' ');
+            line.push_str(&parts.join(", "));
+        }
+        if !comments.is_empty() {
+            line.push_str(" // ");
+            line.push_str(&comments.join(", "));
+        }
+        (line, size)
+    }
+}
+
+fn string_name(pools: &Pools, index: i32) -> Option<String> {
+    if index < 0 {
+        return None;
+    }
+    pools.strings.get(index as usize).cloned()
+}
+
+///The names of the object and its parents, like "Window.onKeyDown"
+fn qualified_name(objects: &[CodeObject], index: usize) -> String {
+    let mut names = Vec::new();
+    let mut current = objects.get(index);
+    while let Some(object) = current {
+        //Parents can't loop forever in a broken file
+        if names.len() > objects.len() {
+            break;
+        }
+        //The top level is the parent of everything, so it's left out of everything else's name
+        let top_level = object.context == CONTEXT_TOP_LEVEL;
+        if top_level && !names.is_empty() {
+            break;
+        }
+
+        names.push(match object.name {
+            Some(ref name) => name.clone(),
+            None if top_level => String::from("global"),
+            None => String::from("anonymous"),
+        });
+        current = if object.parent < 0 { None } else { objects.get(object.parent as usize) };
+    }
+    if names.is_empty() {
+        return String::from("unknown");
+    }
+
+    names.reverse();
+    names.join(".")
+}
+
+fn context_name(context: i32) -> &'static str {
+    match context {
+        CONTEXT_TOP_LEVEL => "top level",
+        1 => "function",
+        2 => "expression function",
+        3 => "property",
+        4 => "property setter",
+        5 => "property getter",
+        6 => "class",
+        7 => "super class getter",
+        _ => "unknown",
+    }
+}
+
+///What an instruction's operand is
+enum Operand {
+    Register,
+    //An index into the object's constants
+    Constant,
+    //Relative to the instruction
+    Jump,
+    Count,
+    //An argument count, then the registers of the arguments
+    Arguments,
+}
+
+///The four forms of every binary operator: on a register, a named member, an indexed member
+/// and a property
+const OPERATORS: [[&str; 4]; 14] = [
+    ["lor", "lorpd", "lorpi", "lorp"],
+    ["land", "landpd", "landpi", "landp"],
+    ["bor", "borpd", "borpi", "borp"],
+    ["bxor", "bxorpd", "bxorpi", "bxorp"],
+    ["band", "bandpd", "bandpi", "bandp"],
+    ["sar", "sarpd", "sarpi", "sarp"],
+    ["sal", "salpd", "salpi", "salp"],
+    ["sr", "srpd", "srpi", "srp"],
+    ["add", "addpd", "addpi", "addp"],
+    ["sub", "subpd", "subpi", "subp"],
+    ["mod", "modpd", "modpi", "modp"],
+    ["div", "divpd", "divpi", "divp"],
+    ["idiv", "idivpd", "idivpi", "idivp"],
+    ["mul", "mulpd", "mulpi", "mulp"],
+];
+const FIRST_OPERATOR: i32 = 26;
+
+///Gives the mnemonic and operands of an opcode
+fn opcode_info(opcode: i32) -> Option<(&'static str, &'static [Operand])> {
+    use self::Operand::*;
+    const R: &[Operand] = &[Register];
+    const RR: &[Operand] = &[Register, Register];
+    const RRR: &[Operand] = &[Register, Register, Register];
+    const RRC: &[Operand] = &[Register, Register, Constant];
+    const RCR: &[Operand] = &[Register, Constant, Register];
+    const NONE: &[Operand] = &[];
+
+    let operator = opcode - FIRST_OPERATOR;
+    if (0..OPERATORS.len() as i32 * 4).contains(&operator) {
+        let operands: &'static [Operand] = match operator % 4 {
+            0 => RR,
+            1 => &[Register, Register, Constant, Register],
+            2 => &[Register, Register, Register, Register],
+            _ => RRR,
+        };
+        return Some((OPERATORS[operator as usize / 4][operator as usize % 4], operands));
+    }
+
+    Some(match opcode {
+        0 => ("nop", NONE),
+        1 => ("const", &[Register, Constant]),
+        2 => ("cp", RR),
+        3 => ("cl", R),
+        4 => ("ccl", &[Register, Count]),
+        5 => ("tt", R),
+        6 => ("tf", R),
+        7 => ("ceq", RR),
+        8 => ("cdeq", RR),
+        9 => ("clt", RR),
+        10 => ("cgt", RR),
+        11 => ("setf", R),
+        12 => ("setnf", R),
+        13 => ("lnot", R),
+        14 => ("nf", NONE),
+        15 => ("jf", &[Jump]),
+        16 => ("jnf", &[Jump]),
+        17 => ("jmp", &[Jump]),
+        18 => ("inc", R),
+        19 => ("incpd", RRC),
+        20 => ("incpi", RRR),
+        21 => ("incp", RR),
+        22 => ("dec", R),
+        23 => ("decpd", RRC),
+        24 => ("decpi", RRR),
+        25 => ("decp", RR),
+        82 => ("bnot", R),
+        83 => ("typeof", R),
+        84 => ("typeofd", RRC),
+        85 => ("typeofi", RRR),
+        86 => ("eval", R),
+        87 => ("eexp", R),
+        88 => ("chkins", RR),
+        89 => ("asc", R),
+        90 => ("chr", R),
+        91 => ("num", R),
+        92 => ("chs", R),
+        93 => ("inv", R),
+        94 => ("chkinv", R),
+        95 => ("int", R),
+        96 => ("real", R),
+        97 => ("str", R),
+        98 => ("octet", R),
+        99 => ("call", &[Register, Register, Arguments]),
+        100 => ("calld", &[Register, Register, Constant, Arguments]),
+        101 => ("calli", &[Register, Register, Register, Arguments]),
+        102 => ("new", &[Register, Register, Arguments]),
+        103 => ("gpd", RRC),
+        104 => ("spd", RCR),
+        105 => ("spde", RCR),
+        106 => ("spdeh", RCR),
+        107 => ("gpi", RRR),
+        108 => ("spi", RRR),
+        109 => ("spie", RRR),
+        110 => ("gpds", RRC),
+        111 => ("spds", RCR),
+        112 => ("gpis", RRR),
+        113 => ("spis", RRR),
+        114 => ("setp", RR),
+        115 => ("getp", RR),
+        116 => ("deld", RRC),
+        117 => ("deli", RRR),
+        118 => ("srv", R),
+        119 => ("ret", NONE),
+        120 => ("entry", &[Jump, Register]),
+        121 => ("extry", NONE),
+        122 => ("throw", R),
+        123 => ("chgthis", RR),
+        124 => ("global", R),
+        125 => ("addci", RR),
+        126 => ("regmember", NONE),
+        127 => ("debugger", NONE),
+        _ => return None,
+    })
+}
@@ -0,0 +1,876 @@
+use std::cmp;
+use std::collections::{BTreeMap};
+use std::convert::{TryFrom};
+use std::io::{Cursor, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+
+use super::{
+    output_path,
+    png,
+    read_bytes,
+    read_value,
+    Converter,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    ListedItem,
+};
+use file_utils;
+use sink::{Sink};
+use stream::{OffsetStream, SliceStream, Stream};
+
+//Notes taken from kirikiri LoadTLG.cpp and tvpgl.c
+/*
+TLG5 and TLG6 both start with an 11 byte mark, then the color count (3 or 4, TLG6 can also be 1)
+and the size of the image. Pixels are BGRA and each channel is kept apart.
+
+TLG5 splits the image into blocks of rows. Each channel of a block is either raw or LZSS compressed,
+with a 4096 byte sliding window that is kept across every block. Every pixel is the difference from
+the pixel to its left and the one above it, after green has been taken out of blue and red.
+
+TLG6 splits the image into 8x8 blocks that each have a filter type. The channels of every row of
+blocks are Golomb coded, then each pixel is predicted from its neighbours (MED or average) with one
+of 16 color correlation filters. The pixels of every other block are stored in reverse.
+
+TLG0.0 sds is a wrapper around either of them, followed by chunks. The "tags" chunk is a dictionary
+written as "length:key=length:value," pairs.
+*/
+
+pub const TLG0_HEADER: &[u8] = b"TLG0.0\x00sds\x1a";
+pub const TLG5_HEADER: &[u8] = b"TLG5.0\x00raw\x1a";
+pub const TLG6_HEADER: &[u8] = b"TLG6.0\x00raw\x1a";
+
+//The most pixels that an image can have before it isn't decoded, so a bad size can't use up
+// all of the memory
+const MAX_PIXELS: u64 = 1 << 27;
+//The most bytes that all of the buffers of an image can add up to, since they're made before any
+// of the pixels are read. That's the 4 bytes of each pixel and half as much again
+const MAX_BUFFER_SIZE: u64 = MAX_PIXELS * 6;
+
+//The sliding window of the LZSS compression
+const SLIDE_SIZE: usize = 4096;
+
+//TLG6 blocks are this many pixels wide and high
+const TLG6_BLOCK_SIZE: usize = 8;
+
+//How many values the Golomb coder reads before it halves the running total
+const GOLOMB_N_COUNT: usize = 4;
+
+//How many entries of the Golomb bit length table have each bit length, for every n
+const GOLOMB_COMPRESSED: [[u16; 9]; GOLOMB_N_COUNT] = [
+    [3, 7, 15, 27, 63, 108, 223, 448, 130],
+    [3, 5, 13, 24, 51, 95, 192, 384, 257],
+    [2, 5, 12, 21, 39, 86, 155, 320, 384],
+    [2, 3, 9, 18, 33, 61, 129, 258, 511],
+];
+
+//The size of the Golomb bit length table
+const GOLOMB_TABLE_SIZE: usize = GOLOMB_N_COUNT * 2 * 128;
+
+pub struct TLGImage {
+    options: FlareOptions,
+}
+
+impl Converter for TLGImage {
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        stream.little_endian(true);
+        if stream.seek(SeekFrom::Start(0)).is_err() {
+            return 0;
+        }
+        let header = match read_bytes(stream, TLG0_HEADER.len() as u64) {
+            Ok(header) => header.into_owned(),
+            Err(_) => return 0,
+        };
+
+        //The wrapper needs to have one of the images right after its size
+        let valid = if header == TLG0_HEADER {
+            stream.skip(4).is_ok() && read_bytes(stream, TLG5_HEADER.len() as u64)
+                .is_ok_and(|inner| *inner == *TLG5_HEADER || *inner == *TLG6_HEADER)
+        } else if header == TLG5_HEADER || header == TLG6_HEADER {
+            let colors = read_value::<u8, _>(stream).ok();
+            if header == TLG6_HEADER {
+                stream.skip(3).ok();
+            }
+            let width = read_value::<u32, _>(stream).ok();
+            let height = read_value::<u32, _>(stream).ok();
+            match (colors, width, height) {
+                (Some(colors), Some(width), Some(height)) => {
+                    (colors == 3 || colors == 4 || (colors == 1 && header == TLG6_HEADER)) &&
+                        check_size(width, height, 0).is_ok()
+                },
+                _ => false,
+            }
+        } else {
+            return 0;
+        };
+
+        if valid {
+            100
+        } else {
+            60
+        }
+    }
+
+    fn new(options: FlareOptions) -> TLGImage {
+        TLGImage {
+            options,
+        }
+    }
+
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let mut report = FlareReport::default();
+        if !self.options.filter.matches(IMAGE_NAME) {
+            report.skipped = 1;
+            return Ok(report);
+        }
+        report.matched = 1;
+
+        let image = read_image(&mut stream)?;
+        let png = image.to_png();
+        let (path, _) = file_utils::member_path(Path::new(""), IMAGE_NAME);
+        let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+            Some(output) => output,
+            None => return Ok(report),
+        };
+        let location = sink.location(&path);
+        if renamed {
+            report.renamed.push((String::from(IMAGE_NAME), location.clone()));
+        }
+
+        sink.save(&path, png.len() as u64, &mut &png[..])?;
+        report.entries.push(FlaredEntry {
+            name: String::from(IMAGE_NAME),
+            path: location,
+            size: png.len() as u64,
+            offset: 0,
+            attributes: image.attributes(),
+        });
+
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        //The size of the PNG is only known once the whole image is decoded, so the size of the
+        // image that's stored is given instead
+        if Self::confidence(&mut stream) < 100 {
+            return Err(FlareError::Malformed {
+                reason: String::from("the TLG header doesn't make sense"),
+                offset: 0,
+            });
+        }
+        Ok(vec![ListedItem {
+            name: String::from(IMAGE_NAME),
+            original_size: stream.len(),
+            archive_size: stream.len(),
+            compressed_segments: vec![true],
+            protected: false,
+            hash: None,
+        }])
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        //There isn't a checksum, but the image still has to decode
+        read_image(&mut stream)?;
+        Ok(Vec::new())
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        if name != IMAGE_NAME {
+            return Err(FlareError::NotFound {
+                name: String::from(name),
+            });
+        }
+
+        Ok(Box::new(Cursor::new(read_image(&mut stream)?.to_png())))
+    }
+}
+
+//The name of the only item that an image is flared into
+const IMAGE_NAME: &str = "image.png";
+
+///A decoded image, with every pixel as BGRA in the bytes of a little endian u32
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+    version: u8,
+    colors: u8,
+    tags: BTreeMap<String, String>,
+}
+
+impl Image {
+    fn to_png(&self) -> Vec<u8> {
+        let rgba: Vec<u8> = self.pixels.iter().flat_map(|&pixel| {
+            let [blue, green, red, alpha] = pixel.to_le_bytes();
+            [red, green, blue, alpha]
+        }).collect();
+        png::encode_rgba(self.width, self.height, &rgba)
+    }
+
+    fn attributes(&self) -> BTreeMap<String, String> {
+        let mut attributes = BTreeMap::new();
+        attributes.insert(String::from("width"), self.width.to_string());
+        attributes.insert(String::from("height"), self.height.to_string());
+        attributes.insert(String::from("colors"), self.colors.to_string());
+        attributes.insert(String::from("version"), format!("TLG{}", self.version));
+        for (key, value) in &self.tags {
+            attributes.insert(format!("tag:{}", key), value.clone());
+        }
+        attributes
+    }
+}
+
+///Decodes an image that's already in memory straight into a PNG
+pub fn decode_png(data: &[u8]) -> Result<Vec<u8>, FlareError> {
+    Ok(read_image(&mut SliceStream::new(data, true))?.to_png())
+}
+
+///Decodes the image, taking it out of the TLG0.0 sds wrapper if it's in one
+fn read_image<S: Stream>(stream: &mut S) -> Result<Image, FlareError> {
+    stream.little_endian(true);
+    stream.seek(SeekFrom::Start(0))?;
+    if *read_bytes(stream, TLG0_HEADER.len() as u64)? != *TLG0_HEADER {
+        return read_raw_image(stream);
+    }
+
+    let raw_size = read_value::<u32, _>(stream)? as u64;
+    let raw_start = stream.pos();
+    let mut image = read_raw_image(&mut OffsetStream::new(&mut *stream, raw_start)?)?;
+
+    //The chunks go to the end of the file
+    stream.seek(SeekFrom::Start(raw_start + raw_size))?;
+    while stream.pos() < stream.len() {
+        let name = read_bytes(stream, 4)?.into_owned();
+        let size = read_value::<u32, _>(stream)? as u64;
+        let offset = stream.pos();
+        let data = read_bytes(stream, size)?;
+        if *name == *b"tags" {
+            image.tags = parse_tags(&data).ok_or_else(|| FlareError::Malformed {
+                reason: String::from("the tags aren't in the right format"),
+                offset,
+            })?;
+        }
+    }
+
+    Ok(image)
+}
+
+///Decodes a TLG5 or TLG6 image that starts at the start of the stream
+fn read_raw_image<S: Stream>(stream: &mut S) -> Result<Image, FlareError> {
+    stream.seek(SeekFrom::Start(0))?;
+    let header = read_bytes(stream, TLG5_HEADER.len() as u64)?.into_owned();
+    if header == TLG5_HEADER {
+        decode_tlg5(stream)
+    } else if header == TLG6_HEADER {
+        decode_tlg6(stream)
+    } else {
+        Err(FlareError::Malformed {
+            reason: String::from("there isn't a TLG5 or TLG6 header"),
+            offset: 0,
+        })
+    }
+}
+
+///Reads the "length:key=length:value," pairs of the tags chunk
+fn parse_tags(data: &[u8]) -> Option<BTreeMap<String, String>> {
+    //Reads a length, then that many bytes, then the byte that has to come after them
+    fn read_part<'a>(data: &mut &'a [u8], after: u8) -> Option<&'a [u8]> {
+        let colon = data.iter().position(|&byte| byte == b':')?;
+        let size: usize = String::from_utf8_lossy(&data[..colon]).trim().parse().ok()?;
+        let part = data.get(colon + 1..colon + 1 + size)?;
+        if data.get(colon + 1 + size) != Some(&after) {
+            return None;
+        }
+        *data = &data[colon + 2 + size..];
+        Some(part)
+    }
+
+    let mut tags = BTreeMap::new();
+    let mut data = data;
+    //Some writers pad the end with zeros
+    while data.iter().any(|&byte| byte != 0) {
+        let key = read_part(&mut data, b'=')?;
+        let value = read_part(&mut data, b',')?;
+        tags.insert(String::from_utf8_lossy(key).into_owned(),
+            String::from_utf8_lossy(value).into_owned());
+    }
+    Some(tags)
+}
+
+///Makes sure that the image has pixels, but not so many that they can't be held in memory
+fn check_size(width: u32, height: u32, offset: u64) -> Result<(), FlareError> {
+    let pixels = width as u64 * height as u64;
+    if pixels == 0 || pixels > MAX_PIXELS {
+        return Err(FlareError::Malformed {
+            reason: format!("the image can't be {}x{}", width, height),
+            offset,
+        });
+    }
+    Ok(())
+}
+
+///Makes sure that the buffers that the image is decoded into fit in MAX_BUFFER_SIZE
+fn check_buffers(size: u64, offset: u64) -> Result<(), FlareError> {
+    if size > MAX_BUFFER_SIZE {
+        return Err(FlareError::Malformed {
+            reason: format!("the image needs {} bytes of buffers to be decoded", size),
+            offset,
+        });
+    }
+    Ok(())
+}
+
+///Makes the image with any channels that weren't stored filled in
+fn finish_image(width: u32, height: u32, mut pixels: Vec<u32>, version: u8, colors: u8)
+-> Image {
+    for pixel in &mut pixels {
+        *pixel = match colors {
+            //Gray is only kept in blue
+            1 => ((*pixel & 0xff) * 0x01_0101) | 0xff00_0000,
+            3 => *pixel | 0xff00_0000,
+            _ => *pixel,
+        };
+    }
+
+    Image {
+        width,
+        height,
+        pixels,
+        version,
+        colors,
+        tags: BTreeMap::new(),
+    }
+}
+
+fn decode_tlg5<S: Stream>(stream: &mut S) -> Result<Image, FlareError> {
+    let colors_offset = stream.pos();
+    let colors = read_value::<u8, _>(stream)?;
+    if colors != 3 && colors != 4 {
+        return Err(FlareError::BadFlag {
+            what: "TLG5 color count",
+            flag: colors as u64,
+            offset: colors_offset,
+        });
+    }
+    let width = read_value::<u32, _>(stream)?;
+    let height = read_value::<u32, _>(stream)?;
+    let block_height = read_value::<u32, _>(stream)?;
+    check_size(width, height, colors_offset)?;
+    if block_height == 0 {
+        return Err(FlareError::Malformed {
+            reason: String::from("the blocks can't have 0 rows"),
+            offset: colors_offset,
+        });
+    }
+    //Every channel of a block, then the pixels
+    let block_size = cmp::min(block_height, height) as u64 * width as u64;
+    check_buffers(colors as u64 * block_size + width as u64 * height as u64 * 4, colors_offset)?;
+    let (width, height, block_height) = (width as usize, height as usize, block_height as usize);
+
+    //Skip the size of every block since they're read in order anyway
+    let block_count = (height - 1) / block_height + 1;
+    stream.skip(block_count as i64 * 4)?;
+
+    let mut text = [0; SLIDE_SIZE];
+    let mut slide_pos = 0;
+    let mut channels = vec![vec![0_u8; block_size as usize]; colors as usize];
+    let mut pixels = vec![0_u32; width * height];
+    for block_y in (0..height).step_by(block_height) {
+        let rows = cmp::min(block_height, height - block_y);
+        for channel in &mut channels {
+            let mark = read_value::<u8, _>(stream)?;
+            let size = read_value::<u32, _>(stream)? as u64;
+            let offset = stream.pos();
+            let data = read_bytes(stream, size)?;
+            let channel = &mut channel[..rows * width];
+            if mark == 0 {
+                slide_pos = decompress_slide(&data, channel, &mut text, slide_pos)
+                    .ok_or_else(|| FlareError::Malformed {
+                        reason: String::from("the compressed block doesn't fit the image"),
+                        offset,
+                    })?;
+            } else {
+                let size = cmp::min(data.len(), channel.len());
+                channel[..size].copy_from_slice(&data[..size]);
+            }
+        }
+
+        //Green is taken out of blue and red, then each pixel is from its left and upper pixels
+        for y in block_y..block_y + rows {
+            let mut sums = [0_u8; 4];
+            for x in 0..width {
+                let index = (y - block_y) * width + x;
+                let mut deltas = [0_u8; 4];
+                for (delta, channel) in deltas.iter_mut().zip(&channels) {
+                    *delta = channel[index];
+                }
+                deltas[0] = deltas[0].wrapping_add(deltas[1]);
+                deltas[2] = deltas[2].wrapping_add(deltas[1]);
+
+                let upper = if y == 0 { 0 } else { pixels[(y - 1) * width + x] }.to_le_bytes();
+                let mut pixel = [0_u8; 4];
+                for channel in 0..4 {
+                    sums[channel] = sums[channel].wrapping_add(deltas[channel]);
+                    pixel[channel] = sums[channel].wrapping_add(upper[channel]);
+                }
+                pixels[y * width + x] = u32::from_le_bytes(pixel);
+            }
+        }
+    }
+
+    Ok(finish_image(width as u32, height as u32, pixels, 5, colors))
+}
+
+///Decompresses LZSS data into out with the sliding window text, starting at slide_pos
+///Gives the position of the window to carry on with, or None if the data doesn't fit into out
+fn decompress_slide(data: &[u8], out: &mut [u8], text: &mut [u8; SLIDE_SIZE], slide_pos: usize)
+-> Option<usize> {
+    let mut slide_pos = slide_pos;
+    let mut out_pos = 0;
+    let mut data_pos = 0;
+    //The high byte marks when another byte of flags needs to be read
+    let mut flags = 0_u32;
+    while data_pos < data.len() {
+        flags >>= 1;
+        if flags & 0x100 == 0 {
+            flags = *data.get(data_pos)? as u32 | 0xff00;
+            data_pos += 1;
+        }
+
+        if flags & 1 == 1 {
+            //A match from the window, with its position and length packed into two bytes
+            let low = *data.get(data_pos)? as usize;
+            let high = *data.get(data_pos + 1)? as usize;
+            data_pos += 2;
+            let mut match_pos = low | ((high & 0xf) << 8);
+            let mut match_len = (high >> 4) + 3;
+            if match_len == 18 {
+                match_len += *data.get(data_pos)? as usize;
+                data_pos += 1;
+            }
+
+            for _ in 0..match_len {
+                let byte = text[match_pos];
+                *out.get_mut(out_pos)? = byte;
+                text[slide_pos] = byte;
+                out_pos += 1;
+                match_pos = (match_pos + 1) & (SLIDE_SIZE - 1);
+                slide_pos = (slide_pos + 1) & (SLIDE_SIZE - 1);
+            }
+        } else if data_pos < data.len() {
+            let byte = data[data_pos];
+            data_pos += 1;
+            *out.get_mut(out_pos)? = byte;
+            text[slide_pos] = byte;
+            out_pos += 1;
+            slide_pos = (slide_pos + 1) & (SLIDE_SIZE - 1);
+        }
+    }
+
+    Some(slide_pos)
+}
+
+fn decode_tlg6<S: Stream>(stream: &mut S) -> Result<Image, FlareError> {
+    let colors_offset = stream.pos();
+    let colors = read_value::<u8, _>(stream)?;
+    if colors != 1 && colors != 3 && colors != 4 {
+        return Err(FlareError::BadFlag {
+            what: "TLG6 color count",
+            flag: colors as u64,
+            offset: colors_offset,
+        });
+    }
+    //The data flag, color type and external Golomb table are never used
+    for what in ["TLG6 data flag", "TLG6 color type", "TLG6 Golomb table"] {
+        let offset = stream.pos();
+        let flag = read_value::<u8, _>(stream)?;
+        if flag != 0 {
+            return Err(FlareError::BadFlag {
+                what,
+                flag: flag as u64,
+                offset,
+            });
+        }
+    }
+    let width = read_value::<u32, _>(stream)?;
+    let height = read_value::<u32, _>(stream)?;
+    check_size(width, height, colors_offset)?;
+    //The longest any channel's bits can be, which isn't needed to decode
+    stream.skip(4)?;
+    let (width, height) = (width as usize, height as usize);
+
+    let x_blocks = (width - 1) / TLG6_BLOCK_SIZE + 1;
+    let y_blocks = (height - 1) / TLG6_BLOCK_SIZE + 1;
+    let block_rows = cmp::min(TLG6_BLOCK_SIZE, height);
+    //The filter types, the pixels, the row above the first one and a row of blocks
+    let buffer_size = (x_blocks * y_blocks) as u64 +
+        (width * height + width + block_rows * width) as u64 * 4;
+    check_buffers(buffer_size, colors_offset)?;
+    let main_count = width / TLG6_BLOCK_SIZE;
+    let fraction = width - main_count * TLG6_BLOCK_SIZE;
+
+    //The filter type of every block is LZSS compressed with a window that starts filled in
+    let filter_size = read_value::<u32, _>(stream)? as u64;
+    let filter_offset = stream.pos();
+    let filter_data = read_bytes(stream, filter_size)?.into_owned();
+    let mut filter_types = vec![0; x_blocks * y_blocks];
+    decompress_slide(&filter_data, &mut filter_types, &mut filter_text(), 0)
+        .ok_or_else(|| FlareError::Malformed {
+            reason: String::from("the filter types don't fit the image"),
+            offset: filter_offset,
+        })?;
+
+    let golomb_table = golomb_table();
+    let initial = if colors == 3 { 0xff00_0000 } else { 0 };
+    let mut pixels = vec![0_u32; width * height];
+    //The row above the first one
+    let zero_line = vec![initial; width];
+    let mut block_pixels = vec![0_u32; block_rows * width];
+    for y in (0..height).step_by(TLG6_BLOCK_SIZE) {
+        let y_limit = cmp::min(y + TLG6_BLOCK_SIZE, height);
+        let pixel_count = (y_limit - y) * width;
+
+        block_pixels.iter_mut().for_each(|pixel| *pixel = 0);
+        for channel in 0..colors as usize {
+            let length_offset = stream.pos();
+            let bit_length = read_value::<u32, _>(stream)?;
+            let method = bit_length >> 30;
+            if method != 0 {
+                return Err(FlareError::BadFlag {
+                    what: "TLG6 entropy coding",
+                    flag: method as u64,
+                    offset: length_offset,
+                });
+            }
+            let byte_length = ((bit_length & 0x3fff_ffff) as u64).div_ceil(8);
+            let data = read_bytes(stream, byte_length)?;
+            decode_golomb(&data, &mut block_pixels[..pixel_count], channel, &golomb_table)
+                .ok_or_else(|| FlareError::Malformed {
+                    reason: String::from("the Golomb coded pixels don't fit the image"),
+                    offset: length_offset,
+                })?;
+        }
+
+        let filters = &filter_types[(y / TLG6_BLOCK_SIZE) * x_blocks..][..x_blocks];
+        let skip = (y_limit - y) * TLG6_BLOCK_SIZE;
+        for line_y in y..y_limit {
+            let (before, rest) = pixels.split_at_mut(line_y * width);
+            let previous = if line_y == 0 { &zero_line[..] } else { &before[(line_y - 1) * width..] };
+            let line = Line {
+                previous,
+                current: &mut rest[..width],
+                filters,
+                skip,
+                pixels: &block_pixels,
+                initial,
+                odd_skip: (y_limit - line_y - 1) as isize - (line_y - y) as isize,
+                forward: line_y & 1 == 0,
+            };
+
+            let decoded = line.decode(main_count, x_blocks, fraction, line_y - y);
+            if decoded.is_none() {
+                return Err(FlareError::Malformed {
+                    reason: format!("row {} can't be decoded with its filters", line_y),
+                    offset: filter_offset,
+                });
+            }
+        }
+    }
+
+    Ok(finish_image(width as u32, height as u32, pixels, 6, colors))
+}
+
+///The window that the filter types are decompressed with, which starts with every pair of
+/// color correlation filter and predictor
+fn filter_text() -> [u8; SLIDE_SIZE] {
+    let mut text = [0; SLIDE_SIZE];
+    for (index, pair) in text.chunks_mut(8).enumerate() {
+        pair[..4].copy_from_slice(&[(index / 16) as u8; 4]);
+        pair[4..].copy_from_slice(&[(index % 16) as u8; 4]);
+    }
+    text
+}
+
+///The number of bits that each Golomb value has, by the running total then the value count
+fn golomb_table() -> Vec<[u8; GOLOMB_N_COUNT]> {
+    let mut table = vec![[0; GOLOMB_N_COUNT]; GOLOMB_TABLE_SIZE];
+    for (n, lengths) in GOLOMB_COMPRESSED.iter().enumerate() {
+        let mut index = 0;
+        for (bits, &count) in lengths.iter().enumerate() {
+            for _ in 0..count {
+                table[index][n] = bits as u8;
+                index += 1;
+            }
+        }
+    }
+    table
+}
+
+///Reads bits from the lowest bit of each byte up
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl <'a> BitReader<'a> {
+    ///The next 25 to 32 bits, with zeros past the end
+    fn window(&self) -> u32 {
+        let byte = self.pos / 8;
+        let mut bytes = [0; 4];
+        for (index, window_byte) in bytes.iter_mut().enumerate() {
+            *window_byte = self.data.get(byte + index).cloned().unwrap_or(0);
+        }
+        u32::from_le_bytes(bytes) >> (self.pos % 8)
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        let mut done = 0;
+        while done < count {
+            let size = cmp::min(count - done, 16);
+            value |= (self.window() & ((1 << size) - 1)) << done;
+            self.pos += size as usize;
+            done += size;
+        }
+        value
+    }
+
+    ///Counts the zeros before the next one bit, moving past the one
+    ///Gives None if there isn't another one bit
+    fn zeros(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        loop {
+            if self.pos >= self.data.len() * 8 {
+                return None;
+            }
+            let window = self.window();
+            if window != 0 {
+                self.pos += window.trailing_zeros() as usize + 1;
+                return Some(zeros + window.trailing_zeros());
+            }
+            let size = 32 - (self.pos % 8) as u32;
+            zeros += size;
+            self.pos += size as usize;
+        }
+    }
+}
+
+///Decodes the Golomb coded values of one channel into that byte of every pixel
+///Gives None if the values don't fit the pixels or the bits run out
+fn decode_golomb(data: &[u8], pixels: &mut [u32], channel: usize,
+    table: &[[u8; GOLOMB_N_COUNT]])
+-> Option<()> {
+    let mut bits = BitReader {
+        data,
+        pos: 1,
+    };
+    //Runs of zeros and runs of values take turns, and the first bit says which comes first
+    let mut zero = *data.first()? & 1 == 0;
+    let mut n = GOLOMB_N_COUNT - 1;
+    let mut total = 0;
+    let shift = channel * 8;
+
+    let mut index = 0;
+    while index < pixels.len() {
+        let count_bits = bits.zeros()?;
+        if count_bits >= 32 {
+            return None;
+        }
+        let count = ((1_u64 << count_bits) + bits.read(count_bits) as u64) as usize;
+        let run = pixels.get_mut(index..index + count)?;
+        index += count;
+        if zero {
+            zero = false;
+            continue;
+        }
+        zero = true;
+
+        for pixel in run {
+            let k = table[cmp::min(total, GOLOMB_TABLE_SIZE - 1)][n] as u32;
+            //All zeros means the number of bits is in the byte after the next four
+            let bit_count = if bits.window() == 0 {
+                let byte = bits.pos / 8 + 5;
+                bits.pos = byte * 8;
+                *data.get(byte - 1)? as u32
+            } else {
+                bits.zeros()?
+            };
+            let value = (bit_count << k) + bits.read(k);
+
+            //Odd values are positive and even values are negative
+            let magnitude = value >> 1;
+            total += magnitude as usize;
+            let byte = if value & 1 == 1 {
+                magnitude.wrapping_add(1)
+            } else {
+                !magnitude
+            } as u8;
+            *pixel |= (byte as u32) << shift;
+
+            if n == 0 {
+                total >>= 1;
+                n = GOLOMB_N_COUNT - 1;
+            } else {
+                n -= 1;
+            }
+        }
+    }
+
+    Some(())
+}
+
+///Everything needed to decode a single row of a TLG6 image
+struct Line<'a> {
+    previous: &'a [u32],
+    current: &'a mut [u32],
+    //The filter type of every block in this row of blocks
+    filters: &'a [u8],
+    //How many pixels there are in each block of this row of blocks
+    skip: usize,
+    pixels: &'a [u32],
+    initial: u32,
+    odd_skip: isize,
+    forward: bool,
+}
+
+impl <'a> Line<'a> {
+    ///Decodes the whole blocks, then the block that's cut off by the right edge
+    fn decode(mut self, main_count: usize, block_count: usize, fraction: usize, row: usize)
+    -> Option<()> {
+        let width = self.current.len();
+        if main_count > 0 {
+            let start = cmp::min(width, TLG6_BLOCK_SIZE) * row;
+            self.decode_blocks(0, main_count, start)?;
+        }
+        if main_count != block_count {
+            let start = cmp::min(fraction, TLG6_BLOCK_SIZE) * row;
+            self.decode_blocks(main_count, block_count, start)?;
+        }
+        Some(())
+    }
+
+    fn decode_blocks(&mut self, first_block: usize, block_limit: usize, start: usize)
+    -> Option<()> {
+        let width = self.current.len();
+        let mut x = first_block * TLG6_BLOCK_SIZE;
+        let (mut left, mut upper_left) = if first_block > 0 {
+            (self.current[x - 1], self.previous[x - 1])
+        } else {
+            (self.initial, self.initial)
+        };
+
+        let step: isize = if self.forward { 1 } else { -1 };
+        let mut input = (start + self.skip * first_block) as isize;
+        for block in first_block..block_limit {
+            let block_width = cmp::min(width - block * TLG6_BLOCK_SIZE, TLG6_BLOCK_SIZE) as isize;
+            if step == -1 {
+                input += block_width - 1;
+            }
+            if block & 1 == 1 {
+                input += self.odd_skip * block_width;
+            }
+
+            let filter = *self.filters.get(block)?;
+            if filter >= 32 {
+                return None;
+            }
+            for _ in 0..block_width {
+                let [blue, green, red, alpha] = self.pixels.get(usize::try_from(input).ok()?)?
+                    .to_le_bytes();
+                let (blue, green, red) = decorrelate(filter >> 1, blue, green, red);
+                let delta = u32::from_le_bytes([blue, green, red, alpha]);
+
+                let upper = self.previous[x];
+                let predicted = if filter & 1 == 0 {
+                    median(left, upper, upper_left)
+                } else {
+                    average(left, upper)
+                };
+                left = add_bytes(predicted, delta);
+                upper_left = upper;
+                self.current[x] = left;
+                x += 1;
+                input += step;
+            }
+
+            input += if step == 1 {
+                self.skip as isize - block_width
+            } else {
+                self.skip as isize + 1
+            };
+            if block & 1 == 1 {
+                input -= self.odd_skip * block_width;
+            }
+        }
+        Some(())
+    }
+}
+
+///Undoes one of the 16 color correlation filters
+fn decorrelate(filter: u8, blue: u8, green: u8, red: u8) -> (u8, u8, u8) {
+    let (b, g, r) = (blue, green, red);
+    match filter {
+        0 => (b, g, r),
+        1 => (b.wrapping_add(g), g, r.wrapping_add(g)),
+        2 => (b, g.wrapping_add(b), r.wrapping_add(b).wrapping_add(g)),
+        3 => (b.wrapping_add(r).wrapping_add(g), g.wrapping_add(r), r),
+        4 => (b.wrapping_add(r), g.wrapping_add(b).wrapping_add(r),
+            r.wrapping_add(b).wrapping_add(r).wrapping_add(g)),
+        5 => (b.wrapping_add(r), g.wrapping_add(b).wrapping_add(r), r),
+        6 => (b.wrapping_add(g), g, r),
+        7 => (b, g.wrapping_add(b), r),
+        8 => (b, g, r.wrapping_add(g)),
+        9 => (b.wrapping_add(g).wrapping_add(r).wrapping_add(b), g.wrapping_add(r).wrapping_add(b),
+            r.wrapping_add(b)),
+        10 => (b.wrapping_add(r), g.wrapping_add(r), r),
+        11 => (b, g.wrapping_add(b), r.wrapping_add(b)),
+        12 => (b, g.wrapping_add(r).wrapping_add(b), r.wrapping_add(b)),
+        13 => (b.wrapping_add(g), g.wrapping_add(r).wrapping_add(b).wrapping_add(g),
+            r.wrapping_add(b).wrapping_add(g)),
+        14 => (b.wrapping_add(g).wrapping_add(r), g.wrapping_add(r),
+            r.wrapping_add(b).wrapping_add(g).wrapping_add(r)),
+        _ => (b, g.wrapping_add(b << 1), r.wrapping_add(b << 1)),
+    }
+}
+
+///Applies the function to every byte of the pixels on their own
+fn per_byte<F: Fn(u8, u8, u8) -> u8>(a: u32, b: u32, c: u32, function: F) -> u32 {
+    let (a, b, c) = (a.to_le_bytes(), b.to_le_bytes(), c.to_le_bytes());
+    let mut out = [0; 4];
+    for (index, byte) in out.iter_mut().enumerate() {
+        *byte = function(a[index], b[index], c[index]);
+    }
+    u32::from_le_bytes(out)
+}
+
+///The median edge detector, which picks from the left and upper pixels unless the upper left pixel
+/// is between them
+fn median(left: u32, upper: u32, upper_left: u32) -> u32 {
+    per_byte(left, upper, upper_left, |a, b, c| {
+        let (max, min) = if a > b { (a, b) } else { (b, a) };
+        if c >= max {
+            min
+        } else if c <= min {
+            max
+        } else {
+            a.wrapping_add(b).wrapping_sub(c)
+        }
+    })
+}
+
+///The average of the left and upper pixels, rounded up
+fn average(left: u32, upper: u32) -> u32 {
+    per_byte(left, upper, 0, |a, b, _| ((a as u16 + b as u16 + 1) >> 1) as u8)
+}
+
+///Adds every byte without carrying into the next one
+fn add_bytes(a: u32, b: u32) -> u32 {
+    per_byte(a, b, 0, |a, b, _| a.wrapping_add(b))
+}
@@ -1,16 +1,40 @@
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
-use std::io::{Cursor, SeekFrom};
+use std::borrow::{Cow};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{Error, Result as IOResult, SeekFrom, Take};
+use std::mem;
 use std::io::prelude::*;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use flate2::read::{ZlibDecoder as ZlibReadDecoder};
 use flate2::write::{ZlibDecoder};
-
-
-use super::{Converter};
+use serde_json;
+
+
+use super::{
+    output_path,
+    read_bytes,
+    read_value,
+    save_item,
+    ChecksumPolicy,
+    Converter,
+    FlaredEntry,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    ListedItem,
+    NameList,
+};
+use super::crypt::{CryptFilter};
+use super::pe;
+use checksum::{Adler32, Adler32Reader};
 use file_utils;
-use stream::{ReadStream, UTF16LE};
+use sink::{Sink};
+use stream::{SliceStream, Stream, UTF16LE};
 
-type InMemoryStream = ReadStream<Cursor<Vec<u8>>>;
+type InMemoryStream<'a> = SliceStream<&'a [u8]>;
 
 //Notes taken from kirikiri XP3Archive.cpp
 /*
@@ -97,7 +121,7 @@ XP3 header mark contains:
 
 
 //If 1, uses zLib compression, if 0 then raw, error if anything else
-const ENCODING_MASK: u8 = 0x07;
+pub const ENCODING_MASK: u8 = 0x07;
 
 //The mask for the index flag to keep reading entries
 const CONTINUE_MASK: u8 = 0x80;
@@ -105,105 +129,553 @@ const CONTINUE_MASK: u8 = 0x80;
 //The mask to check if an index is protected
 const PROTECTED_MASK: u32 = 1 << 31;
 
+//How many 100 nanosecond ticks there are from 1601, where a FILETIME starts, to 1970
+const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+//The file that the unknown chunks of every flared item are saved in, by the item's path
+pub const CHUNKS_NAME: &str = ".xp3_chunks.json";
+
+//How much of a segment gets copied at a time
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
 //Magic: XP3\r\n \x1a\x8b\x67\x01
-const HEADER: &'static [u8] = &[ 0x58, 0x50, 0x33, 0x0d, 0x0a, 0x20, 0x0a, 0x1a, 0x8b, 0x67, 0x01 ];
+pub const HEADER: &[u8] = &[ 0x58, 0x50, 0x33, 0x0d, 0x0a, 0x20, 0x0a, 0x1a, 0x8b, 0x67, 0x01 ];
 
 pub struct XP3Archive {
-
+    options: FlareOptions,
 }
 
 impl Converter for XP3Archive {
-    fn is_correct_format<R: Read + Seek>(stream: &mut ReadStream<R>) -> bool {
-        find_start_offset(stream).is_some()
+    fn confidence<S: Stream>(stream: &mut S) -> u8 {
+        let start_offset = match find_start_offset(stream) {
+            Some(start_offset) => start_offset,
+            None => return 0,
+        };
+
+        //A header that points its index past the end is probably broken, but is still an XP3
+        let len = stream.len();
+        let index_inside = read_value::<u64, _>(stream).ok()
+            .and_then(|index_offset| index_offset.checked_add(start_offset))
+            .is_some_and(|index_offset| index_offset < len);
+        match (start_offset, index_inside) {
+            (_, false) => 60,
+            (0, true) => 100,
+            //Hidden behind an exe
+            (_, true) => 90,
+        }
     }
 
-    fn new() -> XP3Archive {
+    fn new(options: FlareOptions) -> XP3Archive {
         XP3Archive {
-            
+            options,
         }
     }
 
-    fn flare<R: Read + Seek>(&mut self, mut stream: ReadStream<R>, save_folder: &PathBuf) {
-        // If this is called, the stream is the correct format
-        let start_offset = find_start_offset(&mut stream).unwrap();
+    fn flare<S: Stream + Read>(&mut self, mut stream: S, sink: &mut dyn Sink)
+    -> Result<FlareReport, FlareError> {
+        let start_offset = find_header(&mut stream)?;
 
-        let mut items: Vec<ArchiveItem> = Vec::new();
+        let mut report = FlareReport::default();
+        let items = read_index(&mut stream, start_offset, &self.options.names)?;
+        let item_count = items.len();
 
-        loop {
-            let (mut entry_data, entry_flag) = find_entry_data(&mut stream, start_offset);
+        //Skip the items that we don't want before any of their segments get read
+        let mut items: Vec<ArchiveItem> = items.into_iter()
+            .filter(|item| self.options.filter.matches(&item.name))
+            .collect();
+        items.sort();
+        report.matched = items.len();
+        report.skipped = item_count - items.len();
+
+        //The unknown chunks of every saved item by the name that pack_folder() would give it
+        let mut saved_chunks: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+        //Save all of the files from the items
+        for item in items {
+            let (path, rewritten) = file_utils::member_path(Path::new(""), &item.name);
+            let (path, renamed) = match output_path(path, &self.options, &mut report, sink)? {
+                Some(output) => output,
+                None => continue,
+            };
+            let location = sink.location(&path);
+            if rewritten || renamed {
+                report.renamed.push((item.name.clone(), location.clone()));
+            }
 
-            //Keep going while we are finding file chunks
-            while let Some(Chunk::File(ref mut file_data)) = find_chunk(&mut entry_data) {
-                items.push(ArchiveItem::new(file_data, start_offset));
+            //The reader gives the file in order so the checksum can be done at the same time
+            let mut reader = Adler32Reader::new(
+                EntryReader::new(&mut stream, &item, self.options.crypt.clone()));
+            //One broken item doesn't stop the rest, only the sink breaking does
+            if !save_item(sink, &path, &item.name, item.original_size, &mut reader, &mut report)? {
+                continue;
+            }
+            if item.protected {
+                report.warnings.push(format!("{} is protected", item.name));
+            }
+            report.warnings.extend(item.warnings.iter()
+                .map(|warning| format!("{}: {}", item.name, warning)));
+
+            if reader.hash() != item.file_hash {
+                report.bad_checksums.push(location.clone());
+                if self.options.bad_checksum == ChecksumPolicy::Delete {
+                    sink.remove(&path)?;
+                    continue;
+                }
+            }
+            if let Some(modified) = item.time.and_then(from_filetime) {
+                sink.set_modified(&path, modified)?;
             }
 
-            if entry_flag & CONTINUE_MASK == 0 {
-                break;
+            let offset = item.segments.first().map_or(0, |segment| segment.start);
+            let mut attributes = BTreeMap::new();
+            attributes.insert(String::from("hash"), format!("{:08x}", item.file_hash));
+            attributes.insert(String::from("protected"), item.protected.to_string());
+            attributes.insert(String::from("archive_size"), item.archive_size.to_string());
+            attributes.insert(String::from("segments"), item.segments.len().to_string());
+            if let Some(stored_name) = item.stored_name {
+                attributes.insert(String::from("stored_name"), stored_name);
             }
+            if let Some(time) = item.time {
+                attributes.insert(String::from("time"), time.to_string());
+            }
+            //Chunks that we don't know are kept so they can be packed again
+            let mut chunks = BTreeMap::new();
+            for (name, data) in item.chunks {
+                let data: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+                chunks.insert(chunk_attribute(name), data);
+            }
+            if !chunks.is_empty() {
+                attributes.extend(chunks.clone());
+                let packed_name = path.components()
+                    .map(|part| part.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<String>>()
+                    .join("/");
+                saved_chunks.insert(packed_name, chunks);
+            }
+            report.entries.push(FlaredEntry {
+                name: item.name,
+                path: location,
+                //The reader makes sure that every segment was the right size
+                size: item.original_size,
+                offset,
+                attributes,
+            });
+        }
+
+        if !saved_chunks.is_empty() {
+            save_chunks(&saved_chunks, &self.options, &mut report, sink)?;
         }
 
+        Ok(report)
+    }
+
+    fn list<S: Stream>(&mut self, mut stream: S)
+    -> Result<Vec<ListedItem>, FlareError> {
+        let start_offset = find_header(&mut stream)?;
+
+        //Keep the index order since that's how the archive was made
+        Ok(read_index(&mut stream, start_offset, &self.options.names)?.into_iter().map(|item| {
+            ListedItem {
+                compressed_segments: item.segments.iter().map(|segment| segment.compressed).collect(),
+                name: item.name,
+                original_size: item.original_size,
+                archive_size: item.archive_size,
+                protected: item.protected,
+                hash: Some(item.file_hash),
+            }
+        }).collect())
+    }
+
+    fn verify<S: Stream + Read>(&mut self, mut stream: S)
+    -> Result<Vec<String>, FlareError> {
+        let start_offset = find_header(&mut stream)?;
+
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        let mut items: Vec<ArchiveItem> = read_index(&mut stream, start_offset, &self.options.names)?
+            .into_iter()
+            .filter(|item| self.options.filter.matches(&item.name))
+            .collect();
         items.sort();
 
-        //Write all of the files from the items
+        let mut bad_items = Vec::new();
         for item in items {
-            let mut path = save_folder.clone();
-            path.push(item.name);
-
-            let mut file = file_utils::make_file(&path);
-            for segment in item.segments {
-                stream.seek(SeekFrom::Start(segment.start)).unwrap();
-                let buffer = if segment.compressed {
-                    let compressed = stream.read_exact(segment.archive_size as usize).unwrap();
-
-                    let mut decompressor = ZlibDecoder::new(Vec::new());
-                    decompressor.write_all(&compressed).unwrap();
-                    decompressor.finish().unwrap()
+            let mut adler = Adler32::new();
+            let mut reader = EntryReader::new(&mut stream, &item, self.options.crypt.clone());
+            let mut readable = true;
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => adler.update(&buffer[..read]),
+                    //An item that can't be read is just as bad, and the rest still get checked
+                    Err(_) => {
+                        readable = false;
+                        break;
+                    },
+                }
+            }
+
+            if !readable || adler.hash() != item.file_hash {
+                bad_items.push(item.name);
+            }
+        }
+
+        Ok(bad_items)
+    }
+
+    fn entry_reader<S: Stream + Read + 'static>(&mut self, mut stream: S, name: &str)
+    -> Result<Box<dyn Read>, FlareError> {
+        let start_offset = find_header(&mut stream)?;
+
+        let item = match read_index(&mut stream, start_offset, &self.options.names)?.into_iter()
+            .find(|item| item.name == name) {
+            Some(item) => item,
+            None => return Err(FlareError::NotFound {
+                name: String::from(name),
+            }),
+        };
+
+        Ok(Box::new(EntryReader::new(stream, &item, self.options.crypt.clone())))
+    }
+}
+
+///Saves the unknown chunks of every item next to the flared files, so pack_folder() can write
+/// them back
+fn save_chunks(saved_chunks: &BTreeMap<String, BTreeMap<String, String>>, options: &FlareOptions,
+    report: &mut FlareReport, sink: &mut dyn Sink) -> Result<(), FlareError> {
+    let (path, renamed) = match output_path(PathBuf::from(CHUNKS_NAME), options, report, sink)? {
+        Some(output) => output,
+        None => return Ok(()),
+    };
+    let location = sink.location(&path);
+    if renamed {
+        report.renamed.push((String::from(CHUNKS_NAME), location.clone()));
+    }
+
+    let json = serde_json::to_vec_pretty(saved_chunks).expect("Strings can always be JSON");
+    sink.save(&path, json.len() as u64, &mut &json[..])?;
+    let mut attributes = BTreeMap::new();
+    attributes.insert(String::from("items"), saved_chunks.len().to_string());
+    report.entries.push(FlaredEntry {
+        name: String::from(CHUNKS_NAME),
+        path: location,
+        size: json.len() as u64,
+        offset: 0,
+        attributes,
+    });
+
+    Ok(())
+}
+
+///Gives the attribute that a chunk's data is kept in
+///Names that aren't printable ASCII are given in hex so nothing is lost
+pub fn chunk_attribute(name: [u8; 4]) -> String {
+    if name.iter().all(|byte| byte.is_ascii_graphic()) {
+        format!("chunk:{}", String::from_utf8_lossy(&name))
+    } else {
+        format!("chunk:0x{:08x}", u32::from_be_bytes(name))
+    }
+}
+
+///Reads the flared contents of a single item, one segment after another
+///
+///Each segment is decompressed and decrypted while it's read, so only the caller's buffer is needed.
+///Errors are FlareErrors inside of the io::Error so they can be turned back with FlareError::from().
+struct EntryReader<S: Stream + Read> {
+    //Only None while moving between segments
+    state: Option<SegmentState<S>>,
+    segments: VecDeque<ArchiveSegment>,
+    //The segment that is being read right now
+    current: Option<ArchiveSegment>,
+    //How many bytes have been given from the current segment
+    copied: u64,
+    file_hash: u32,
+    crypt: Option<Arc<dyn CryptFilter>>,
+}
+
+enum SegmentState<S: Stream + Read> {
+    Between(S),
+    Raw(Take<S>),
+    //One byte more than the segment should have is let out, so data that inflates past its size is
+    // caught without reading all of it
+    Zlib(Take<ZlibReadDecoder<Take<S>>>),
+}
+
+impl <S: Stream + Read> EntryReader<S> {
+    fn new(stream: S, item: &ArchiveItem, crypt: Option<Arc<dyn CryptFilter>>) -> EntryReader<S> {
+        //The segments need to be in order for the item to come out in order
+        let mut segments = item.segments.clone();
+        segments.sort();
+
+        EntryReader {
+            state: Some(SegmentState::Between(stream)),
+            segments: segments.into_iter().collect(),
+            current: None,
+            copied: 0,
+            file_hash: item.file_hash,
+            crypt,
+        }
+    }
+
+    ///Moves the stream to the start of the next segment
+    ///Gives false if there aren't any segments left
+    fn next_segment(&mut self) -> Result<bool, FlareError> {
+        let mut stream = match self.state.take() {
+            Some(SegmentState::Between(stream)) => stream,
+            _ => unreachable!("a segment can only be started between segments"),
+        };
+
+        let segment = match self.segments.pop_front() {
+            Some(segment) => segment,
+            None => {
+                self.state = Some(SegmentState::Between(stream));
+                return Ok(false);
+            },
+        };
+
+        stream.seek(SeekFrom::Start(segment.start))?;
+        self.state = Some(if segment.compressed {
+            let decoder = ZlibReadDecoder::new(stream.take(segment.archive_size));
+            SegmentState::Zlib(decoder.take(segment.original_size.saturating_add(1)))
+        } else {
+            SegmentState::Raw(stream.take(segment.original_size))
+        });
+        self.current = Some(segment);
+        self.copied = 0;
+
+        Ok(true)
+    }
+
+    ///Makes sure that the current segment gave all of its bytes, then gets the stream back
+    fn finish_segment(&mut self) -> Result<(), FlareError> {
+        let stream = match self.state.take() {
+            Some(SegmentState::Raw(reader)) => reader.into_inner(),
+            Some(SegmentState::Zlib(reader)) => reader.into_inner().into_inner().into_inner(),
+            _ => unreachable!("only a started segment can be finished"),
+        };
+        self.state = Some(SegmentState::Between(stream));
+
+        let segment = self.current.take().unwrap();
+        if self.copied != segment.original_size {
+            return if segment.compressed {
+                Err(FlareError::Malformed {
+                    reason: format!("the segment should be {} bytes but was {}",
+                        segment.original_size, self.copied),
+                    offset: segment.start,
+                })
+            } else {
+                Err(FlareError::Truncated {
+                    offset: segment.start + self.copied,
+                })
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl <S: Stream + Read> Read for EntryReader<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        loop {
+            let read = match self.state {
+                Some(SegmentState::Raw(ref mut reader)) => reader.read(buffer),
+                Some(SegmentState::Zlib(ref mut reader)) => reader.read(buffer),
+                _ => {
+                    if !self.next_segment().map_err(Error::other)? {
+                        return Ok(0);
+                    }
+                    continue;
+                },
+            };
+
+            let segment = self.current.as_ref().unwrap();
+            let read = read.map_err(|err| {
+                Error::other(if segment.compressed {
+                    FlareError::Decompression {
+                        offset: segment.start,
+                        source: err,
+                    }
                 } else {
-                    stream.read_exact(segment.original_size as usize).unwrap()
-                };
-
-                //Unencrypt the buffer
-                // for byte in buffer.iter_mut() {
-                //     *byte ^= i.file_hash as u8;
-                // }
-                // Seek to the offset specified in the file before we start writing
-                file.seek(SeekFrom::Start(segment.offset)).unwrap();
-                file.write_all(&buffer).unwrap();
+                    FlareError::from_read(err, segment.start + self.copied)
+                })
+            })?;
+            if read == 0 {
+                self.finish_segment().map_err(Error::other)?;
+                continue;
             }
+
+            //Unencrypt the piece
+            let piece = &mut buffer[..read];
+            if let Some(ref crypt) = self.crypt {
+                crypt.decrypt(piece, segment.offset + self.copied, self.file_hash);
+            }
+            self.copied += read as u64;
+
+            return Ok(read);
         }
     }
 }
 
+///Finds the start of the archive and leaves the stream right after the header
+///This is the same as find_start_offset() but it's an error to not find the header
+fn find_header<S: Stream>(stream: &mut S) -> Result<u64, FlareError> {
+    match find_start_offset(stream) {
+        Some(start_offset) => Ok(start_offset),
+        None => Err(FlareError::Malformed {
+            reason: String::from("couldn't find the XP3 header"),
+            offset: 0,
+        }),
+    }
+}
+
+///Reads every item from all of the entries in the index
+///The stream needs to be right after the header
+///Archives with hashed names get their real names from their eliF chunks or the name list
+fn read_index<S: Stream>(stream: &mut S, start_offset: u64, name_list: &NameList)
+-> Result<Vec<ArchiveItem>, FlareError> {
+    let mut items: Vec<ArchiveItem> = Vec::new();
+    //The real names by the adlr hash of the item that they belong to
+    let mut names: BTreeMap<u32, String> = BTreeMap::new();
+    //The Hxv4 name tables can only be read once the stream isn't holding an entry
+    let mut name_tables: Vec<(u64, u32, u16)> = Vec::new();
+
+    loop {
+        let (entry_data, entry_flag) = find_entry_data(stream, start_offset)?;
+        let mut entry_data = SliceStream::new(&entry_data[..], true);
+
+        //The names can come before or after the file chunks, and unknown chunks are skipped
+        while let Some(mut chunk) = find_chunk(&mut entry_data) {
+            match chunk {
+                Chunk::File(ref mut file_data) => {
+                    items.push(ArchiveItem::new(file_data, start_offset)?);
+                },
+                Chunk::Names(ref mut names_data) => read_names(names_data, &mut names)?,
+                Chunk::NameTable(ref mut table_data) => {
+                    let offset = read_value::<u64, _>(table_data)?.wrapping_add(start_offset);
+                    let size = read_value::<u32, _>(table_data)?;
+                    let flags = read_value::<u16, _>(table_data)?;
+                    name_tables.push((offset, size, flags));
+                },
+                _ => (),
+            }
+        }
+
+        if entry_flag & CONTINUE_MASK == 0 {
+            break;
+        }
+    }
+
+    for (offset, size, flags) in name_tables {
+        stream.seek(SeekFrom::Start(offset))?;
+        let table = read_bytes(stream, size as u64)?;
+        let table = if flags & ENCODING_MASK as u16 == 1 {
+            Cow::Owned(decompress(&table, offset)?)
+        } else if flags & ENCODING_MASK as u16 == 0 {
+            table
+        } else {
+            return Err(FlareError::BadFlag {
+                what: "name table",
+                flag: flags as u64,
+                offset,
+            });
+        };
+
+        let mut table = SliceStream::new(&table[..], true);
+        while let Some(mut chunk) = find_chunk(&mut table) {
+            if let Chunk::Names(ref mut names_data) = chunk {
+                read_names(names_data, &mut names)?;
+            }
+        }
+    }
+
+    resolve_names(&mut items, &names, name_list);
+    Ok(items)
+}
+
+///Reads every adlr hash and name pair inside of an eliF chunk
+fn read_names(names_data: &mut InMemoryStream, names: &mut BTreeMap<u32, String>)
+-> Result<(), FlareError> {
+    while !names_data.remaining().is_empty() {
+        let file_hash = read_value::<u32, _>(names_data)?;
+        let utf16_len = read_value::<u16, _>(names_data)?;
+        let name_offset = names_data.pos();
+        let name = names_data.read_with_len::<UTF16LE>(utf16_len as usize)
+            .map_err(|err| FlareError::from_read(err, name_offset))?;
+        names.insert(file_hash, name);
+    }
+
+    Ok(())
+}
+
+///Gives every item its real name if the archive or the name list has one
+///Items without a name get one made from their hash, so they don't all end up as the same file
+fn resolve_names(items: &mut [ArchiveItem], names: &BTreeMap<u32, String>, name_list: &NameList) {
+    let mut used: BTreeSet<String> = items.iter().map(|item| item.name.clone()).collect();
+    for item in items.iter_mut() {
+        let file_hash = format!("{:08x}", item.file_hash);
+        let real_name = names.get(&item.file_hash).map(String::as_str)
+            .or_else(|| name_list.get(&item.name))
+            .or_else(|| name_list.get(&file_hash))
+            .map(String::from);
+
+        let name = match real_name {
+            Some(name) => name,
+            None if item.name.is_empty() => format!("unnamed/{}", file_hash),
+            None => continue,
+        };
+
+        //Items with the same contents have the same hash, so they're numbered after the first one
+        // in the order of the index
+        let name = unique_name(name, &used);
+        used.insert(name.clone());
+        item.stored_name = Some(mem::replace(&mut item.name, name));
+    }
+}
+
+///Adds _1, _2 and so on before the extension until the name isn't one that's used
+fn unique_name(name: String, used: &BTreeSet<String>) -> String {
+    if !used.contains(&name) {
+        return name;
+    }
+
+    let file_start = name.rfind('/').map_or(0, |slash| slash + 1);
+    let split = name[file_start..].rfind('.').map_or(name.len(), |dot| file_start + dot);
+    (1..).map(|copy| format!("{}_{}{}", &name[..split], copy, &name[split..]))
+        .find(|name| !used.contains(name))
+        .unwrap()
+}
+
 ///Finds the start of the XP3 Archive and returns the offset
 ///An XP3 archive can be after a Win32 exe container in the same file
-fn find_start_offset<R: Read + Seek>(stream: &mut ReadStream<R>) -> Option<u64> {
+fn find_start_offset<S: Stream>(stream: &mut S) -> Option<u64> {
     // Make sure that the stream is set correctly
     stream.little_endian(true);
     stream.seek(SeekFrom::Start(0)).unwrap();
 
     //Try to read the header right from the start
-    let mut header_buffer = match stream.read_exact(11) {
-        Ok(x) => x,
-        Err(_) => return None,
-    };
+    let mut header_buffer = [0; 11];
+    if stream.read_into(&mut header_buffer).is_err() {
+        return None;
+    }
 
     //See if the file is an XP3 file
     //Also see if it's a WIN32 exe file because an XP3 payload may be hidden within;
     // starts with "MZ"
-    //The header must start on a 16 byte boundary
+    //Only the overlay after everything that the exe loads can hold the archive, so the code is
+    // never searched. The header must start on a 16 byte boundary
     if header_buffer[0] == 0x4d && header_buffer[1] == 0x5a {
-        //Seek to an even 16 byte boundary
-        stream.seek(SeekFrom::Current(5)).unwrap();
-        let mut offset = 16_u64;
-        while let Ok(_) = stream.read_into(&mut header_buffer) {
+        //If the headers don't make sense, the whole file is searched like any other
+        let mut offset = pe::overlay_start(stream).unwrap_or(16).next_multiple_of(16);
+        stream.seek(SeekFrom::Start(offset)).ok()?;
+        while stream.read_into(&mut header_buffer).is_ok() {
             if header_buffer == HEADER {
                 return Some(offset);
             }
             offset += 16;
-            stream.seek(SeekFrom::Current(5)).unwrap();
+            stream.skip(5).ok()?;
         }
 
-        //If we got this far, it means we went through the entire file and couldn't find the header
+        //If we got this far, it means we went through the entire overlay and couldn't find the header
         return None;
     } else if header_buffer != HEADER {
         return None;
@@ -214,43 +686,63 @@ fn find_start_offset<R: Read + Seek>(stream: &mut ReadStream<R>) -> Option<u64>
 
 ///Finds and returns the next entry data with the associated entry flag
 ///This function assumes that the stream is at the start of an entry
-fn find_entry_data<R: Read + Seek>(stream: &mut ReadStream<R>, start_offset: u64)
--> (InMemoryStream, u8) {
+///Raw entries are given without being copied if the stream already has them in memory
+fn find_entry_data<S: Stream>(stream: &mut S, start_offset: u64)
+-> Result<(Cow<'_, [u8]>, u8), FlareError> {
     //The entry offset may be required to overflow if the header is not at the beginning of a file
-    let entry_offset = stream.read::<u64>().unwrap().wrapping_add(start_offset);
-    stream.seek(SeekFrom::Start(entry_offset)).unwrap();
+    let entry_offset = read_value::<u64, _>(stream)?.wrapping_add(start_offset);
+    stream.seek(SeekFrom::Start(entry_offset))?;
 
-    let entry_flag = stream.read::<u8>().unwrap();
+    let entry_flag = read_value::<u8, _>(stream)?;
     let entry_data = if entry_flag & ENCODING_MASK == 1 {
-        let enc_size = stream.read::<u64>().unwrap();
-        let real_size = stream.read::<u64>().unwrap();
-
-        let compressed = stream.read_exact(enc_size as usize).unwrap();
-
-        let mut decompressor = ZlibDecoder::new(Vec::new());
-        decompressor.write_all(&compressed).unwrap();
-        let entry_data = decompressor.finish().unwrap();
-
-        debug_assert_eq!(entry_data.len(), real_size as usize);
-        entry_data
+        let enc_size = read_value::<u64, _>(stream)?;
+        let real_size = read_value::<u64, _>(stream)?;
+
+        let compressed = read_bytes(stream, enc_size)?;
+        let entry_data = decompress(&compressed, entry_offset)?;
+        drop(compressed);
+
+        if entry_data.len() as u64 != real_size {
+            return Err(FlareError::Malformed {
+                reason: format!("the index should be {} bytes but was {}", real_size,
+                    entry_data.len()),
+                offset: entry_offset,
+            });
+        }
+        Cow::Owned(entry_data)
     } else if entry_flag & ENCODING_MASK == 0 {
-        let index_size = stream.read::<u64>().unwrap();
-        stream.read_exact(index_size as usize).unwrap()
+        let index_size = read_value::<u64, _>(stream)?;
+        read_bytes(stream, index_size)?
     } else {
-        panic!("Bad flag in entry at 0x{:x}", entry_offset);
+        return Err(FlareError::BadFlag {
+            what: "entry",
+            flag: entry_flag as u64,
+            offset: entry_offset,
+        });
     };
 
-    (ReadStream::new(Cursor::new(entry_data), true), entry_flag)
+    Ok((entry_data, entry_flag))
+}
+
+///Decompresses the zlib data that was read from offset
+fn decompress(compressed: &[u8], offset: u64) -> Result<Vec<u8>, FlareError> {
+    let mut decompressor = ZlibDecoder::new(Vec::new());
+    decompressor.write_all(compressed)
+        .and_then(|_| decompressor.finish())
+        .map_err(|source| FlareError::Decompression {
+            offset,
+            source,
+        })
 }
 
 ///Returns the next chunk type with the stream positioned to start reading its data
-fn find_chunk<R: Read + Seek>(stream: &mut ReadStream<R>) -> Option<Chunk> {
+fn find_chunk<'a>(stream: &mut InMemoryStream<'a>) -> Option<Chunk<'a>> {
     //Read the name of the chunk
-    let name = match stream.read_exact(4) {
+    let name = match stream.read_slice(4) {
         Ok(x) => x,
         Err(_) => return None,
     };
-    let real_size = match stream.read::<u64>() {
+    let real_size = match Stream::read::<u64>(stream) {
         Ok(x) => x,
         Err(_) => return None,
     };
@@ -262,64 +754,88 @@ fn find_chunk<R: Read + Seek>(stream: &mut ReadStream<R>) -> Option<Chunk> {
 #[derive(Debug)]
 struct ArchiveItem {
     name: String,
+    //The hash that was stored in place of the name, if the name came from somewhere else
+    stored_name: Option<String>,
+    //The FILETIME from the time chunk
+    time: Option<u64>,
+    //Every sub-chunk that isn't known, by its name
+    chunks: Vec<([u8; 4], Vec<u8>)>,
     file_hash: u32,
     original_size: u64,
     archive_size: u64,
+    protected: bool,
     segments: Vec<ArchiveSegment>,
+    //Anything odd about the item that could still be read around
+    warnings: Vec<String>,
 }
 
 impl ArchiveItem {
-    fn new<R: Read + Seek>(file_data: &mut ReadStream<R>, start_offset: u64) -> ArchiveItem {
+    fn new(file_data: &mut InMemoryStream, start_offset: u64)
+    -> Result<ArchiveItem, FlareError> {
         let mut item = ArchiveItem {
             name: String::new(),
+            stored_name: None,
+            time: None,
+            chunks: Vec::new(),
             file_hash: 0,
             original_size: 0,
             archive_size: 0,
+            protected: false,
             segments: Vec::new(),
+            warnings: Vec::new(),
         };
-        
-        //We have a max of 3 sub-chunks that can be found
-        for _ in 0..3 {
-            if let Some(mut chunk) = find_chunk(file_data) {
-                match chunk {
-                    Chunk::Info(ref mut info_data) => {
-                        item.read_info(info_data);
-                    },
-                    Chunk::Segment(ref mut segm_data) => {
-                        item.segments.append(
-                            &mut ArchiveSegment::find_all(segm_data, start_offset)
-                        );
 
-                        //Sort all of the segments so that the offset of the first segment always starts at 0
-                        item.segments.sort();
-                    },
-                    Chunk::Adlr(ref mut adlr_data) => {
-                        item.file_hash = adlr_data.read::<u32>().unwrap();
-                    },
-                    Chunk::File(_) => panic!("A file chunk cannot be within another file chunk"),
-                }
-            } else {
-                //Just break if we get None and move onto the next
-                break;
-            }    
+        //Besides info, segm and adlr there can be optional sub-chunks in any order
+        while let Some(mut chunk) = find_chunk(file_data) {
+            match chunk {
+                Chunk::Info(ref mut info_data) => {
+                    item.read_info(info_data)?;
+                },
+                Chunk::Segment(ref mut segm_data) => {
+                    if !segm_data.len().is_multiple_of(28) {
+                        item.warnings.push(String::from(
+                            "the segment chunk isn't divisible by 28 bytes"));
+                    }
+                    item.segments.append(
+                        &mut ArchiveSegment::find_all(segm_data, start_offset)?
+                    );
+
+                    //Sort all of the segments so that the offset of the first segment always starts at 0
+                    item.segments.sort();
+                },
+                Chunk::Adlr(ref mut adlr_data) => {
+                    item.file_hash = read_value::<u32, _>(adlr_data)?;
+                },
+                Chunk::Time(ref mut time_data) => {
+                    item.time = Some(read_value::<u64, _>(time_data)?);
+                },
+                Chunk::File(_) => return Err(FlareError::Malformed {
+                    reason: String::from("a file chunk cannot be within another file chunk"),
+                    offset: file_data.pos(),
+                }),
+                //Names only belong to the index itself
+                Chunk::Names(_) | Chunk::NameTable(_) => (),
+                Chunk::Unknown(name, ref unknown_data) => {
+                    item.chunks.push((name, unknown_data.remaining().to_vec()));
+                },
+            }
         }
 
-        item
+        Ok(item)
     }
 
-    fn read_info<R>(&mut self, info_data: &mut ReadStream<R>)
-     where R: Read + Seek {
-        let item_flags = info_data.read::<u32>().unwrap();
-        if item_flags & PROTECTED_MASK == 1 {
-            eprintln!("The current index is protected");
-        }
+    fn read_info<S: Stream>(&mut self, info_data: &mut S) -> Result<(), FlareError> {
+        let item_flags = read_value::<u32, _>(info_data)?;
+        self.protected = item_flags & PROTECTED_MASK != 0;
 
-        self.original_size = info_data.read::<u64>().unwrap();
-        self.archive_size = info_data.read::<u64>().unwrap();
+        self.original_size = read_value::<u64, _>(info_data)?;
+        self.archive_size = read_value::<u64, _>(info_data)?;
 
         //Read the UTF-16 name
-        let utf16_len = info_data.read::<u16>().unwrap();
-        self.name = info_data.read_with_len::<UTF16LE>(utf16_len as usize).unwrap();
+        let utf16_len = read_value::<u16, _>(info_data)?;
+        let name_offset = info_data.pos();
+        self.name = info_data.read_with_len::<UTF16LE>(utf16_len as usize)
+            .map_err(|err| FlareError::from_read(err, name_offset))?;
         //We need to shorten the path name if it's longer than 255 bytes
         if self.name.len() > 255 {
             //Find all of the character boundaries
@@ -343,18 +859,22 @@ impl ArchiveItem {
             //Create slices that go from the beginning up to the 126th byte, then the last 126 bytes
             // after adding an elipsis
             let mut new_name = String::from(&self.name[..bounds[first_split_index]]);
-            new_name.push_str(&"...");
+            new_name.push_str("...");
             new_name.push_str(&self.name[bounds[second_split_index]..]);
 
             self.name = new_name;
         }
+
+        Ok(())
     }
 }
 
 impl Ord for ArchiveItem {
     fn cmp(&self, other: &ArchiveItem) -> Ordering {
         // self.name.cmp(&other.name)
-        self.segments[0].start.cmp(&other.segments[0].start)
+        //An item without any segments is just an empty file, so it can go anywhere
+        let start = self.segments.first().map(|segment| segment.start);
+        start.cmp(&other.segments.first().map(|segment| segment.start))
     }
 }
 
@@ -372,7 +892,7 @@ impl PartialEq for ArchiveItem {
 
 impl Eq for ArchiveItem {}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ArchiveSegment {
     start: u64,
 	offset: u64, //This is offset in the new file
@@ -382,16 +902,13 @@ struct ArchiveSegment {
 }
 
 impl ArchiveSegment {
-    fn find_all<R>(segm_data: &mut ReadStream<R>, start_offset: u64) -> Vec<ArchiveSegment>
-     where R: Read + Seek {
-        let segm_size = segm_data.len();
-        if segm_size % 28 != 0 {
-            eprintln!("The segment isn't divisable by 28 bytes");
-        }
-        let count = segm_size / 28;
+    fn find_all<S: Stream>(segm_data: &mut S, start_offset: u64)
+    -> Result<Vec<ArchiveSegment>, FlareError> {
+        let count = segm_data.len() / 28;
         let mut offset_in_archive: u64 = 0;
-        (0..count).map(|i| {
-            let flags = segm_data.read::<u32>().unwrap();
+        (0..count).map(|_| {
+            let flags_offset = segm_data.pos();
+            let flags = read_value::<u32, _>(segm_data)?;
 
             // Since the mask is 0b111, other values besides 0 or 1 could possibly appear
             let compressed = if flags & (ENCODING_MASK as u32) == 1 {
@@ -399,23 +916,27 @@ impl ArchiveSegment {
             } else if flags & (ENCODING_MASK as u32) == 0 {
                 false
             } else {
-                panic!("Bad flag in segment {}", i);
+                return Err(FlareError::BadFlag {
+                    what: "segment",
+                    flag: flags as u64,
+                    offset: flags_offset,
+                });
             };
 
-            let start = segm_data.read::<u64>().unwrap() + start_offset;
+            let start = read_value::<u64, _>(segm_data)?.wrapping_add(start_offset);
             let offset = offset_in_archive;
-            let original_size = segm_data.read::<u64>().unwrap();
-            let archive_size = segm_data.read::<u64>().unwrap();
+            let original_size = read_value::<u64, _>(segm_data)?;
+            let archive_size = read_value::<u64, _>(segm_data)?;
 
-            offset_in_archive += original_size;
+            offset_in_archive = offset_in_archive.wrapping_add(original_size);
 
-            ArchiveSegment {
+            Ok(ArchiveSegment {
                 start,
                 offset,
                 original_size,
                 archive_size,
                 compressed,
-            }
+            })
         }).collect()
     }
 }
@@ -441,32 +962,44 @@ impl PartialEq for ArchiveSegment {
 impl Eq for ArchiveSegment {}
 
 //Chunk Names
-const FILE_CHUNK: [u8; 4] = [0x46, 0x69, 0x6c, 0x65]; //"File"
-const INFO_CHUNK: [u8; 4] = [0x69, 0x6e, 0x66, 0x6f]; //"info"
-const SEGM_CHUNK: [u8; 4] = [0x73, 0x65, 0x67, 0x6d]; //"segm"
-const ADLR_CHUNK: [u8; 4] = [0x61, 0x64, 0x6c, 0x72]; //"adlr"
-
-enum Chunk {
-    File(InMemoryStream),
-    Info(InMemoryStream),
-    Segment(InMemoryStream),
-    Adlr(InMemoryStream),
+pub const FILE_CHUNK: [u8; 4] = [0x46, 0x69, 0x6c, 0x65]; //"File"
+pub const INFO_CHUNK: [u8; 4] = [0x69, 0x6e, 0x66, 0x6f]; //"info"
+pub const SEGM_CHUNK: [u8; 4] = [0x73, 0x65, 0x67, 0x6d]; //"segm"
+pub const ADLR_CHUNK: [u8; 4] = [0x61, 0x64, 0x6c, 0x72]; //"adlr"
+pub const TIME_CHUNK: [u8; 4] = [0x74, 0x69, 0x6d, 0x65]; //"time"
+//The real names for archives that only store hashes in place of names
+pub const ELIF_CHUNK: [u8; 4] = [0x65, 0x6c, 0x69, 0x46]; //"eliF"
+//Points to a separate table of eliF chunks
+pub const HXV4_CHUNK: [u8; 4] = [0x48, 0x78, 0x76, 0x34]; //"Hxv4"
+
+enum Chunk<'a> {
+    File(InMemoryStream<'a>),
+    Info(InMemoryStream<'a>),
+    Segment(InMemoryStream<'a>),
+    Adlr(InMemoryStream<'a>),
+    Time(InMemoryStream<'a>),
+    Names(InMemoryStream<'a>),
+    NameTable(InMemoryStream<'a>),
+    //Any other chunk with its name
+    Unknown([u8; 4], InMemoryStream<'a>),
 }
 
-impl Chunk {
+impl <'a> Chunk<'a> {
     //Tries to guess the type of the chunk
-    fn guess<R>(stream: &mut ReadStream<R>, name: [u8; 4], size: u64) -> Option<Chunk>
-    where R: Read + Seek {
+    fn guess(stream: &mut InMemoryStream<'a>, name: [u8; 4], size: u64) -> Option<Chunk<'a>> {
         //Try to create the stream first as the order for this doesn't matter and doing this first
         // makes the code cleaner
-        //If either the name checking or the stream fails, we need to return None, anyway
+        //Only the stream failing gives None, since unknown chunks still have to be skipped
         if let Some(stream) = create_stream(stream, size) {
             match name {
                 FILE_CHUNK => Some(Chunk::File(stream)),
                 INFO_CHUNK => Some(Chunk::Info(stream)),
                 SEGM_CHUNK => Some(Chunk::Segment(stream)),
                 ADLR_CHUNK => Some(Chunk::Adlr(stream)),
-                _ => None,
+                TIME_CHUNK => Some(Chunk::Time(stream)),
+                ELIF_CHUNK => Some(Chunk::Names(stream)),
+                HXV4_CHUNK => Some(Chunk::NameTable(stream)),
+                _ => Some(Chunk::Unknown(name, stream)),
             }
         } else {
             None
@@ -474,13 +1007,30 @@ impl Chunk {
     }
 }
 
-///Creates a stream from the given stream from the next size bytes
-fn create_stream<R>(stream: &mut ReadStream<R>, size: u64) -> Option<InMemoryStream>
-where R: Read + Seek {
-    let buffer = match stream.read_exact(size as usize) {
-        Ok(x) => x,
-        Err(_) => return None,
-    };
+///Turns the FILETIME from a time chunk into the time it stands for
+///Gives None if the time can't be held by a SystemTime
+pub fn from_filetime(ticks: u64) -> Option<SystemTime> {
+    let since_epoch = |ticks: u64| Duration::new(ticks / TICKS_PER_SECOND,
+        (ticks % TICKS_PER_SECOND * 100) as u32);
+    if ticks >= UNIX_EPOCH_TICKS {
+        UNIX_EPOCH.checked_add(since_epoch(ticks - UNIX_EPOCH_TICKS))
+    } else {
+        UNIX_EPOCH.checked_sub(since_epoch(UNIX_EPOCH_TICKS - ticks))
+    }
+}
 
-    Some(ReadStream::new(Cursor::new(buffer), true))
+///Turns the time into a FILETIME for a time chunk
+pub fn to_filetime(time: SystemTime) -> u64 {
+    let ticks = |duration: Duration| duration.as_secs().saturating_mul(TICKS_PER_SECOND)
+        .saturating_add(duration.subsec_nanos() as u64 / 100);
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => UNIX_EPOCH_TICKS.saturating_add(ticks(after)),
+        Err(before) => UNIX_EPOCH_TICKS.saturating_sub(ticks(before.duration())),
+    }
+}
+
+///Creates a stream from the given stream from the next size bytes
+///The new stream shares the same bytes, so nothing gets copied
+fn create_stream<'a>(stream: &mut InMemoryStream<'a>, size: u64) -> Option<InMemoryStream<'a>> {
+    stream.sub_stream(size as usize).ok()
 }
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap};
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Result as IOResult, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+use std::time::{SystemTime};
+
+use flate2::{Compression};
+use flate2::write::{ZlibEncoder};
+use serde_json;
+
+
+use super::xp3::{self, ADLR_CHUNK, FILE_CHUNK, HEADER, INFO_CHUNK, SEGM_CHUNK, TIME_CHUNK};
+use checksum;
+use file_utils;
+
+//The entry flag for a zlib compressed index. The continue bit is never set since we only write 1
+const ZLIB_INDEX_FLAG: u8 = 1;
+
+//The segment flags for the two kinds of storage
+const RAW_SEGMENT_FLAG: u32 = 0;
+const ZLIB_SEGMENT_FLAG: u32 = 1;
+
+///Builds a new XP3 archive that XP3Archive can read back
+///
+///The header is written right away, then each added file's data is appended as a single segment.
+///The index is only written once finish() is called.
+pub struct XP3Writer<W: Write + Seek> {
+    out: W,
+    ///Where the header was written. All offsets in the archive are relative to this
+    base_offset: u64,
+    items: Vec<WriterItem>,
+}
+
+impl <W: Write + Seek> XP3Writer<W> {
+    ///Starts a new archive at the current position of out
+    pub fn new(mut out: W) -> IOResult<XP3Writer<W>> {
+        let base_offset = out.stream_position()?;
+        out.write_all(HEADER)?;
+        //The index offset isn't known until all of the files have been written
+        out.write_all(&[0; 8])?;
+
+        Ok(XP3Writer {
+            out,
+            base_offset,
+            items: Vec::new(),
+        })
+    }
+
+    ///Adds a file to the archive with the given name
+    ///The data is zlib compressed if that makes it smaller, otherwise it's stored raw
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> IOResult<()> {
+        self.add_file_with(name, data, &XP3ItemChunks::default())
+    }
+
+    ///Adds a file to the archive the same as add_file(), along with its optional sub-chunks
+    pub fn add_file_with(&mut self, name: &str, data: &[u8], chunks: &XP3ItemChunks)
+    -> IOResult<()> {
+        //The length of the name is kept in a u16
+        if name.encode_utf16().count() > u16::MAX as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "the name {}... is longer than {} UTF-16 code units",
+                name.chars().take(32).collect::<String>(), u16::MAX)));
+        }
+        let start = self.out.stream_position()? - self.base_offset;
+
+        let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressor.write_all(data)?;
+        let compressed = compressor.finish()?;
+
+        let segment = if compressed.len() < data.len() {
+            self.out.write_all(&compressed)?;
+            WriterSegment {
+                start,
+                original_size: data.len() as u64,
+                archive_size: compressed.len() as u64,
+                compressed: true,
+            }
+        } else {
+            self.out.write_all(data)?;
+            WriterSegment {
+                start,
+                original_size: data.len() as u64,
+                archive_size: data.len() as u64,
+                compressed: false,
+            }
+        };
+
+        self.items.push(WriterItem {
+            name: String::from(name),
+            file_hash: checksum::adler32(data),
+            segments: vec![segment],
+            time: chunks.modified.map(xp3::to_filetime),
+            raw_chunks: chunks.raw.clone(),
+        });
+
+        Ok(())
+    }
+
+    ///Writes out the index and points the header at it
+    ///Gives back the inner writer positioned at the end of the archive
+    pub fn finish(mut self) -> IOResult<W> {
+        let mut index = Vec::new();
+        for item in &self.items {
+            item.write_chunk(&mut index);
+        }
+
+        let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressor.write_all(&index)?;
+        let compressed = compressor.finish()?;
+
+        let index_offset = self.out.stream_position()? - self.base_offset;
+        self.out.write_all(&[ZLIB_INDEX_FLAG])?;
+        self.out.write_all(&(compressed.len() as u64).to_le_bytes())?;
+        self.out.write_all(&(index.len() as u64).to_le_bytes())?;
+        self.out.write_all(&compressed)?;
+        let end = self.out.stream_position()?;
+
+        //Now we can fill in the index offset right after the header
+        self.out.seek(SeekFrom::Start(self.base_offset + HEADER.len() as u64))?;
+        self.out.write_all(&index_offset.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+
+        Ok(self.out)
+    }
+}
+
+///The optional sub-chunks that an item can have besides the ones every item needs
+#[derive(Debug, Clone, Default)]
+pub struct XP3ItemChunks {
+    ///When the file was last changed, written as the time chunk
+    pub modified: Option<SystemTime>,
+    ///The chunks that aren't understood by their names, written back as they are
+    pub raw: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl XP3ItemChunks {
+    ///Gets the sub-chunks back from the attributes of a flared XP3 item
+    ///Attributes that aren't from a sub-chunk are skipped, but a chunk that can't be read is an
+    /// error so that it's never lost without anyone knowing
+    pub fn from_attributes(attributes: &BTreeMap<String, String>) -> IOResult<XP3ItemChunks> {
+        let mut chunks = XP3ItemChunks {
+            modified: attributes.get("time")
+                .and_then(|time| time.parse().ok())
+                .and_then(xp3::from_filetime),
+            raw: Vec::new(),
+        };
+
+        for (key, value) in attributes {
+            let name = match key.strip_prefix("chunk:") {
+                Some(name) => name,
+                None => continue,
+            };
+            //Names that weren't printable are 8 hex digits instead of 4 bytes
+            let name = match name.len() {
+                4 => {
+                    let name = name.as_bytes();
+                    Some([name[0], name[1], name[2], name[3]])
+                },
+                10 => name.strip_prefix("0x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .map(u32::to_be_bytes),
+                _ => None,
+            };
+            let data: Option<Vec<u8>> = (0..value.len()).step_by(2)
+                .map(|start| value.get(start..start + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect();
+            match (name, data) {
+                (Some(name), Some(data)) => chunks.raw.push((name, data)),
+                _ => return Err(Error::new(ErrorKind::InvalidData, format!(
+                    "the {} attribute isn't a chunk name with its data in hex", key))),
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+///Packs every file inside of folder (recursively) into a new XP3 archive at archive_path
+///The names in the archive are relative to folder and always use '/' as the separator
+///Each file's modified time is kept in its time chunk, and the unknown chunks that were saved
+/// in the folder when it was flared are written back
+///Returns the number of files that were packed
+pub fn pack_folder(folder: &Path, archive_path: &Path) -> IOResult<usize> {
+    let chunks_path = folder.join(xp3::CHUNKS_NAME);
+    let saved_chunks: BTreeMap<String, BTreeMap<String, String>> = if chunks_path.is_file() {
+        serde_json::from_slice(&fs::read(&chunks_path)?)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!(
+                "{} couldn't be read: {}", chunks_path.display(), err)))?
+    } else {
+        BTreeMap::new()
+    };
+
+    let mut files: Vec<_> = file_utils::all_files(folder, true)?.into_iter()
+        .filter(|file| file.is_file() && *file != chunks_path)
+        .collect();
+    //Sort so that the same folder always gives the same archive
+    files.sort();
+
+    let mut writer = XP3Writer::new(file_utils::make_file(archive_path)?)?;
+    for file in &files {
+        let name = file.strip_prefix(folder).unwrap()
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+
+        let mut chunks = match saved_chunks.get(&name) {
+            Some(attributes) => XP3ItemChunks::from_attributes(attributes)?,
+            None => XP3ItemChunks::default(),
+        };
+        chunks.modified = file.metadata()?.modified().ok();
+        writer.add_file_with(&name, &fs::read(file)?, &chunks)?;
+    }
+    let mut archive: File = writer.finish()?;
+    archive.flush()?;
+
+    Ok(files.len())
+}
+
+struct WriterItem {
+    name: String,
+    file_hash: u32,
+    segments: Vec<WriterSegment>,
+    time: Option<u64>,
+    raw_chunks: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl WriterItem {
+    ///Writes the File chunk, along with all of its sub-chunks
+    fn write_chunk(&self, index: &mut Vec<u8>) {
+        let original_size: u64 = self.segments.iter().map(|segment| segment.original_size).sum();
+        let archive_size: u64 = self.segments.iter().map(|segment| segment.archive_size).sum();
+
+        let mut info = Vec::new();
+        //No flags since we never protect anything
+        info.extend_from_slice(&0_u32.to_le_bytes());
+        info.extend_from_slice(&original_size.to_le_bytes());
+        info.extend_from_slice(&archive_size.to_le_bytes());
+        let utf16: Vec<u16> = self.name.encode_utf16().collect();
+        info.extend_from_slice(&(utf16.len() as u16).to_le_bytes());
+        for code_point in utf16 {
+            info.extend_from_slice(&code_point.to_le_bytes());
+        }
+
+        let mut segm = Vec::new();
+        for segment in &self.segments {
+            let flag = if segment.compressed { ZLIB_SEGMENT_FLAG } else { RAW_SEGMENT_FLAG };
+            segm.extend_from_slice(&flag.to_le_bytes());
+            segm.extend_from_slice(&segment.start.to_le_bytes());
+            segm.extend_from_slice(&segment.original_size.to_le_bytes());
+            segm.extend_from_slice(&segment.archive_size.to_le_bytes());
+        }
+
+        let mut file = Vec::new();
+        write_chunk(&mut file, INFO_CHUNK, &info);
+        write_chunk(&mut file, SEGM_CHUNK, &segm);
+        write_chunk(&mut file, ADLR_CHUNK, &self.file_hash.to_le_bytes());
+        if let Some(time) = self.time {
+            write_chunk(&mut file, TIME_CHUNK, &time.to_le_bytes());
+        }
+        for (name, data) in &self.raw_chunks {
+            write_chunk(&mut file, *name, data);
+        }
+
+        write_chunk(index, FILE_CHUNK, &file);
+    }
+}
+
+struct WriterSegment {
+    start: u64,
+    original_size: u64,
+    archive_size: u64,
+    compressed: bool,
+}
+
+///Writes a chunk with its name and size in front of the data
+fn write_chunk(out: &mut Vec<u8>, name: [u8; 4], data: &[u8]) {
+    out.extend_from_slice(&name);
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    out.extend_from_slice(data);
+}
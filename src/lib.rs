@@ -0,0 +1,42 @@
+//!Flares files into all of the files that are hidden inside of them
+//!
+//!The formats can be detected, listed, read one item at a time or flared into a folder. The
+//!binaryflare executable is a command line interface over this library.
+extern crate flate2;
+extern crate glob;
+extern crate memmap2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate sha2;
+
+mod archive;
+mod checksum;
+pub mod file_utils;
+pub mod formats;
+pub mod sink;
+pub mod stream;
+
+pub use archive::{Archive};
+pub use file_utils::{SymlinkPolicy};
+pub use formats::{
+    carve,
+    guess_format,
+    identify,
+    Candidate,
+    CarveHit,
+    ChecksumPolicy,
+    FlareError,
+    FlareOptions,
+    FlareReport,
+    FlaredEntry,
+    Format,
+    ListedItem,
+    NameFilter,
+    NameList,
+    OverwritePolicy,
+    TextEncoding,
+    XP3ItemChunks,
+    XP3Writer,
+};
@@ -1,131 +1,727 @@
-extern crate flate2;
+extern crate binaryflare;
 extern crate time;
 extern crate rayon;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
-mod file_utils;
-mod formats;
-pub mod stream;
+mod cli;
+mod report;
 
 use std::collections::{HashMap};
+use std::collections::hash_map::{Entry};
 use std::env;
-use std::fmt::{Write};
-use std::fs::{File};
-use std::io::{Write as IOWrite};
-use std::path::{PathBuf};
+use std::fs;
+use std::io::{self, BufWriter, Result as IOResult, Write as IOWrite};
+use std::path::{Path, PathBuf};
 use std::process::{self};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use rayon::prelude::*;
 
 use time::{SteadyTime};
 
+use binaryflare::{file_utils, formats};
+use binaryflare::{Archive, Candidate, CarveHit, ChecksumPolicy, FlareError, FlareReport, Format, ListedItem};
+use binaryflare::formats::{simple_crypt, MIN_CONFIDENCE};
 
-const OUT_DIR: &'static str = "out";
+use binaryflare::sink::{DirSink, SharedSink, Sink, SpoolSink, TarSink, ZipSink};
+
+use cli::{Cli, Command, ReportFormat, SinkChoice, Verbosity};
+use report::{ErrorReport, InputReport, OutputReport, RunReport};
+
+/// The folder inside of the output where files saved into a tar or zip are kept to be flared again
+/// It's deleted once everything is flared
+const SPOOL_FOLDER: &str = ".spool";
 
 fn main() {
     //The first argument is the executable path, so we can skip that
-    let mut flares: Vec<Flare> = env::args().skip(1).flat_map(|file| {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = match cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("Run binaryflare help to see how it can be used");
+            process::exit(cli::EXIT_USAGE);
+        },
+    };
+
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("Failed to start the threads");
+    }
+
+    let exit_code = match cli.command {
+        Command::Help => {
+            print!("{}", cli::HELP);
+            cli::EXIT_OK
+        },
+        Command::Extract => extract(&cli),
+        Command::List => list(&cli),
+        Command::Identify => identify(&cli),
+        Command::Carve => carve(&cli),
+        Command::Verify => verify(&cli),
+        Command::Pack => pack(&cli),
+        Command::EncryptText => encrypt_text(&cli),
+    };
+    process::exit(exit_code);
+}
+
+/// Flares every file, then keeps flaring the files that come out until nothing new does
+/// Gives the exit code
+fn extract(cli: &Cli) -> i32 {
+    let start_time = SteadyTime::now();
+    let started = time::now_utc();
+    let mut flares: Vec<Flare> = find_input_files(cli).into_iter().map(|(file, parent)| {
+        Flare::new(make_save_path(&cli.output, &file, parent.as_deref()), file, None, 0)
+    }).collect();
+
+    // Every flare that's been done, with the nested ones after the ones they came out of
+    let mut finished: Vec<Flare> = Vec::new();
+    let mut any_supported = false;
+    let mut first_round = true;
+
+    let shared_sink = match open_shared_sink(cli) {
+        Ok(shared_sink) => shared_sink,
+        Err(err) => {
+            eprintln!("Couldn't create the sink due to {}", err);
+            return cli::EXIT_ERRORS;
+        },
+    };
+    let spool_folder = shared_sink.as_ref().map(|_| cli.output.join(SPOOL_FOLDER));
+
+    // The hash of every file that's been flared, with the first file that had it
+    let mut flared_hashes: HashMap<String, PathBuf> = HashMap::new();
+
+    while !flares.is_empty() {
+        flares.par_iter_mut().for_each(|flare| flare.detect(cli));
+
+        // Anything that was already flared gets skipped, which also stops archives that hold
+        // themselves from looping forever
+        // They're sorted so the first one is always the same
+        flares.sort_by(|a, b| a.to_convert.cmp(&b.to_convert));
+        for flare in &mut flares {
+            if let Some(ref hash) = flare.hash {
+                match flared_hashes.entry(hash.clone()) {
+                    Entry::Occupied(entry) => flare.duplicate_of = Some(entry.get().clone()),
+                    Entry::Vacant(entry) => {
+                        entry.insert(flare.to_convert.clone());
+                    },
+                }
+            }
+        }
+
+        // Flare each of our files
+        let mut flared: Vec<Flare> = flares.into_par_iter()
+            .map(|mut flare| {
+                flare.flare(cli, shared_sink.as_ref(), spool_folder.as_deref());
+                flare
+            }).collect();
+
+        if first_round {
+            any_supported = flared.iter().any(|flare| !flare.formats.is_empty());
+            first_round = false;
+        }
+
+        // Get new flares from the ones that we just did, unless they're already deep enough
+        for flare in &mut flared {
+            flare.depth_limited = flare.depth >= cli.depth && !flare.report.entries.is_empty();
+        }
+        let spool_folder = &spool_folder;
+        flares = flared.iter()
+            .filter(|flare| !flare.depth_limited)
+            .flat_map(|flare| flare.report.entries.iter().map(move |entry| {
+                // Create the flare in the nested flared base
+                let mut nested = Flare::new(make_flared_save_path(&entry.path),
+                    entry.path.clone(), Some(flare.to_convert.clone()), flare.depth + 1);
+                // Files that were saved into a tar or zip are read back from their spooled copy
+                nested.spooled = spool_folder.as_ref()
+                    .map(|spool_folder| spool_folder.join(&entry.path));
+                nested
+            })).collect();
+
+        finished.extend(flared);
+    }
+    let mut sink_failed = false;
+    if let Some(ref shared_sink) = shared_sink {
+        if let Err(err) = shared_sink.lock().unwrap_or_else(PoisonError::into_inner).finish() {
+            eprintln!("Couldn't finish the sink due to {}", err);
+            sink_failed = true;
+        }
+    }
+    if let Some(ref spool_folder) = spool_folder {
+        if let Err(err) = fs::remove_dir_all(spool_folder) {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!("Couldn't delete {} due to {}", spool_folder.display(), err);
+            }
+        }
+    }
+
+    // Sort everything so that the results come out the same way every time
+    finished.sort_by(|a, b| a.to_convert.cmp(&b.to_convert));
+
+    let bad_checksum_count: usize = finished.iter()
+        .map(|flare| flare.report.bad_checksums.len())
+        .sum();
+    let renamed_count: usize = finished.iter().map(|flare| flare.report.renamed.len()).sum();
+    let existing_count: usize = finished.iter().map(|flare| flare.report.existing.len()).sum();
+    let error_count = finished.iter()
+        .filter(|flare| !flare.errors.is_empty() || !flare.report.failed.is_empty())
+        .count();
+    let duplicate_count = finished.iter().filter(|flare| flare.duplicate_of.is_some()).count();
+    if bad_checksum_count > 0 {
+        say(cli, Verbosity::Normal, format!("{} files failed their checksum", bad_checksum_count));
+    }
+    if renamed_count > 0 {
+        say(cli, Verbosity::Normal, format!("{} files had to be renamed to be saved safely",
+            renamed_count));
+    }
+    if existing_count > 0 {
+        say(cli, Verbosity::Normal, format!("{} files already existed and were kept",
+            existing_count));
+    }
+    if duplicate_count > 0 {
+        say(cli, Verbosity::Normal, format!("{} files were already flared and were skipped",
+            duplicate_count));
+    }
+    if error_count > 0 {
+        eprintln!("{} files failed to flare", error_count);
+    }
+
+    if cli.report != ReportFormat::None {
+        // Write out all of the results into a file
+        let results_path = cli.report_file.clone().unwrap_or_else(|| {
+            let mut results_path = cli.output.clone();
+            // Get rid of all of the colons so that it's a valid file name
+            results_path.push(format!("{}", time::now().rfc822z()).replace(":", ""));
+            results_path.set_extension(match cli.report {
+                ReportFormat::Json => "json",
+                ReportFormat::JsonLines => "jsonl",
+                _ => "txt",
+            });
+            results_path
+        });
+
+        let results_file = file_utils::make_file(&results_path)
+            .expect("Failed to create the results file");
+        let seconds = ((SteadyTime::now() - start_time).num_milliseconds() as f64) / 1000.0;
+        let written = match cli.report {
+            ReportFormat::Json => run_report(cli, &finished, &started, seconds)
+                .write_json(results_file),
+            ReportFormat::JsonLines => run_report(cli, &finished, &started, seconds)
+                .write_json_lines(results_file),
+            _ => text_report(cli, &finished, results_file),
+        };
+        written.expect("Failed to write the results file");
+    }
+
+    //Bad checksums are only a failure when they were asked to be
+    let failed_checksums = cli.options.bad_checksum == ChecksumPolicy::Fail &&
+        bad_checksum_count > 0;
+    cli::exit_code(failed_checksums, error_count > 0 || sink_failed, any_supported)
+}
+
+/// Writes the results as text for people to read
+fn text_report<W: IOWrite>(cli: &Cli, finished: &[Flare], mut out: W) -> IOResult<()> {
+    for flare in finished.iter().filter(|flare| !flare.report.entries.is_empty()) {
+        writeln!(out, "In: {}", flare.to_convert.display())?;
+        writeln!(out, "Out:")?;
+
+        for entry in &flare.report.entries {
+            writeln!(out, "    {}", entry.path.display())?;
+        }
+
+        writeln!(out, "\n========")?;
+    }
+
+    if finished.iter().any(|flare| !flare.carved.is_empty()) {
+        writeln!(out, "Carved:")?;
+        for flare in finished {
+            for hit in &flare.carved {
+                writeln!(out, "    {} at 0x{:x}: {:?} ({}%)", flare.to_convert.display(),
+                    hit.offset, hit.format, hit.confidence)?;
+            }
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.report.bad_checksums.is_empty()) {
+        writeln!(out, "Bad checksums:")?;
+        for file in finished.iter().flat_map(|flare| &flare.report.bad_checksums) {
+            writeln!(out, "    {}", file.display())?;
+        }
+    }
+
+    if !cli.options.filter.is_empty() {
+        writeln!(out, "Filtered:")?;
+        for flare in finished.iter().filter(|flare| !flare.formats.is_empty()) {
+            writeln!(out, "    {}: {} matched, {} skipped", flare.to_convert.display(),
+                flare.report.matched, flare.report.skipped)?;
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.report.renamed.is_empty()) {
+        writeln!(out, "Renamed:")?;
+        for (name, file) in finished.iter().flat_map(|flare| &flare.report.renamed) {
+            writeln!(out, "    {} -> {}", name, file.display())?;
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.report.existing.is_empty()) {
+        writeln!(out, "Already existed:")?;
+        for file in finished.iter().flat_map(|flare| &flare.report.existing) {
+            writeln!(out, "    {}", file.display())?;
+        }
+    }
+
+    if finished.iter().any(|flare| flare.duplicate_of.is_some()) {
+        writeln!(out, "Duplicates:")?;
+        for flare in finished {
+            if let Some(ref original) = flare.duplicate_of {
+                writeln!(out, "    {} = {}", flare.to_convert.display(), original.display())?;
+            }
+        }
+    }
+
+    if finished.iter().any(|flare| flare.depth_limited) {
+        writeln!(out, "Too deep to flare again:")?;
+        for flare in finished.iter().filter(|flare| flare.depth_limited) {
+            writeln!(out, "    {}", flare.to_convert.display())?;
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.report.warnings.is_empty()) {
+        writeln!(out, "Warnings:")?;
+        for flare in finished {
+            for warning in &flare.report.warnings {
+                writeln!(out, "    {}: {}", flare.to_convert.display(), warning)?;
+            }
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.report.failed.is_empty()) {
+        writeln!(out, "Failed items:")?;
+        for flare in finished {
+            for (name, err) in &flare.report.failed {
+                writeln!(out, "    {}: {}: {}", flare.to_convert.display(), name, err)?;
+            }
+        }
+    }
+
+    if finished.iter().any(|flare| !flare.errors.is_empty()) {
+        writeln!(out, "Errors:")?;
+        for flare in finished {
+            for &(format, ref err) in &flare.errors {
+                match format {
+                    Some(format) => writeln!(out, "    {} ({:?}): {}", flare.to_convert.display(),
+                        format, err)?,
+                    None => writeln!(out, "    {}: {}", flare.to_convert.display(), err)?,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the machine readable report out of every flare
+/// Only the given files and the flared files that were a supported format get their own input
+fn run_report(cli: &Cli, finished: &[Flare], started: &time::Tm, seconds: f64) -> RunReport {
+    let inputs = finished.iter()
+        .filter(|flare| flare.depth == 0 || !flare.formats.is_empty() || !flare.errors.is_empty())
+        .map(|flare| {
+            let mut warnings: Vec<String> = flare.report.warnings.clone();
+            warnings.extend(flare.report.bad_checksums.iter()
+                .map(|file| format!("{} failed its checksum", file.display())));
+            warnings.extend(flare.report.renamed.iter()
+                .map(|(name, file)| format!("{} was saved as {}", name, file.display())));
+            warnings.extend(flare.report.existing.iter()
+                .map(|file| format!("{} already existed and was kept", file.display())));
+            if flare.report.skipped > 0 {
+                warnings.push(format!("{} items were skipped by the filter",
+                    flare.report.skipped));
+            }
+            if flare.depth_limited {
+                warnings.push(String::from("the flared files are too deep to be flared again"));
+            }
+
+            InputReport {
+                path: flare.to_convert.clone(),
+                parent: flare.parent.clone(),
+                children: Vec::new(),
+                depth: flare.depth,
+                sha256: flare.hash.clone(),
+                duplicate_of: flare.duplicate_of.clone(),
+                formats: flare.formats.clone(),
+                carved: flare.carved.clone(),
+                seconds: flare.seconds,
+                outputs: flare.report.entries.iter().filter_map(|entry| {
+                    // Only files in folders can be read back to be hashed
+                    OutputReport::new(entry, cli.sink == SinkChoice::Dir).map_err(|err| {
+                        eprintln!("Couldn't hash {} due to {}", entry.path.display(), err);
+                    }).ok()
+                }).collect(),
+                errors: flare.errors.iter().map(|&(format, ref err)| ErrorReport {
+                    format,
+                    message: err.to_string(),
+                }).chain(flare.report.failed.iter().map(|(name, err)| ErrorReport {
+                    format: None,
+                    message: format!("{}: {}", name, err),
+                })).collect(),
+                warnings,
+            }
+        }).collect();
+
+    let mut report = RunReport {
+        started: started.rfc3339().to_string(),
+        seconds,
+        output: cli.output.clone(),
+        inputs,
+    };
+    report.finish();
+    report
+}
+
+/// Prints the message if the verbosity is at least the given level
+/// Uses stderr when stdout is taken by the sink
+fn say(cli: &Cli, level: Verbosity, message: String) {
+    if cli.verbosity >= level {
+        if cli.sink.is_stdout() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+/// Opens the sink that every flare shares, unless the files go into folders
+fn open_shared_sink(cli: &Cli) -> IOResult<Option<Arc<Mutex<dyn Sink>>>> {
+    let open = |path: &Path| -> IOResult<Box<dyn IOWrite + Send>> {
+        if path == Path::new("-") {
+            Ok(Box::new(io::stdout()))
+        } else {
+            Ok(Box::new(BufWriter::new(file_utils::make_file(path)?)))
+        }
+    };
+
+    Ok(match cli.sink {
+        SinkChoice::Dir => None,
+        SinkChoice::Tar(ref path) => Some(Arc::new(Mutex::new(TarSink::new(open(path)?)))),
+        SinkChoice::Zip(ref path) => Some(Arc::new(Mutex::new(ZipSink::new(open(path)?)))),
+    })
+}
+
+/// Guesses the formats of the file, keeping only the ones that are allowed
+fn allowed_formats(cli: &Cli, file: &Path) -> Result<Vec<Format>, FlareError> {
+    Ok(binaryflare::guess_format(file)?.into_iter()
+        .filter(|&format| cli.allows(format))
+        .collect())
+}
+
+/// Carves the file, keeping only the hits that are allowed
+fn allowed_hits(cli: &Cli, file: &Path) -> Result<Vec<CarveHit>, FlareError> {
+    Ok(binaryflare::carve(file)?.into_iter()
+        .filter(|hit| cli.allows(hit.format))
+        .collect())
+}
+
+/// Finds every file that was given as an argument
+/// Files inside of a given directory are given with that directory
+fn find_input_files(cli: &Cli) -> Vec<(PathBuf, Option<PathBuf>)> {
+    cli.paths.iter().flat_map(|file| {
         let file_path = match PathBuf::from(&file).canonicalize() {
             Ok(path) => path,
             Err(_) => {
-                println!("{} needs to be valid path", file);
-                process::exit(-1);
+                eprintln!("{} needs to be valid path", file);
+                process::exit(cli::EXIT_USAGE);
             },
         };
         // We need to make sure that every file exists
         if !file_path.exists() {
-            println!("{} must exist", file);
-            process::exit(-1);
+            eprintln!("{} must exist", file);
+            process::exit(cli::EXIT_USAGE);
         }
 
         if file_path.is_dir() {
-            // Create a Flare for each file inside the directory
-            file_path.read_dir().unwrap().filter_map(|result| {
-                match result {
-                    Ok(dir_entry) => {
-                        if dir_entry.file_type().unwrap().is_file() {
-                            Some(dir_entry.path())
-                        } else {
-                            None
-                        }
-                    },
-                    Err(err) => {
-                        println!("Couldn't read a file in the directory {} due to {}", file_path.display(), err);
-                        process::exit(-1);
-                    }
-                }
-            }).map(|file| {
-                Flare::new(make_save_path(&file, Some(&file_path)), file)
+            // Take each file inside the directory, and inside of its directories
+            let (files, errors) = file_utils::walk_files(&file_path, Some(cli.recurse), cli.symlinks);
+            for (path, err) in errors {
+                eprintln!("Couldn't read {} due to {}", path.display(), err);
+            }
+            files.into_iter().map(|file| {
+                (file, Some(file_path.clone()))
             }).collect()
         } else {
-            vec![Flare::new(make_save_path(&file_path, None), file_path)]
+            vec![(file_path, None)]
         }
+    }).collect()
+}
+
+/// Prints out every item inside of the given files without flaring anything
+/// Prints JSON instead of a table if --json was given
+fn list(cli: &Cli) -> i32 {
+    let mut failed = false;
+    let mut any_supported = false;
+    let listings: Vec<Listing> = find_input_files(cli).into_iter().flat_map(|(file, _)| {
+        let file_formats = allowed_formats(cli, &file).unwrap_or_else(|err| {
+            eprintln!("Couldn't read {} due to {}", file.display(), err);
+            failed = true;
+            Vec::new()
+        });
+        any_supported |= !file_formats.is_empty();
+
+        file_formats.into_iter().filter_map(|format| {
+            match Archive::open_with(&file, format, cli.options.clone()).entries() {
+                Ok(items) => Some(Listing {
+                    file: file.clone(),
+                    format,
+                    items,
+                }),
+                Err(err) => {
+                    eprintln!("Couldn't list {} as {:?} due to {}", file.display(), format, err);
+                    failed = true;
+                    None
+                },
+            }
+        }).collect::<Vec<Listing>>()
     }).collect();
 
-    if flares.len() == 0 {
-        println!("A file or folder needs to supplied");
-        process::exit(-1);
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&listings)
+            .expect("Failed to turn the listing into JSON"));
+    } else {
+        for listing in listings {
+            println!("{} ({:?}) {} items", listing.file.display(), listing.format,
+                listing.items.len());
+            println!("{:>12} {:>12} {:>8} {:<12} {:<9} {:<8} Name",
+                "Original", "Archived", "Segments", "Compression", "Protected", "Hash");
+            for item in listing.items {
+                let compression = item.compressed_segments.iter()
+                    .map(|&compressed| if compressed { "zlib" } else { "raw" })
+                    .collect::<Vec<&str>>()
+                    .join(",");
+                let hash = match item.hash {
+                    Some(hash) => format!("{:08x}", hash),
+                    None => String::from("-"),
+                };
+                println!("{:>12} {:>12} {:>8} {:<12} {:<9} {:<8} {}", item.original_size,
+                    item.archive_size, item.compressed_segments.len(), compression, item.protected,
+                    hash, item.name);
+            }
+            println!();
+        }
     }
 
-    // Keep track of the results of the flaring
-    let mut results: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
-    
-    while flares.len() > 0 {
-        // Flare each of our files
-        let mut new_results: HashMap<PathBuf, Vec<PathBuf>> = flares.into_par_iter()
-            .filter_map(|mut flare| {
-                flare.flare();
-                let (to_convert, converted_files) = flare.results();
-                if converted_files.is_empty() {
-                    // We couldn't convert any files for this format
-                    None
-                } else {
-                    Some((to_convert, converted_files))
-                }
-            }).collect();
+    cli::exit_code(false, failed, any_supported)
+}
 
-        // Get new flares from the ones that we just did
-        flares = new_results.values()
-            .flat_map(|converted_files| { converted_files })
-            // We will now have just a list of files that we can make into flares
-            .map(|file| {
-                // Create the flare in the nested flared base
-                Flare::new(make_flared_save_path(file), file.clone())
-            }).collect();
+/// All of the items found inside of a single file for one of its formats
+#[derive(Serialize)]
+struct Listing {
+    file: PathBuf,
+    format: Format,
+    items: Vec<ListedItem>,
+}
+
+/// Prints the formats that every file was detected as
+fn identify(cli: &Cli) -> i32 {
+    let mut failed = false;
+    let identities: Vec<Identity> = find_input_files(cli).into_iter().map(|(file, _)| {
+        let candidates = binaryflare::identify(&file).unwrap_or_else(|err| {
+            eprintln!("Couldn't read {} due to {}", file.display(), err);
+            failed = true;
+            Vec::new()
+        });
+        let candidates: Vec<Candidate> = candidates.into_iter()
+            .filter(|candidate| cli.allows(candidate.format))
+            .collect();
+        Identity {
+            formats: candidates.iter()
+                .filter(|candidate| candidate.confidence >= MIN_CONFIDENCE)
+                .map(|candidate| candidate.format)
+                .collect(),
+            file,
+            candidates,
+        }
+    }).collect();
+    let any_supported = identities.iter().any(|identity| !identity.formats.is_empty());
+
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&identities)
+            .expect("Failed to turn the formats into JSON"));
+    } else {
+        for identity in identities {
+            if identity.candidates.is_empty() {
+                println!("{}: unknown", identity.file.display());
+                continue;
+            }
+            println!("{}:", identity.file.display());
+            //Already ranked with the most confident first
+            for candidate in identity.candidates {
+                println!("    {:>3}%  {}{}{}",
+                    candidate.confidence,
+                    candidate.format.name(),
+                    if candidate.extension_match { "  (extension matches)" } else { "" },
+                    if candidate.confidence < MIN_CONFIDENCE { "  (too unsure to flare)" } else { "" });
+            }
+        }
+    }
+
+    cli::exit_code(false, failed, any_supported)
+}
+
+/// The formats that a single file could be
+#[derive(Serialize)]
+struct Identity {
+    file: PathBuf,
+    /// The candidates that are confident enough to be flared
+    formats: Vec<Format>,
+    /// Every format that the file could be, with the most confident first
+    candidates: Vec<Candidate>,
+}
+
+/// Prints every format that was found anywhere inside of the given files
+fn carve(cli: &Cli) -> i32 {
+    let mut failed = false;
+    let carvings: Vec<Carving> = find_input_files(cli).into_iter().map(|(file, _)| {
+        let hits = allowed_hits(cli, &file).unwrap_or_else(|err| {
+            eprintln!("Couldn't read {} due to {}", file.display(), err);
+            failed = true;
+            Vec::new()
+        });
+        Carving {
+            file,
+            hits,
+        }
+    }).collect();
+    let any_supported = carvings.iter().any(|carving| !carving.hits.is_empty());
 
-        results.extend(new_results.drain());
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&carvings)
+            .expect("Failed to turn the hits into JSON"));
+    } else {
+        for carving in carvings {
+            if carving.hits.is_empty() {
+                println!("{}: nothing found", carving.file.display());
+                continue;
+            }
+            println!("{}:", carving.file.display());
+            for hit in carving.hits {
+                println!("    0x{:<12x} {:>3}%  {}", hit.offset, hit.confidence, hit.format.name());
+            }
+        }
     }
 
-    // Format the results into a String
-    let mut results_string = String::new();
-    for (base, flared_files) in results {
-        write!(results_string, "In: {}\n", base.display()).unwrap();
-        write!(results_string, "Out:\n").unwrap();
-        
-        for file in flared_files {
-            write!(results_string, "    {}\n", file.display()).unwrap();
+    cli::exit_code(false, failed, any_supported)
+}
+
+/// Every format that was found inside of a single file
+#[derive(Serialize)]
+struct Carving {
+    file: PathBuf,
+    hits: Vec<CarveHit>,
+}
+
+/// Checks every item of the given files against its stored checksum without saving anything
+fn verify(cli: &Cli) -> i32 {
+    let mut failed = false;
+    let mut any_supported = false;
+    let mut bad_count = 0;
+    for (file, _) in find_input_files(cli) {
+        let file_formats = allowed_formats(cli, &file).unwrap_or_else(|err| {
+            eprintln!("Couldn't read {} due to {}", file.display(), err);
+            failed = true;
+            Vec::new()
+        });
+        any_supported |= !file_formats.is_empty();
+
+        for format in file_formats {
+            match Archive::open_with(&file, format, cli.options.clone()).verify() {
+                Ok(bad_items) => {
+                    if bad_items.is_empty() {
+                        say(cli, Verbosity::Normal, format!("{} ({:?}): ok", file.display(),
+                            format));
+                    } else {
+                        println!("{} ({:?}): {} items failed their checksum or couldn't be read",
+                            file.display(), format, bad_items.len());
+                        for name in &bad_items {
+                            println!("    {}", name);
+                        }
+                    }
+                    bad_count += bad_items.len();
+                },
+                Err(err) => {
+                    eprintln!("Couldn't verify {} as {:?} due to {}", file.display(), format, err);
+                    failed = true;
+                },
+            }
         }
-        
-        write!(results_string, "\n========\n").unwrap();
     }
 
-    // Write out all of the results into a file
-    let mut results_path = PathBuf::from(OUT_DIR);
-    // Get rid of all of the colons so that it's a valid file name
-    results_path.push(format!("{}", time::now().rfc822z()).replace(":", ""));
-    results_path.set_extension("txt");
+    cli::exit_code(bad_count > 0, failed, any_supported)
+}
+
+/// Packs a folder back into an XP3 archive
+/// Expects the folder to pack followed by the archive to create
+fn pack(cli: &Cli) -> i32 {
+    let folder = PathBuf::from(&cli.paths[0]);
+    if !folder.is_dir() {
+        eprintln!("{} must be a folder", folder.display());
+        return cli::EXIT_USAGE;
+    }
+    let archive_path = PathBuf::from(&cli.paths[1]);
 
-    let mut results_file = File::create(results_path)
-        .expect("Failed to create the results file");
-    results_file.write_all(results_string.as_bytes())
-        .expect("Failed to write the results file");
+    let start_time = SteadyTime::now();
+    match formats::pack_folder(&folder, &archive_path) {
+        Ok(file_count) => {
+            let seconds = ((SteadyTime::now() - start_time).num_milliseconds() as f64) / 1000.0;
+            say(cli, Verbosity::Normal, format!("{} packed! {} files in {:.3} sec",
+                archive_path.display(), file_count, seconds));
+            cli::EXIT_OK
+        },
+        Err(err) => {
+            eprintln!("Failed to pack {} due to {}", archive_path.display(), err);
+            cli::EXIT_ERRORS
+        },
+    }
+}
+
+/// Encrypts a text file so KiriKiri reads it the same as a script that was flared out of a game
+fn encrypt_text(cli: &Cli) -> i32 {
+    let text_path = Path::new(&cli.paths[0]);
+    let encrypted_path = Path::new(&cli.paths[1]);
+    let encrypted = fs::read(text_path).and_then(|data| {
+        let text = simple_crypt::plain_text(&data);
+        fs::write(encrypted_path, simple_crypt::encode(&text, cli.text_mode))
+    });
+
+    match encrypted {
+        Ok(()) => {
+            say(cli, Verbosity::Normal, format!("{} encrypted with mode {}!",
+                encrypted_path.display(), cli.text_mode.byte()));
+            cli::EXIT_OK
+        },
+        Err(err) => {
+            eprintln!("Failed to encrypt {} due to {}", text_path.display(), err);
+            cli::EXIT_ERRORS
+        },
+    }
 }
 
 /// Creates the save path from the given file name and a parent
-/// The parent should be specified if a directory was given initially
-fn make_save_path(file: &PathBuf, parent: Option<&PathBuf>) -> PathBuf {
-    let mut save_path = PathBuf::from(OUT_DIR);
+/// The parent should be specified if a directory was given initially. The folders between the
+/// parent and the file are kept so the layout of the directory is mirrored.
+fn make_save_path(output: &Path, file: &Path, parent: Option<&Path>) -> PathBuf {
+    let mut save_path = output.to_path_buf();
     if let Some(parent) = parent {
-        save_path.push(file_utils::file_stem(parent));
+        // A parent without a name, like `.`, has its files saved right in the output
+        if let Some(stem) = file_utils::file_stem(parent) {
+            save_path.push(stem);
+        }
+        if let Some(folders) = file.parent().and_then(|folder| folder.strip_prefix(parent).ok()) {
+            save_path.push(folders);
+        }
     }
     save_path.push(make_flared_base(file));
 
@@ -133,8 +729,8 @@ fn make_save_path(file: &PathBuf, parent: Option<&PathBuf>) -> PathBuf {
 }
 
 /// Similar to make_save_path() but we assume that the file is already saved in the out folder
-/// Since the original only does a single layer of directories we need to be lower than that
-fn make_flared_save_path(file: &PathBuf) -> PathBuf {
+/// The flared files are saved next to the file
+fn make_flared_save_path(file: &Path) -> PathBuf {
     let mut save_path = file.parent().unwrap().to_path_buf();
     save_path.push(make_flared_base(file));
 
@@ -142,13 +738,13 @@ fn make_flared_save_path(file: &PathBuf) -> PathBuf {
 }
 
 /// Creates the base folder name for a file that will be flared
-fn make_flared_base(file: &PathBuf) -> String {
-    format!("{}({})", file_utils::file_stem(file), file_utils::extension(file))
+fn make_flared_base(file: &Path) -> String {
+    format!("{}({})", file_utils::file_stem(file).unwrap_or_default(), file_utils::extension(file))
 }
 
 
 /// Holds all of the information needed to flare a file
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct Flare {
     /// The folder to save all of the flared files
     save_folder: PathBuf,
@@ -156,44 +752,183 @@ struct Flare {
     /// The file to perform a conversion on
     to_convert: PathBuf,
 
-    /// The converted files. These are the resulting files from the flaring
-    converted_files: Vec<PathBuf>,
+    /// Where to read to_convert from instead, if it was saved into a tar or zip
+    spooled: Option<PathBuf>,
+
+    /// Everything else that the converters found while flaring
+    report: FlareReport,
+
+    /// Everything that went wrong, with the format that was being flared if we got that far
+    errors: Vec<(Option<Format>, FlareError)>,
+
+    /// The formats that the file was flared as
+    formats: Vec<Format>,
+
+    /// Every format that was found partway into the file, only when carving
+    carved: Vec<CarveHit>,
+
+    /// The file that this one was flared out of
+    parent: Option<PathBuf>,
+
+    /// How many times the file has been flared out of other files
+    depth: usize,
+
+    /// How long flaring took
+    seconds: f64,
+
+    /// The SHA-256 of the file, only if it's a supported format
+    hash: Option<String>,
+
+    /// The file that was already flared with the same contents, if there was one
+    duplicate_of: Option<PathBuf>,
+
+    /// Whether the flared files weren't flared again because they would be too deep
+    depth_limited: bool,
 }
 
 impl Flare {
-    fn new(save_folder: PathBuf, to_convert: PathBuf) -> Flare {
+    fn new(save_folder: PathBuf, to_convert: PathBuf, parent: Option<PathBuf>, depth: usize)
+    -> Flare {
         Flare {
             save_folder,
             to_convert,
-            converted_files: Vec::new(),
+            spooled: None,
+            report: FlareReport::default(),
+            errors: Vec::new(),
+            formats: Vec::new(),
+            carved: Vec::new(),
+            parent,
+            depth,
+            seconds: 0.0,
+            hash: None,
+            duplicate_of: None,
+            depth_limited: false,
         }
     }
 
-    fn flare(&mut self) {
-        // Figure out if this is a supported file format
-        let file_formats = formats::guess_format(&self.to_convert);
-        if file_formats.is_empty() {
+    /// The file that actually gets read to flare to_convert
+    fn source(&self) -> &Path {
+        self.spooled.as_deref().unwrap_or(&self.to_convert)
+    }
+
+    /// Figures out the formats of the file, and hashes it if it's a supported format
+    /// When carving, the whole file is searched for formats instead
+    fn detect(&mut self, cli: &Cli) {
+        let detected = if cli.carve {
+            allowed_hits(cli, self.source()).map(|hits| {
+                self.carved = hits;
+                let mut formats: Vec<Format> = Vec::new();
+                for hit in &self.carved {
+                    if !formats.contains(&hit.format) {
+                        formats.push(hit.format);
+                    }
+                }
+                formats
+            })
+        } else {
+            allowed_formats(cli, self.source())
+        };
+        self.formats = match detected {
+            Ok(file_formats) => file_formats,
+            Err(err) => {
+                self.errors.push((None, err));
+                return
+            },
+        };
+        if self.formats.is_empty() {
             return
         };
+        say(cli, Verbosity::Verbose, format!("{} is {:?}", self.to_convert.display(),
+            self.formats));
+        for hit in &self.carved {
+            say(cli, Verbosity::Verbose, format!("    {:?} at 0x{:x}", hit.format, hit.offset));
+        }
+
+        match file_utils::hash_file(self.source()) {
+            Ok((_, hash)) => self.hash = Some(hash),
+            Err(err) => self.errors.push((None, FlareError::from(err))),
+        }
+    }
+
+    /// Flares the file with every format that detect() found
+    /// Everything is saved into the shared sink if there is one, otherwise into save_folder
+    /// Files saved into the shared sink also get a copy in spool_folder, if they'll be flared again
+    fn flare(&mut self, cli: &Cli, shared_sink: Option<&Arc<Mutex<dyn Sink>>>,
+        spool_folder: Option<&Path>) {
+        if self.formats.is_empty() || !self.errors.is_empty() {
+            return
+        }
+        if let Some(ref original) = self.duplicate_of {
+            say(cli, Verbosity::Verbose, format!("{} was already flared as {}",
+                self.to_convert.display(), original.display()));
+            return
+        }
+
+        // Carved formats that don't start the file get their own folder, named by their offset
+        let targets: Vec<(Format, u64, PathBuf)> = if cli.carve {
+            self.carved.iter().map(|hit| {
+                let folder = match hit.offset {
+                    0 => self.save_folder.clone(),
+                    offset => self.save_folder.join(format!("0x{:x}", offset)),
+                };
+                (hit.format, hit.offset, folder)
+            }).collect()
+        } else {
+            self.formats.iter().map(|&format| (format, 0, self.save_folder.clone())).collect()
+        };
 
         let start_time = SteadyTime::now();
-        for file_format in file_formats {
+        for (file_format, offset, save_folder) in targets {
+            let mut sink: Box<dyn Sink> = match shared_sink {
+                Some(shared_sink) => {
+                    let folder = save_folder.strip_prefix(&cli.output).unwrap_or(&save_folder);
+                    let sink = Box::new(SharedSink::new(shared_sink.clone(), folder.to_path_buf()));
+                    match spool_folder {
+                        Some(spool_folder) if self.depth < cli.depth => {
+                            Box::new(SpoolSink::new(sink, spool_folder.join(folder)))
+                        },
+                        _ => sink,
+                    }
+                },
+                None => Box::new(DirSink::new(save_folder)),
+            };
+
             // Actually flare the file for each format
-            formats::flare_file(&self.to_convert, &self.save_folder, file_format);
+            // A format that fails still leaves the others to try
+            let archive = Archive::open_at(self.source(), offset, file_format,
+                cli.options.clone());
+            match archive.extract_to(&mut *sink) {
+                Ok(report) => self.report.merge(report),
+                Err(err) => {
+                    eprintln!("{} failed to flare as {:?} due to {}", self.to_convert.display(),
+                        file_format, err);
+                    self.errors.push((Some(file_format), err));
+                },
+            }
         }
 
-        // Read the save directory to figure out all of the files that were just flared
-        self.converted_files = file_utils::all_files(&self.save_folder, true);
-
-        let file_count = self.converted_files.len();
-        let seconds = ((SteadyTime::now() - start_time).num_milliseconds() as f64) / 1000.0;
-        println!("{} complete! {} files in {:.3} sec", self.to_convert.display(),
-            file_count, seconds);
-    }
+        // Sort them so that the results are the same every time
+        self.report.entries.sort_by(|a, b| a.path.cmp(&b.path));
 
-    /// Returns the results of the flaring
-    /// Gives the converted file first, with the resulting flared files second
-    fn results(self) -> (PathBuf, Vec<PathBuf>) {
-        (self.to_convert, self.converted_files)
+        let file_count = self.report.entries.len();
+        self.seconds = ((SteadyTime::now() - start_time).num_milliseconds() as f64) / 1000.0;
+        say(cli, Verbosity::Normal, format!("{} complete! {} files in {:.3} sec",
+            self.to_convert.display(), file_count, self.seconds));
+        if !cli.options.filter.is_empty() {
+            say(cli, Verbosity::Normal, format!("{} matched {} items and skipped {}",
+                self.to_convert.display(), self.report.matched, self.report.skipped));
+        }
+        for entry in &self.report.entries {
+            say(cli, Verbosity::Verbose, format!("    {}", entry.path.display()));
+        }
+        for file in &self.report.bad_checksums {
+            say(cli, Verbosity::Verbose, format!("{} failed its checksum", file.display()));
+        }
+        for warning in &self.report.warnings {
+            say(cli, Verbosity::Verbose, format!("{}: {}", self.to_convert.display(), warning));
+        }
+        for (name, err) in &self.report.failed {
+            eprintln!("{}: {} failed to flare due to {}", self.to_convert.display(), name, err);
+        }
     }
 }
@@ -0,0 +1,241 @@
+//!The machine readable report of an extract run
+//!
+//!Everything is sorted by path so that two runs over the same files can be diffed.
+use std::collections::{BTreeMap};
+use std::io::{Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use binaryflare::{file_utils, CarveHit, FlaredEntry, Format};
+
+///Everything that happened during a run
+#[derive(Serialize)]
+pub struct RunReport {
+    ///When the run started, as RFC 3339
+    pub started: String,
+    pub seconds: f64,
+    pub output: PathBuf,
+    ///Every file that was given plus every flared file that was a supported format
+    pub inputs: Vec<InputReport>,
+}
+
+///The fields of a run other than its inputs, for the first line of JSON lines
+#[derive(Serialize)]
+struct RunLine<'a> {
+    started: &'a str,
+    seconds: f64,
+    output: &'a Path,
+}
+
+///Everything that happened to a single file
+#[derive(Serialize)]
+pub struct InputReport {
+    pub path: PathBuf,
+    ///The file that this one was flared out of
+    pub parent: Option<PathBuf>,
+    ///The flared files that were flared again and have their own report
+    pub children: Vec<PathBuf>,
+    ///How many times the file has been flared out of other files
+    pub depth: usize,
+    ///Only there for files that were a supported format
+    pub sha256: Option<String>,
+    ///The file with the same contents that was flared instead of this one
+    pub duplicate_of: Option<PathBuf>,
+    pub formats: Vec<Format>,
+    ///Where every format was found inside of the file, only when carving
+    pub carved: Vec<CarveHit>,
+    pub seconds: f64,
+    pub outputs: Vec<OutputReport>,
+    pub errors: Vec<ErrorReport>,
+    ///Things that didn't stop the flare but should be looked at
+    pub warnings: Vec<String>,
+}
+
+///A single flared file
+#[derive(Serialize)]
+pub struct OutputReport {
+    pub path: PathBuf,
+    ///The name that the item had inside of the file
+    pub name: String,
+    pub size: u64,
+    ///Only there for files that were saved into folders
+    pub sha256: Option<String>,
+    ///Where the item's data starts inside of the file that was flared
+    pub offset: u64,
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorReport {
+    ///The format that was being flared, if the format got found
+    pub format: Option<Format>,
+    pub message: String,
+}
+
+impl OutputReport {
+    ///The file is only read back to hash it if hash is true
+    pub fn new(entry: &FlaredEntry, hash: bool) -> IOResult<OutputReport> {
+        let sha256 = if hash {
+            Some(file_utils::hash_file(&entry.path)?.1)
+        } else {
+            None
+        };
+        Ok(OutputReport {
+            path: entry.path.clone(),
+            name: entry.name.clone(),
+            size: entry.size,
+            sha256,
+            offset: entry.offset,
+            attributes: entry.attributes.clone(),
+        })
+    }
+}
+
+impl RunReport {
+    ///Sorts everything and fills in the children of every input from their parents
+    pub fn finish(&mut self) {
+        self.inputs.sort_by(|a, b| a.path.cmp(&b.path));
+        for input in &mut self.inputs {
+            input.outputs.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+
+        let links: Vec<(PathBuf, PathBuf)> = self.inputs.iter().filter_map(|input| {
+            input.parent.clone().map(|parent| (parent, input.path.clone()))
+        }).collect();
+        for (parent, child) in links {
+            if let Some(input) = self.inputs.iter_mut().find(|input| input.path == parent) {
+                input.children.push(child);
+            }
+        }
+    }
+
+    ///Writes the whole report as a single JSON object
+    pub fn write_json<W: Write>(&self, mut out: W) -> IOResult<()> {
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)
+    }
+
+    ///Writes the run's own fields as the first line of JSON, then every input as its own line
+    pub fn write_json_lines<W: Write>(&self, mut out: W) -> IOResult<()> {
+        serde_json::to_writer(&mut out, &RunLine {
+            started: &self.started,
+            seconds: self.seconds,
+            output: &self.output,
+        })?;
+        writeln!(out)?;
+        for input in &self.inputs {
+            serde_json::to_writer(&mut out, input)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(path: &str, parent: Option<&str>, outputs: &[&str]) -> InputReport {
+        InputReport {
+            path: PathBuf::from(path),
+            parent: parent.map(PathBuf::from),
+            children: Vec::new(),
+            depth: parent.map_or(0, |_| 1),
+            sha256: None,
+            duplicate_of: None,
+            formats: Vec::new(),
+            carved: Vec::new(),
+            seconds: 0.0,
+            outputs: outputs.iter().map(|&path| OutputReport {
+                path: PathBuf::from(path),
+                name: String::from(path),
+                size: 0,
+                sha256: None,
+                offset: 0,
+                attributes: BTreeMap::new(),
+            }).collect(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn finished(inputs: Vec<InputReport>) -> RunReport {
+        let mut report = RunReport {
+            started: String::from("2026-01-01T00:00:00Z"),
+            seconds: 1.5,
+            output: PathBuf::from("out"),
+            inputs,
+        };
+        report.finish();
+        report
+    }
+
+    fn paths<'a, I: Iterator<Item = &'a PathBuf>>(paths: I) -> Vec<&'a str> {
+        paths.map(|path| path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn sorts_in_the_same_order_every_time() {
+        let report = finished(vec![
+            input("out/b.xp3", None, &["out/b/2.txt", "out/b/1.txt"]),
+            input("a.xp3", None, &[]),
+            input("out/a/z.tlg", Some("a.xp3"), &[]),
+        ]);
+        let inputs = paths(report.inputs.iter().map(|input| &input.path));
+        assert_eq!(inputs, ["a.xp3", "out/a/z.tlg", "out/b.xp3"]);
+        let outputs = paths(report.inputs[2].outputs.iter().map(|output| &output.path));
+        assert_eq!(outputs, ["out/b/1.txt", "out/b/2.txt"]);
+
+        //The order that the files were flared in doesn't matter
+        let reversed = finished(vec![
+            input("out/a/z.tlg", Some("a.xp3"), &[]),
+            input("a.xp3", None, &[]),
+            input("out/b.xp3", None, &["out/b/1.txt", "out/b/2.txt"]),
+        ]);
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        report.write_json_lines(&mut first).unwrap();
+        reversed.write_json_lines(&mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn links_children_to_their_parents() {
+        let report = finished(vec![
+            input("out/data/b.psb", Some("data.xp3"), &[]),
+            input("data.xp3", None, &[]),
+            input("out/data/a.tlg", Some("data.xp3"), &[]),
+            input("out/data/b/0.tlg", Some("out/data/b.psb"), &[]),
+            input("other.xp3", None, &[]),
+        ]);
+        let children = |index: usize| paths(report.inputs[index].children.iter());
+        assert_eq!(report.inputs[0].path, PathBuf::from("data.xp3"));
+        assert_eq!(children(0), ["out/data/a.tlg", "out/data/b.psb"]);
+        //Paths are sorted by their components, so a folder comes before a file with an extension
+        assert_eq!(report.inputs[4].path, PathBuf::from("out/data/b.psb"));
+        assert_eq!(children(4), ["out/data/b/0.tlg"]);
+        for index in 1..4 {
+            assert!(children(index).is_empty());
+        }
+    }
+
+    #[test]
+    fn writes_the_run_to_both_formats() {
+        let report = finished(vec![input("a.xp3", None, &[])]);
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let mut lines = Vec::new();
+        report.write_json_lines(&mut lines).unwrap();
+        let lines: Vec<serde_json::Value> = lines.split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        for field in ["started", "seconds", "output"] {
+            assert_eq!(lines[0][field], json[field]);
+        }
+        assert_eq!(lines[1], json["inputs"][0]);
+    }
+}
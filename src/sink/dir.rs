@@ -0,0 +1,47 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime};
+
+
+use file_utils;
+use super::{copy_exact, Sink};
+
+///Saves every file into a folder, making any folders that are needed along the way
+#[derive(Debug, Clone)]
+pub struct DirSink {
+    root: PathBuf,
+}
+
+impl DirSink {
+    pub fn new(root: PathBuf) -> DirSink {
+        DirSink {
+            root,
+        }
+    }
+}
+
+impl Sink for DirSink {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.root.join(path).exists()
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        let mut file = BufWriter::new(file_utils::make_file(&self.root.join(path))?);
+        copy_exact(data, &mut file, size)?;
+        file.flush()
+    }
+
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        fs::remove_file(self.root.join(path))
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    fn set_modified(&mut self, path: &Path, modified: SystemTime) -> IOResult<()> {
+        File::options().write(true).open(self.root.join(path))?.set_modified(modified)
+    }
+}
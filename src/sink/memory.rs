@@ -0,0 +1,53 @@
+use std::cmp;
+use std::collections::{BTreeMap};
+use std::io::{Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+
+use super::{copy_exact, Sink};
+
+//Sizes come from the file being flared, so they can't be trusted to reserve memory with
+const MAX_RESERVE: u64 = 1024 * 1024;
+
+///Keeps every file in memory, sorted by path
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    pub fn files(&self) -> &BTreeMap<PathBuf, Vec<u8>> {
+        &self.files
+    }
+
+    pub fn into_files(self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.files
+    }
+}
+
+impl Sink for MemorySink {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        let mut file = Vec::with_capacity(cmp::min(size, MAX_RESERVE) as usize);
+        copy_exact(data, &mut file, size)?;
+        self.files.insert(path.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        self.files.remove(path);
+        Ok(())
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+}
@@ -0,0 +1,209 @@
+mod dir;
+mod memory;
+mod spool;
+mod tar;
+mod zip;
+
+pub use self::dir::{DirSink};
+pub use self::memory::{MemorySink};
+pub use self::spool::{SpoolSink};
+pub use self::tar::{TarSink};
+pub use self::zip::{ZipSink};
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Error, ErrorKind, Result as IOResult, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime};
+
+//Files up to this size are spooled in memory, anything bigger goes into a temporary file
+const MEMORY_SPOOL_SIZE: u64 = 16 * 1024 * 1024;
+
+//Numbers the temporary files so that every spooled file gets its own
+static NEXT_SPOOL: AtomicUsize = AtomicUsize::new(0);
+
+///Somewhere that flared files get saved
+///
+///Converters save every file through a sink so that they don't care if it ends up in a folder, an
+///archive or memory. The paths are always relative and already safe to use, since they come from
+///file_utils::member_path().
+pub trait Sink: Send {
+    ///Checks if a file was already saved at path
+    fn exists(&mut self, path: &Path) -> bool;
+
+    ///Saves everything that data gives at path
+    ///size is exactly how many bytes data will give, for sinks that need to know it up front
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()>;
+
+    ///Takes back the file that was saved at path
+    ///Sinks that have already written the file out for good can't do this
+    fn remove(&mut self, path: &Path) -> IOResult<()>;
+
+    ///Where the file at path ends up, to tell people about it
+    fn location(&self, path: &Path) -> PathBuf;
+
+    ///Gives the saved file at path the time it was last changed inside of what it was flared from
+    ///Sinks that don't keep times (or always use the same one) can ignore this
+    fn set_modified(&mut self, _path: &Path, _modified: SystemTime) -> IOResult<()> {
+        Ok(())
+    }
+
+    ///Writes out anything that's left, like the end of an archive
+    ///Nothing can be saved afterwards
+    fn finish(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+///Lets many flares save into the same sink at the same time, each inside of its own folder
+///
+///Only one file is saved at a time. The files of different flares can be mixed together.
+#[derive(Clone)]
+pub struct SharedSink {
+    sink: Arc<Mutex<dyn Sink>>,
+    folder: PathBuf,
+}
+
+impl SharedSink {
+    ///Everything that's saved goes inside of folder in the sink
+    pub fn new(sink: Arc<Mutex<dyn Sink>>, folder: PathBuf) -> SharedSink {
+        SharedSink {
+            sink,
+            folder,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, dyn Sink + 'static> {
+        //A flare that panicked while saving leaves the sink as usable as any other failed save
+        self.sink.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Sink for SharedSink {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.lock().exists(&self.folder.join(path))
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        self.lock().save(&self.folder.join(path), size, data)
+    }
+
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        self.lock().remove(&self.folder.join(path))
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        self.lock().location(&self.folder.join(path))
+    }
+
+    fn set_modified(&mut self, path: &Path, modified: SystemTime) -> IOResult<()> {
+        self.lock().set_modified(&self.folder.join(path), modified)
+    }
+
+    ///The shared sink is finished by whoever made it, once every flare is done
+    fn finish(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+///Whether an error from a sink means that nothing else can be saved into it either, like when the
+/// disk is full or the stream an archive is written to is broken
+///Anything else, like a name that's too long or a file that can't be read, only stops that file
+pub fn stops_sink(err: &Error) -> bool {
+    matches!(err.kind(), ErrorKind::StorageFull | ErrorKind::QuotaExceeded |
+        ErrorKind::ReadOnlyFilesystem | ErrorKind::BrokenPipe | ErrorKind::WriteZero |
+        ErrorKind::OutOfMemory)
+}
+
+///Marks an error from writing the stream of an archive, which can't be finished properly after it
+fn broken(err: Error) -> Error {
+    if stops_sink(&err) {
+        err
+    } else {
+        Error::new(ErrorKind::BrokenPipe, err)
+    }
+}
+
+///All of a file's data, read before any of it goes into an archive
+///
+///Sinks that write their files out for good can't take back a file that failed partway through,
+///so the file is read in full first and only written if that worked.
+enum Spooled {
+    Memory(Cursor<Vec<u8>>),
+    //The file is only None once it's been closed to be deleted
+    File(Option<File>, PathBuf),
+}
+
+impl Spooled {
+    ///Reads exactly size bytes from data, giving an error if there are more or less
+    fn new(data: &mut dyn Read, size: u64) -> IOResult<Spooled> {
+        let mut spooled = if size <= MEMORY_SPOOL_SIZE {
+            Spooled::Memory(Cursor::new(Vec::with_capacity(size as usize)))
+        } else {
+            let path = env::temp_dir().join(format!("binaryflare-{}-{}.spool", process::id(),
+                NEXT_SPOOL.fetch_add(1, Ordering::Relaxed)));
+            let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+            Spooled::File(Some(file), path)
+        };
+
+        //One byte past the size is enough to know that there was too much
+        let mut data = data.take(size.saturating_add(1));
+        let copied = match spooled {
+            Spooled::Memory(ref mut buffer) => io::copy(&mut data, buffer.get_mut())?,
+            Spooled::File(Some(ref mut file), _) => {
+                let copied = io::copy(&mut data, file)?;
+                file.seek(SeekFrom::Start(0))?;
+                copied
+            },
+            Spooled::File(None, _) => unreachable!("the file is only closed when it's dropped"),
+        };
+        if copied != size {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "the file should have been {} bytes but was {}", size,
+                if copied > size { String::from("more") } else { copied.to_string() })));
+        }
+        Ok(spooled)
+    }
+}
+
+impl Read for Spooled {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        match *self {
+            Spooled::Memory(ref mut data) => data.read(buffer),
+            Spooled::File(Some(ref mut file), _) => file.read(buffer),
+            Spooled::File(None, _) => Ok(0),
+        }
+    }
+}
+
+impl Drop for Spooled {
+    fn drop(&mut self) {
+        //The file has to be closed before it can be deleted everywhere
+        if let Spooled::File(ref mut file, ref path) = *self {
+            *file = None;
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+///Copies exactly size bytes from data into out
+fn copy_exact<W: Write + ?Sized>(data: &mut dyn Read, out: &mut W, size: u64) -> IOResult<()> {
+    let copied = io::copy(data, out)?;
+    if copied != size {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "the file should have been {} bytes but was {}", size, copied)));
+    }
+    Ok(())
+}
+
+///The name of a path inside of an archive, always with `/` as the separator
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+}
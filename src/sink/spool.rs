@@ -0,0 +1,63 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime};
+
+
+use super::{DirSink, Sink};
+
+///Saves every file into another sink while keeping a copy in a folder so it can be read back
+///
+///Files that were saved into a tar or zip can't be opened again to be flared, so the copy in the
+///folder is flared instead. Each file is saved into the folder first, which also means the other
+///sink is only busy while the copy is read back.
+pub struct SpoolSink {
+    sink: Box<dyn Sink>,
+    spool: DirSink,
+    folder: PathBuf,
+}
+
+impl SpoolSink {
+    ///Everything that's saved into sink also gets a copy inside of folder
+    pub fn new(sink: Box<dyn Sink>, folder: PathBuf) -> SpoolSink {
+        SpoolSink {
+            sink,
+            spool: DirSink::new(folder.clone()),
+            folder,
+        }
+    }
+}
+
+impl Sink for SpoolSink {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.sink.exists(path)
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        self.spool.save(path, size, data)?;
+        let mut copy = BufReader::new(File::open(self.folder.join(path))?);
+        self.sink.save(path, size, &mut copy)
+    }
+
+    ///The copy is taken back too, even if the other sink can't take back its own
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        let removed = self.sink.remove(path);
+        if self.folder.join(path).exists() {
+            fs::remove_file(self.folder.join(path))?;
+        }
+        removed
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        self.sink.location(path)
+    }
+
+    fn set_modified(&mut self, path: &Path, modified: SystemTime) -> IOResult<()> {
+        self.sink.set_modified(path, modified)
+    }
+
+    fn finish(&mut self) -> IOResult<()> {
+        self.sink.finish()
+    }
+}
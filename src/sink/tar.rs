@@ -0,0 +1,143 @@
+use std::collections::{HashSet};
+use std::io::{Error, ErrorKind, Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+
+use super::{archive_name, broken, copy_exact, Sink, Spooled};
+
+//Everything in a tar is in blocks of this size
+const BLOCK_SIZE: usize = 512;
+
+//The name field can only hold this many bytes, longer ones get their own entry in front
+const NAME_SIZE: usize = 100;
+
+//The largest size that fits into the 11 octal digits of the size field
+const MAX_OCTAL_SIZE: u64 = 0o77_777_777_777;
+
+//The GNU magic and version, since the long name entries are a GNU extension
+const MAGIC: &[u8] = b"ustar  \0";
+
+const FILE_TYPE: u8 = b'0';
+const LONG_NAME_TYPE: u8 = b'L';
+
+///Saves every file into a single tar stream, one after another
+///
+///The files are written out right away, so the stream doesn't need to be seekable and can be as
+///big as it needs to be. Every file is given the same mode and time so the same files always
+///give the same tar. Each file is read in full before its header is written, so a file that
+///fails partway through leaves nothing in the tar.
+pub struct TarSink<W: Write + Send> {
+    out: W,
+    saved: HashSet<PathBuf>,
+    finished: bool,
+}
+
+impl <W: Write + Send> TarSink<W> {
+    pub fn new(out: W) -> TarSink<W> {
+        TarSink {
+            out,
+            saved: HashSet::new(),
+            finished: false,
+        }
+    }
+
+    ///Finishes the tar if it hasn't been yet and gives back the stream
+    pub fn into_inner(mut self) -> IOResult<W> {
+        self.finish()?;
+        Ok(self.out)
+    }
+
+    fn write_header(&mut self, name: &[u8], size: u64, kind: u8) -> IOResult<()> {
+        let mut header = [0; BLOCK_SIZE];
+        let name_size = name.len().min(NAME_SIZE);
+        header[..name_size].copy_from_slice(&name[..name_size]);
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        write_size(&mut header[124..136], size);
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[156] = kind;
+        header[257..265].copy_from_slice(MAGIC);
+
+        //The checksum is figured out as if its own field was all spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.out.write_all(&header)
+    }
+
+    ///Writes the whole entry for a file that was already read in full
+    fn write_entry(&mut self, name: &str, size: u64, data: &mut Spooled) -> IOResult<()> {
+        if name.len() > NAME_SIZE {
+            //The name is saved as the data of an entry before the file, with a nul at the end
+            let long_name = [name.as_bytes(), &[0]].concat();
+            self.write_header(b"././@LongLink", long_name.len() as u64, LONG_NAME_TYPE)?;
+            self.out.write_all(&long_name)?;
+            self.write_padding(long_name.len() as u64)?;
+        }
+
+        self.write_header(name.as_bytes(), size, FILE_TYPE)?;
+        copy_exact(data, &mut self.out, size)?;
+        self.write_padding(size)
+    }
+
+    ///Fills up the rest of the block after size bytes
+    fn write_padding(&mut self, size: u64) -> IOResult<()> {
+        let used = (size % BLOCK_SIZE as u64) as usize;
+        if used != 0 {
+            self.out.write_all(&[0; BLOCK_SIZE][used..])?;
+        }
+        Ok(())
+    }
+}
+
+impl <W: Write + Send> Sink for TarSink<W> {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.saved.contains(path)
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        if self.finished {
+            return Err(Error::other("the tar was already finished"));
+        }
+
+        let mut data = Spooled::new(data, size)?;
+        //Once anything is written, a failure leaves the tar without a way to be finished
+        self.write_entry(&archive_name(path), size, &mut data).map_err(broken)?;
+        self.saved.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        Err(Error::new(ErrorKind::Unsupported, format!(
+            "{} was already written to the tar and can't be removed", path.display())))
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+
+    ///Ends the tar with two empty blocks
+    fn finish(&mut self) -> IOResult<()> {
+        if !self.finished {
+            self.finished = true;
+            self.out.write_all(&[0; BLOCK_SIZE * 2])?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+///Writes the size as octal, or as big endian binary if it's too big for that
+fn write_size(field: &mut [u8], size: u64) {
+    if size <= MAX_OCTAL_SIZE {
+        field.copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    } else {
+        //The high bit of the first byte says that it's binary
+        field.iter_mut().for_each(|byte| *byte = 0);
+        field[4..].copy_from_slice(&size.to_be_bytes());
+        field[0] = 0x80;
+    }
+}
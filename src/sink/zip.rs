@@ -0,0 +1,229 @@
+use std::collections::{HashSet};
+use std::convert::{TryFrom};
+use std::io::{Error, ErrorKind, Result as IOResult};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use flate2::{Compression, CrcReader};
+use flate2::write::{DeflateEncoder};
+
+
+use super::{archive_name, broken, copy_exact, Sink, Spooled};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+//Version 2.0 is the first with deflate
+const VERSION: u16 = 20;
+//The sizes and CRC come after the data (bit 3) and names are UTF-8 (bit 11)
+const FLAGS: u16 = 0x0808;
+const DEFLATE: u16 = 8;
+//Every file gets the earliest date a zip can have (1980-01-01 00:00) so the zip is always the same
+const TIME: u16 = 0;
+const DATE: u16 = 0x21;
+
+///Saves every file into a zip, deflating each one
+///
+///The files are written out right away and their sizes come after their data, so the stream
+///doesn't need to be seekable. Each file is read in full before its header is written, so a file
+///that fails partway through leaves nothing in the zip. There's no ZIP64, so every file and the whole zip need to be
+///smaller than 4 GiB.
+pub struct ZipSink<W: Write + Send> {
+    out: CountingWriter<W>,
+    entries: Vec<ZipEntry>,
+    saved: HashSet<PathBuf>,
+    finished: bool,
+}
+
+struct ZipEntry {
+    name: String,
+    name_len: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl <W: Write + Send> ZipSink<W> {
+    pub fn new(out: W) -> ZipSink<W> {
+        ZipSink {
+            out: CountingWriter {
+                out,
+                count: 0,
+            },
+            entries: Vec::new(),
+            saved: HashSet::new(),
+            finished: false,
+        }
+    }
+
+    ///Finishes the zip if it hasn't been yet and gives back the stream
+    pub fn into_inner(mut self) -> IOResult<W> {
+        self.finish()?;
+        Ok(self.out.out)
+    }
+
+    ///Writes the local header, data and data descriptor for a file that was already read in full
+    ///Gives the CRC and compressed size of the data
+    fn write_entry(&mut self, name: &str, name_len: u16, size: u64, data: &mut Spooled)
+    -> IOResult<(u32, u32)> {
+        write_u32(&mut self.out, LOCAL_HEADER)?;
+        write_u16(&mut self.out, VERSION)?;
+        write_u16(&mut self.out, FLAGS)?;
+        write_u16(&mut self.out, DEFLATE)?;
+        write_u16(&mut self.out, TIME)?;
+        write_u16(&mut self.out, DATE)?;
+        //The CRC and sizes are in the data descriptor instead
+        self.out.write_all(&[0; 12])?;
+        write_u16(&mut self.out, name_len)?;
+        write_u16(&mut self.out, 0)?;
+        self.out.write_all(name.as_bytes())?;
+
+        let start = self.out.count;
+        let mut data = CrcReader::new(data);
+        let mut compressor = DeflateEncoder::new(&mut self.out, Compression::default());
+        copy_exact(&mut data, &mut compressor, size)?;
+        compressor.finish()?;
+        let compressed_size = fits_u32(self.out.count - start)?;
+        let crc = data.crc().sum();
+
+        write_u32(&mut self.out, DATA_DESCRIPTOR)?;
+        write_u32(&mut self.out, crc)?;
+        write_u32(&mut self.out, compressed_size)?;
+        write_u32(&mut self.out, size as u32)?;
+
+        Ok((crc, compressed_size))
+    }
+}
+
+impl <W: Write + Send> Sink for ZipSink<W> {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.saved.contains(path)
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> IOResult<()> {
+        if self.finished {
+            return Err(Error::other("the zip was already finished"));
+        }
+        if size > u32::MAX as u64 {
+            return Err(Error::new(ErrorKind::Unsupported, format!(
+                "{} is too big to put in a zip", path.display())));
+        }
+        //Nothing else fits once the offsets don't
+        let offset = fits_u32(self.out.count).map_err(broken)?;
+        if self.entries.len() == u16::MAX as usize {
+            return Err(Error::new(ErrorKind::Unsupported,
+                "a zip can't hold more than 65535 files without ZIP64"));
+        }
+
+        let name = archive_name(path);
+        let name_len = match u16::try_from(name.len()) {
+            Ok(name_len) => name_len,
+            Err(_) => return Err(Error::new(ErrorKind::Unsupported, format!(
+                "{} has too long of a name to put in a zip", path.display()))),
+        };
+        let mut data = Spooled::new(data, size)?;
+
+        //Once anything is written, a failure leaves the zip without a way to be finished
+        let (crc, compressed_size) = self.write_entry(&name, name_len, size, &mut data)
+            .map_err(broken)?;
+        self.entries.push(ZipEntry {
+            name,
+            name_len,
+            crc,
+            compressed_size,
+            size: size as u32,
+            offset,
+        });
+        self.saved.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> IOResult<()> {
+        Err(Error::new(ErrorKind::Unsupported, format!(
+            "{} was already written to the zip and can't be removed", path.display())))
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+
+    ///Writes the central directory that lists every file
+    fn finish(&mut self) -> IOResult<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let directory_offset = fits_u32(self.out.count)?;
+        for entry in &self.entries {
+            write_u32(&mut self.out, CENTRAL_HEADER)?;
+            write_u16(&mut self.out, VERSION)?;
+            write_u16(&mut self.out, VERSION)?;
+            write_u16(&mut self.out, FLAGS)?;
+            write_u16(&mut self.out, DEFLATE)?;
+            write_u16(&mut self.out, TIME)?;
+            write_u16(&mut self.out, DATE)?;
+            write_u32(&mut self.out, entry.crc)?;
+            write_u32(&mut self.out, entry.compressed_size)?;
+            write_u32(&mut self.out, entry.size)?;
+            write_u16(&mut self.out, entry.name_len)?;
+            //No extra field, comment, disk number or attributes
+            self.out.write_all(&[0; 12])?;
+            write_u32(&mut self.out, entry.offset)?;
+            self.out.write_all(entry.name.as_bytes())?;
+        }
+        let directory_size = fits_u32(self.out.count)? - directory_offset;
+
+        //save() never lets there be more files than this can count
+        let count = match u16::try_from(self.entries.len()) {
+            Ok(count) => count,
+            Err(_) => return Err(Error::new(ErrorKind::Unsupported,
+                "a zip can't hold more than 65535 files without ZIP64")),
+        };
+        write_u32(&mut self.out, END_OF_CENTRAL_DIRECTORY)?;
+        //Everything is on the first disk
+        self.out.write_all(&[0; 4])?;
+        write_u16(&mut self.out, count)?;
+        write_u16(&mut self.out, count)?;
+        write_u32(&mut self.out, directory_size)?;
+        write_u32(&mut self.out, directory_offset)?;
+        write_u16(&mut self.out, 0)?;
+        self.out.flush()
+    }
+}
+
+///Keeps track of how many bytes have been written, since the zip needs offsets
+struct CountingWriter<W: Write> {
+    out: W,
+    count: u64,
+}
+
+impl <W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> IOResult<usize> {
+        let written = self.out.write(buffer)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.out.flush()
+    }
+}
+
+fn fits_u32(value: u64) -> IOResult<u32> {
+    if value > u32::MAX as u64 {
+        return Err(Error::new(ErrorKind::Unsupported, "the zip is too big without ZIP64"));
+    }
+    Ok(value as u32)
+}
+
+fn write_u16<W: Write>(out: &mut W, value: u16) -> IOResult<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(out: &mut W, value: u32) -> IOResult<()> {
+    out.write_all(&value.to_le_bytes())
+}
@@ -0,0 +1,100 @@
+use std::borrow::{Cow};
+use std::fs::{File};
+use std::io::{Result as IOResult, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Path};
+
+use memmap2::{Mmap};
+
+
+use super::{ReadStream, SliceStream, Stream};
+
+///A file that was opened with the fastest stream that works for it
+///
+///Files are memory mapped when they can be so that nothing gets copied while reading them.
+///Anything that can't be mapped (like some special files) is read through a buffer instead.
+pub enum FileStream {
+    Mapped(SliceStream<Mmap>),
+    Buffered(ReadStream<File>),
+}
+
+impl FileStream {
+    ///Opens the file at path for reading
+    pub fn open(path: &Path, little_endian: bool) -> IOResult<FileStream> {
+        let file = File::open(path)?;
+
+        //The file must not be changed by anyone else while it's mapped, which we can't promise.
+        // It's no worse than the file changing under a buffered read though.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => Ok(FileStream::Mapped(SliceStream::new(map, little_endian))),
+            Err(_) => Ok(FileStream::Buffered(ReadStream::new(file, little_endian))),
+        }
+    }
+}
+
+impl Stream for FileStream {
+    fn little_endian(&mut self, new: bool) {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.little_endian(new),
+            FileStream::Buffered(ref mut stream) => stream.little_endian(new),
+        }
+    }
+
+    fn is_little_endian(&self) -> bool {
+        match *self {
+            FileStream::Mapped(ref stream) => stream.is_little_endian(),
+            FileStream::Buffered(ref stream) => stream.is_little_endian(),
+        }
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        match *self {
+            FileStream::Mapped(ref mut stream) => Stream::seek(stream, pos),
+            FileStream::Buffered(ref mut stream) => Stream::seek(stream, pos),
+        }
+    }
+
+    fn skip(&mut self, count: i64) -> IOResult<()> {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.skip(count),
+            FileStream::Buffered(ref mut stream) => stream.skip(count),
+        }
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.read_into(buffer),
+            FileStream::Buffered(ref mut stream) => stream.read_into(buffer),
+        }
+    }
+
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>> {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.read_borrowed(size),
+            FileStream::Buffered(ref mut stream) => stream.read_borrowed(size),
+        }
+    }
+
+    fn pos(&mut self) -> u64 {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.pos(),
+            FileStream::Buffered(ref mut stream) => stream.pos(),
+        }
+    }
+
+    fn len(&mut self) -> u64 {
+        match *self {
+            FileStream::Mapped(ref mut stream) => stream.len(),
+            FileStream::Buffered(ref mut stream) => stream.len(),
+        }
+    }
+}
+
+impl Read for FileStream {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        match *self {
+            FileStream::Mapped(ref mut stream) => Read::read(stream, buffer),
+            FileStream::Buffered(ref mut stream) => Read::read(stream, buffer),
+        }
+    }
+}
@@ -1,14 +1,125 @@
+mod file;
+mod offset;
+mod slice;
 mod types;
 
+pub use self::file::{FileStream};
+pub use self::offset::{OffsetStream};
+pub use self::slice::{SliceStream};
 pub use self::types::{
     Readable,
     UnknownSizeReadable,
     UTF16LE,
 };
 
+use std::borrow::{Cow};
 use std::io::{BufReader, Result as IOResult, SeekFrom};
 use std::io::prelude::*;
 
+///The high level reading methods that every kind of stream gives
+///
+///Formats should be written against this trait so that they work with any of the streams.
+///An implementor only needs to know how to seek, read and skip, everything else builds on that.
+pub trait Stream {
+    ///Changes the stream to read ints as little endian if new is true
+    ///Uses big endian if false
+    fn little_endian(&mut self, new: bool);
+
+    fn is_little_endian(&self) -> bool;
+
+    ///Seeks to the offset given. Same as the Seek trait
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64>;
+
+    ///Moves the stream count bytes from where it is now
+    ///This can be faster than seek() since a buffered stream gets to keep its buffer
+    fn skip(&mut self, count: i64) -> IOResult<()>;
+
+    ///Reads exactly enough bytes to fill the buffer
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()>;
+
+    ///Reads the exact number of bytes as specified by size
+    ///Streams that already have everything in memory will give the bytes without copying them
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>>;
+
+    ///Gets the current position of the stream, from the start (ie. you can seek with
+    ///SeekFrom::Start(pos())) to get back to the current position
+    fn pos(&mut self) -> u64 {
+        //Unwrapping is safe here because nothing can go wrong
+        self.seek(SeekFrom::Current(0)).unwrap()
+    }
+
+    ///Returns the length of the entire stream
+    fn len(&mut self) -> u64 {
+        //Unwrapping in this function is safe because we are seeking to very defined values
+        //Save our current position
+        let current = self.pos();
+
+        //Seek to the end to get the size of the stream
+        let len = self.seek(SeekFrom::End(0)).unwrap();
+
+        //Get back to our original position
+        self.seek(SeekFrom::Start(current)).unwrap();
+        len
+    }
+
+    ///Returns true if the entire stream has no bytes in it
+    fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+
+    ///Will try to read the exact number of bytes as specified by size
+    ///This will return an error if the exact number couldn't be read
+    fn read_exact(&mut self, size: usize) -> IOResult<Vec<u8>> {
+        Ok(self.read_borrowed(size)?.into_owned())
+    }
+
+    /// Reads the given Readable from the stream
+    fn read<T: Readable>(&mut self) -> IOResult<T::Out> {
+        T::read_from(self)
+    }
+
+    /// Reads the given Readable from the stream with a supplied length
+    /// The length is how many Readables you want to get from the stream
+    fn read_with_len<T: UnknownSizeReadable>(&mut self, len: usize) -> IOResult<T::Out> {
+        T::with_len(self, len)
+    }
+}
+
+///A borrowed stream is still a stream, so it can be lent out without giving it away
+impl <S: Stream + ?Sized> Stream for &mut S {
+    fn little_endian(&mut self, new: bool) {
+        (**self).little_endian(new)
+    }
+
+    fn is_little_endian(&self) -> bool {
+        (**self).is_little_endian()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        (**self).seek(pos)
+    }
+
+    fn skip(&mut self, count: i64) -> IOResult<()> {
+        (**self).skip(count)
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
+        (**self).read_into(buffer)
+    }
+
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>> {
+        (**self).read_borrowed(size)
+    }
+
+    fn pos(&mut self) -> u64 {
+        (**self).pos()
+    }
+
+    fn len(&mut self) -> u64 {
+        (**self).len()
+    }
+}
+
 ///An adapter to a reader that can also seek
 ///This adapter provides high level reading methods from the underlying stream
 ///Uses a BufReader to wrap the given reader
@@ -25,65 +136,46 @@ impl <R: Read + Seek> ReadStream<R> {
             little_endian,
         }
     }
+}
 
-    ///Changes the stream to read ints as little endian if new is true
-    ///Uses big endian if false
-    pub fn little_endian(&mut self, new: bool) {
+impl <R: Read + Seek> Stream for ReadStream<R> {
+    fn little_endian(&mut self, new: bool) {
         self.little_endian = new;
     }
 
-    pub fn is_little_endian(&self) -> bool {
+    fn is_little_endian(&self) -> bool {
         self.little_endian
     }
 
-    ///Seeks to the offset given. Same as the Seek trait
-    pub fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
         self.stream.seek(pos)
     }
 
-    ///Gets the current position of the stream, from the start (ie. you can seek with
-    ///SeekFrom::Start(pos())) to get back to the current position
-    pub fn pos(&mut self) -> u64 {
-        //Unwrapping is safe here because nothing can go wrong
-        self.stream.seek(SeekFrom::Current(0)).unwrap()
+    fn skip(&mut self, count: i64) -> IOResult<()> {
+        self.stream.seek_relative(count)
     }
 
-    ///Returns the length of the entire stream
-    pub fn len(&mut self) -> u64 {
-        //Unwrapping in this function is safe because we are seeking to very defined values
-        //Save our current position
-        let current = self.pos();
-
-        //Seek to the end to get the size of the stream
-        let len = self.stream.seek(SeekFrom::End(0)).unwrap();
-
-        //Get back to our original position
-        self.stream.seek(SeekFrom::Start(current)).unwrap();
-        len
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
+        self.stream.read_exact(buffer)
     }
 
-    ///Will try to read the exact number of bytes as specified by size
-    ///This will return an error if the exact number couldn't be read
-    pub fn read_exact(&mut self, size: usize) -> IOResult<Vec<u8>> {
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>> {
         let mut bytes: Vec<u8> = vec![0; size];
         self.stream.read_exact(&mut bytes)?;
 
-        Ok(bytes)
-    }
-
-    pub fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
-        self.stream.read_exact(buffer)?;
-        Ok(())
+        Ok(Cow::Owned(bytes))
     }
 
-    /// Reads the given Readable from the stream
-    pub fn read<T: Readable>(&mut self) -> IOResult<T::Out> {
-        T::read_from(self)
+    fn pos(&mut self) -> u64 {
+        //Unwrapping is safe here because nothing can go wrong
+        self.stream.stream_position().unwrap()
     }
+}
 
-    /// Reads the given Readable from the stream with a supplied length
-    /// The length is how many Readables you want to get from the stream
-    pub fn read_with_len<T: UnknownSizeReadable>(&mut self, len: usize) -> IOResult<T::Out> {
-        T::with_len(self, len)
+///Lets the stream be used anywhere a plain reader can, like for streaming through a decompressor
+///Use take() to keep a reader from going past the data it should read
+impl <R: Read + Seek> Read for ReadStream<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        self.stream.read(buffer)
     }
 }
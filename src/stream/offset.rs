@@ -0,0 +1,94 @@
+use std::borrow::{Cow};
+use std::io::{Error, ErrorKind, Result as IOResult, SeekFrom};
+use std::io::prelude::*;
+
+
+use super::Stream;
+
+///A stream that starts partway into another stream, like an archive glued onto the end of an exe
+///
+///Every position is from the base offset, so a format can be read as if it was its own file.
+///Everything from the base to the end of the other stream is part of this one.
+pub struct OffsetStream<S: Stream> {
+    inner: S,
+    base: u64,
+}
+
+impl <S: Stream> OffsetStream<S> {
+    ///Creates a new OffsetStream that starts at base inside of inner
+    pub fn new(mut inner: S, base: u64) -> IOResult<Self> {
+        if base > inner.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "the base is past the end of the stream"));
+        }
+        inner.seek(SeekFrom::Start(base))?;
+
+        Ok(OffsetStream {
+            inner,
+            base,
+        })
+    }
+
+    ///Where this stream starts inside of the other stream
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    ///Gives back the other stream
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl <S: Stream> Stream for OffsetStream<S> {
+    fn little_endian(&mut self, new: bool) {
+        self.inner.little_endian(new);
+    }
+
+    fn is_little_endian(&self) -> bool {
+        self.inner.is_little_endian()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => self.base.checked_add(pos),
+            SeekFrom::End(offset) => self.inner.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.inner.pos().checked_add_signed(offset),
+        };
+
+        match new_pos {
+            Some(new_pos) if new_pos >= self.base => {
+                Ok(self.inner.seek(SeekFrom::Start(new_pos))? - self.base)
+            },
+            _ => Err(Error::new(ErrorKind::InvalidInput, "can't seek before the start")),
+        }
+    }
+
+    fn skip(&mut self, count: i64) -> IOResult<()> {
+        if count < 0 && self.pos() < count.unsigned_abs() {
+            return Err(Error::new(ErrorKind::InvalidInput, "can't seek before the start"));
+        }
+        self.inner.skip(count)
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
+        self.inner.read_into(buffer)
+    }
+
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>> {
+        self.inner.read_borrowed(size)
+    }
+
+    fn pos(&mut self) -> u64 {
+        self.inner.pos() - self.base
+    }
+
+    fn len(&mut self) -> u64 {
+        self.inner.len() - self.base
+    }
+}
+
+impl <S: Stream + Read> Read for OffsetStream<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        Read::read(&mut self.inner, buffer)
+    }
+}
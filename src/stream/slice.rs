@@ -0,0 +1,121 @@
+use std::borrow::{Cow};
+use std::cmp;
+use std::io::{Error, ErrorKind, Result as IOResult, SeekFrom};
+use std::io::prelude::*;
+
+
+use super::Stream;
+
+///A stream over bytes that are already in memory, like a memory mapped file or a decompressed index
+///
+///Nothing is ever copied out of the bytes unless asked for, so reading chunks from it is cheap.
+///Anything that can be seen as a byte slice can be used, whether it's owned or borrowed.
+pub struct SliceStream<D: AsRef<[u8]>> {
+    data: D,
+    pos: u64,
+    little_endian: bool,
+}
+
+impl <D: AsRef<[u8]>> SliceStream<D> {
+    ///Creates a new SliceStream, starting at the first byte
+    pub fn new(data: D, little_endian: bool) -> Self {
+        SliceStream {
+            data,
+            pos: 0,
+            little_endian,
+        }
+    }
+
+    ///Gives all of the bytes that haven't been read yet
+    pub fn remaining(&self) -> &[u8] {
+        let data = self.data.as_ref();
+        &data[cmp::min(self.pos, data.len() as u64) as usize..]
+    }
+
+    ///Gives the range for the next size bytes, moving past them
+    ///Nothing moves if there aren't enough bytes left
+    ///Being past the end after a seek is an error too, even for 0 bytes
+    fn advance(&mut self, size: usize) -> IOResult<(usize, usize)> {
+        if self.pos > self.data.as_ref().len() as u64 || self.remaining().len() < size {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "not enough bytes left in the slice"));
+        }
+
+        let start = self.pos as usize;
+        self.pos += size as u64;
+        Ok((start, start + size))
+    }
+}
+
+impl <'a> SliceStream<&'a [u8]> {
+    ///Reads the next size bytes as a slice that lives as long as the underlying bytes
+    pub fn read_slice(&mut self, size: usize) -> IOResult<&'a [u8]> {
+        let (start, end) = self.advance(size)?;
+        Ok(&self.data[start..end])
+    }
+
+    ///Creates a new stream for just the next size bytes, moving past them
+    ///The new stream starts at position 0 and has the same endianness
+    pub fn sub_stream(&mut self, size: usize) -> IOResult<SliceStream<&'a [u8]>> {
+        let little_endian = self.little_endian;
+        Ok(SliceStream::new(self.read_slice(size)?, little_endian))
+    }
+}
+
+impl <D: AsRef<[u8]>> Stream for SliceStream<D> {
+    fn little_endian(&mut self, new: bool) {
+        self.little_endian = new;
+    }
+
+    fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+        //Same as a Cursor; seeking past the end is fine but reading there isn't
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => (self.data.as_ref().len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        match new_pos {
+            Some(new_pos) => {
+                self.pos = new_pos;
+                Ok(new_pos)
+            },
+            None => Err(Error::new(ErrorKind::InvalidInput, "can't seek before the start")),
+        }
+    }
+
+    fn skip(&mut self, count: i64) -> IOResult<()> {
+        self.seek(SeekFrom::Current(count))?;
+        Ok(())
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> IOResult<()> {
+        let (start, end) = self.advance(buffer.len())?;
+        buffer.copy_from_slice(&self.data.as_ref()[start..end]);
+        Ok(())
+    }
+
+    fn read_borrowed(&mut self, size: usize) -> IOResult<Cow<'_, [u8]>> {
+        let (start, end) = self.advance(size)?;
+        Ok(Cow::Borrowed(&self.data.as_ref()[start..end]))
+    }
+
+    fn pos(&mut self) -> u64 {
+        self.pos
+    }
+
+    fn len(&mut self) -> u64 {
+        self.data.as_ref().len() as u64
+    }
+}
+
+impl <D: AsRef<[u8]>> Read for SliceStream<D> {
+    fn read(&mut self, buffer: &mut [u8]) -> IOResult<usize> {
+        let size = cmp::min(buffer.len(), self.remaining().len());
+        self.read_into(&mut buffer[..size])?;
+        Ok(size)
+    }
+}
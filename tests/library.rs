@@ -0,0 +1,1507 @@
+extern crate binaryflare;
+extern crate flate2;
+extern crate serde_json;
+
+use std::borrow::{Cow};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Cursor, ErrorKind, SeekFrom};
+use std::io::prelude::*;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use binaryflare::{Archive, ChecksumPolicy, FlareError, FlareOptions, Format, OverwritePolicy,
+    XP3ItemChunks, XP3Writer};
+use binaryflare::{file_utils, SymlinkPolicy, TextEncoding};
+use binaryflare::formats::{crypt, simple_crypt};
+use binaryflare::sink::{MemorySink, Sink, SpoolSink, TarSink, ZipSink};
+use binaryflare::stream::{FileStream, SliceStream, Stream};
+use flate2::{Compression};
+use flate2::read::{ZlibDecoder};
+use flate2::write::{ZlibEncoder};
+
+const TEXT: &[u8] = b"Some text that is long enough to be worth compressing. \
+    Some text that is long enough to be worth compressing.";
+
+///Makes an empty folder for a single test to work in
+fn test_folder(name: &str) -> PathBuf {
+    let folder = env::temp_dir().join(format!("binaryflare-{}-{}", name, std::process::id()));
+    if folder.exists() {
+        fs::remove_dir_all(&folder).unwrap();
+    }
+    fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+///Bytes that won't get smaller when compressed, so they're stored raw
+fn raw_bytes() -> Vec<u8> {
+    (0..200u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect()
+}
+
+///Writes an archive with one compressed item and one raw item
+fn make_archive(folder: &Path) -> PathBuf {
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_file("text/hello.txt", TEXT).unwrap();
+    writer.add_file("raw.bin", &raw_bytes()).unwrap();
+    let archive = writer.finish().unwrap().into_inner();
+
+    let path = folder.join("test.xp3");
+    File::create(&path).unwrap().write_all(&archive).unwrap();
+    path
+}
+
+///Gives a chunk with its name and size in front of the data
+fn xp3_chunk(name: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = name.to_vec();
+    chunk.extend(&(data.len() as u64).to_le_bytes());
+    chunk.extend(data);
+    chunk
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+///Gives an eliF chunk with the name for the adlr hash of the data
+fn elif_chunk(data: &[u8], name: &str) -> Vec<u8> {
+    let mut names = adler32(data).to_le_bytes().to_vec();
+    names.extend(&(name.encode_utf16().count() as u16).to_le_bytes());
+    names.extend(name.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+    xp3_chunk(b"eliF", &names)
+}
+
+///Builds an XP3 archive of raw files that only have the given names stored, which can be hashes
+///The first file is named by an eliF chunk in the index and the second by the Hxv4 table
+fn make_hashed_archive(files: &[(&str, &[u8])], first: &str, second: &str) -> Vec<u8> {
+    let mut archive = b"XP3\r\n \n\x1a\x8b\x67\x01".to_vec();
+    archive.extend(&[0; 8]);
+    let mut index = Vec::new();
+    for (name, data) in files {
+        let mut info = vec![0; 4];
+        info.extend(&(data.len() as u64).to_le_bytes());
+        info.extend(&(data.len() as u64).to_le_bytes());
+        info.extend(&(name.encode_utf16().count() as u16).to_le_bytes());
+        info.extend(name.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        let mut segm = vec![0; 4];
+        segm.extend(&(archive.len() as u64).to_le_bytes());
+        segm.extend(&(data.len() as u64).to_le_bytes());
+        segm.extend(&(data.len() as u64).to_le_bytes());
+
+        let mut file = xp3_chunk(b"info", &info);
+        file.extend(xp3_chunk(b"segm", &segm));
+        file.extend(xp3_chunk(b"adlr", &adler32(data).to_le_bytes()));
+        index.extend(xp3_chunk(b"File", &file));
+        archive.extend(*data);
+    }
+    index.extend(elif_chunk(files[0].1, first));
+
+    //The Hxv4 table is compressed
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&elif_chunk(files[1].1, second)).unwrap();
+    let table = encoder.finish().unwrap();
+    let mut hxv4 = (archive.len() as u64).to_le_bytes().to_vec();
+    hxv4.extend(&(table.len() as u32).to_le_bytes());
+    hxv4.extend(&1_u16.to_le_bytes());
+    index.extend(xp3_chunk(b"Hxv4", &hxv4));
+    archive.extend(table);
+
+    let index_offset = archive.len() as u64;
+    archive[11..19].copy_from_slice(&index_offset.to_le_bytes());
+    archive.push(0);
+    archive.extend(&(index.len() as u64).to_le_bytes());
+    archive.extend(index);
+    archive
+}
+
+///Builds an XP3 archive with a single item, big.bin, made of the segments in order
+///Each segment is zlib compressed if it's set to be
+fn make_segmented_archive(segments: &[(bool, &[u8])]) -> Vec<u8> {
+    let mut archive = b"XP3\r\n \n\x1a\x8b\x67\x01".to_vec();
+    archive.extend(&[0; 8]);
+    let mut segm = Vec::new();
+    let mut data = Vec::new();
+    let mut stored_size = 0;
+    for &(compressed, segment) in segments {
+        let stored = if compressed {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(segment).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            segment.to_vec()
+        };
+        segm.extend(&(compressed as u32).to_le_bytes());
+        segm.extend(&(archive.len() as u64).to_le_bytes());
+        segm.extend(&(segment.len() as u64).to_le_bytes());
+        segm.extend(&(stored.len() as u64).to_le_bytes());
+        archive.extend(&stored);
+        data.extend(segment);
+        stored_size += stored.len() as u64;
+    }
+
+    let mut info = vec![0; 4];
+    info.extend(&(data.len() as u64).to_le_bytes());
+    info.extend(&stored_size.to_le_bytes());
+    info.extend(&7_u16.to_le_bytes());
+    info.extend("big.bin".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+    let mut file = xp3_chunk(b"info", &info);
+    file.extend(xp3_chunk(b"segm", &segm));
+    file.extend(xp3_chunk(b"adlr", &adler32(&data).to_le_bytes()));
+    let index = xp3_chunk(b"File", &file);
+
+    let index_offset = archive.len() as u64;
+    archive[11..19].copy_from_slice(&index_offset.to_le_bytes());
+    archive.push(0);
+    archive.extend(&(index.len() as u64).to_le_bytes());
+    archive.extend(index);
+    archive
+}
+
+///Writes a little endian u16 or u32 at pos
+fn put(bytes: &mut [u8], pos: usize, value: u32, size: usize) {
+    bytes[pos..pos + size].copy_from_slice(&value.to_le_bytes()[..size]);
+}
+
+///Builds a resource directory for a section at rva
+///Each resource is its type, name (a number is an id), language and data, grouped by type in order
+fn make_resources(rva: u32, resources: &[(u32, &str, u32, &[u8])]) -> Vec<u8> {
+    let mut types: Vec<u32> = resources.iter().map(|resource| resource.0).collect();
+    types.dedup();
+
+    //Every directory comes first, then the data entries, the names and the data
+    let directories_size = 16 + 8 * types.len() + 16 * types.len() + 8 * resources.len() +
+        24 * resources.len();
+    let mut section = vec![0; directories_size + 16 * resources.len()];
+    put(&mut section, 14, types.len() as u32, 2);
+    let mut next_directory = 16 + 8 * types.len();
+    let mut index = 0;
+    for (type_index, &kind) in types.iter().enumerate() {
+        let names: Vec<&(u32, &str, u32, &[u8])> = resources.iter()
+            .filter(|resource| resource.0 == kind)
+            .collect();
+        put(&mut section, 16 + 8 * type_index, kind, 4);
+        put(&mut section, 20 + 8 * type_index, (1 << 31) | next_directory as u32, 4);
+        let name_directory = next_directory;
+        put(&mut section, name_directory + 14, names.len() as u32, 2);
+        next_directory += 16 + 8 * names.len();
+
+        for (name_index, &&(_, name, language, data)) in names.iter().enumerate() {
+            let entry = name_directory + 16 + 8 * name_index;
+            let id = match name.parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => {
+                    let name_offset = section.len();
+                    section.extend(&(name.len() as u16).to_le_bytes());
+                    section.extend(name.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+                    (1 << 31) | name_offset as u32
+                },
+            };
+            put(&mut section, entry, id, 4);
+            put(&mut section, entry + 4, (1 << 31) | next_directory as u32, 4);
+
+            //One language that points at the data entry
+            let data_entry = directories_size + 16 * index;
+            put(&mut section, next_directory + 14, 1, 2);
+            put(&mut section, next_directory + 16, language, 4);
+            put(&mut section, next_directory + 20, data_entry as u32, 4);
+            next_directory += 24;
+
+            section.resize(section.len().next_multiple_of(4), 0);
+            let data_rva = rva + section.len() as u32;
+            put(&mut section, data_entry, data_rva, 4);
+            put(&mut section, data_entry + 4, data.len() as u32, 4);
+            section.extend(data);
+            index += 1;
+        }
+    }
+    section
+}
+
+///Builds a 32 bit exe with a single resource section, followed by the overlay
+fn make_exe(resources: &[(u32, &str, u32, &[u8])], overlay: &[u8]) -> Vec<u8> {
+    const RVA: u32 = 0x1000;
+    let mut section = make_resources(RVA, resources);
+    let section_size = section.len();
+    section.resize(section_size.next_multiple_of(0x200), 0);
+
+    let mut exe = vec![0; 0x200];
+    exe[..2].copy_from_slice(b"MZ");
+    put(&mut exe, 0x3c, 0x40, 4);
+    exe[0x40..0x44].copy_from_slice(b"PE\0\0");
+    //The COFF header with one section and the optional header with 16 data directories
+    put(&mut exe, 0x44, 0x14c, 2);
+    put(&mut exe, 0x46, 1, 2);
+    put(&mut exe, 0x54, 224, 2);
+    put(&mut exe, 0x58, 0x10b, 2);
+    put(&mut exe, 0x58 + 60, 0x200, 4);
+    put(&mut exe, 0x58 + 92, 16, 4);
+    put(&mut exe, 0x58 + 96 + 16, RVA, 4);
+    put(&mut exe, 0x58 + 96 + 20, section_size as u32, 4);
+    //The section table
+    exe[0x138..0x13d].copy_from_slice(b".rsrc");
+    put(&mut exe, 0x140, section_size as u32, 4);
+    put(&mut exe, 0x144, RVA, 4);
+    put(&mut exe, 0x148, section.len() as u32, 4);
+    put(&mut exe, 0x14c, 0x200, 4);
+
+    exe.extend(section);
+    exe.extend(overlay);
+    exe
+}
+
+///Fails every read, for data that breaks partway through
+struct BrokenReader;
+
+impl Read for BrokenReader {
+    fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("the data broke"))
+    }
+}
+
+///Saves into memory, except that saving the file named fail gives an error of the given kind
+struct FailingSink {
+    files: MemorySink,
+    fail: &'static str,
+    kind: ErrorKind,
+}
+
+impl Sink for FailingSink {
+    fn exists(&mut self, path: &Path) -> bool {
+        self.files.exists(path)
+    }
+
+    fn save(&mut self, path: &Path, size: u64, data: &mut dyn Read) -> io::Result<()> {
+        if path == Path::new(self.fail) {
+            return Err(io::Error::new(self.kind, "can't save this one"));
+        }
+        self.files.save(path, size, data)
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        self.files.remove(path)
+    }
+
+    fn location(&self, path: &Path) -> PathBuf {
+        self.files.location(path)
+    }
+}
+
+fn read_entry(archive: &Archive, name: &str) -> Vec<u8> {
+    let mut contents = Vec::new();
+    archive.entry(name).unwrap().read_to_end(&mut contents).unwrap();
+    contents
+}
+
+///Builds a 2x2 TLG5 image where every pixel is BGRA 10, 20, 30, 40
+///The channels are compressed with literals only if compressed is set
+fn make_tlg5(compressed: bool) -> Vec<u8> {
+    let mut image = b"TLG5.0\x00raw\x1a\x04".to_vec();
+    for value in &[2_u32, 2, 2, 0] {
+        image.extend(&value.to_le_bytes());
+    }
+    //Blue and red have green taken out, and only the first pixel isn't from its neighbours
+    for first in &[246, 20, 10, 40] {
+        let channel = [*first, 0, 0, 0];
+        if compressed {
+            image.push(0);
+            image.extend(&5_u32.to_le_bytes());
+            image.push(0);
+        } else {
+            image.push(1);
+            image.extend(&4_u32.to_le_bytes());
+        }
+        image.extend(&channel);
+    }
+    image
+}
+
+///Builds a 1x1 TLG6 image with the given BGRA pixel, which needs to be below 17 in every channel
+fn make_tlg6(pixel: [u8; 4]) -> Vec<u8> {
+    let mut image = b"TLG6.0\x00raw\x1a\x04\x00\x00\x00".to_vec();
+    for value in &[1_u32, 1, 8] {
+        image.extend(&value.to_le_bytes());
+    }
+    //One block with the plain MED filter, as a single literal
+    image.extend(&2_u32.to_le_bytes());
+    image.extend(&[0, 0]);
+    for &value in &pixel {
+        //A run of one value that has 2 * value - 1 zeros before its one bit
+        let bit_length = 2 * value as usize + 2;
+        let mut bits = vec![0_u8; bit_length.div_ceil(8)];
+        for bit in &[0, 1, bit_length - 1] {
+            bits[bit / 8] |= 1 << (bit % 8);
+        }
+        image.extend(&(bit_length as u32).to_le_bytes());
+        image.extend(&bits);
+    }
+    image
+}
+
+///Encodes an array of unsigned ints the way a PSB keeps them, with 4 bytes for everything
+fn psb_array(values: &[u32]) -> Vec<u8> {
+    let mut array = vec![0x10];
+    array.extend(&(values.len() as u32).to_le_bytes());
+    array.push(0x10);
+    for value in values {
+        array.extend(&value.to_le_bytes());
+    }
+    array
+}
+
+///Encodes a list or object of a PSB, which is the offset of every value then the values
+fn psb_values(values: &[Vec<u8>]) -> Vec<u8> {
+    let mut offsets = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    for value in values {
+        offsets.push(data.len() as u32);
+        data.extend(value);
+    }
+    let mut encoded = psb_array(&offsets);
+    encoded.extend(data);
+    encoded
+}
+
+///Builds the name trie of a PSB, giving a node to each byte of every name under its parent
+fn psb_names(names: &[&str]) -> Vec<u8> {
+    let mut charset = vec![0];
+    let mut nodes = vec![0];
+    let mut ends = Vec::new();
+    for name in names {
+        let mut node = 0;
+        for byte in name.bytes().chain(Some(0)) {
+            if charset[node] == 0 {
+                charset[node] = nodes.len() as u32;
+                nodes.resize(nodes.len() + 256, 0);
+                charset.resize(nodes.len(), 0);
+            }
+            let child = charset[node] as usize + byte as usize;
+            nodes[child] = node as u32;
+            node = child;
+        }
+        ends.push(node as u32);
+    }
+    let mut trie = psb_array(&charset);
+    trie.extend(psb_array(&nodes));
+    trie.extend(psb_array(&ends));
+    trie
+}
+
+///Builds a version 2 PSB out of its tables and the encoded root
+fn make_psb(names: &[&str], strings: &[&str], root: &[u8], resources: &[&[u8]]) -> Vec<u8> {
+    let mut string_offsets = Vec::new();
+    let mut string_data = Vec::new();
+    for string in strings {
+        string_offsets.push(string_data.len() as u32);
+        string_data.extend(string.bytes().chain(Some(0)));
+    }
+    let mut resource_offsets = Vec::new();
+    let mut resource_data = Vec::new();
+    for resource in resources {
+        resource_offsets.push(resource_data.len() as u32);
+        resource_data.extend(*resource);
+    }
+    let lengths: Vec<u32> = resources.iter().map(|resource| resource.len() as u32).collect();
+
+    //Every part after the 40 byte header, in the order of the header's offsets
+    let parts = [psb_names(names), psb_array(&string_offsets), string_data,
+        psb_array(&resource_offsets), psb_array(&lengths), resource_data, root.to_vec()];
+    let mut psb = b"PSB\x00\x02\x00\x00\x00".to_vec();
+    psb.extend(&40_u32.to_le_bytes());
+    let mut offset = 40;
+    for part in &parts {
+        psb.extend(&(offset as u32).to_le_bytes());
+        offset += part.len();
+    }
+    for part in &parts {
+        psb.extend(part);
+    }
+    psb
+}
+
+///Builds compiled TJS2 with a top level that calls greet("hello") and a greet that returns 42
+fn make_tjs2() -> Vec<u8> {
+    fn words(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+    fn shorts(values: &[i16]) -> Vec<u8> {
+        let mut data: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        data.resize(data.len().div_ceil(4) * 4, 0);
+        data
+    }
+    fn string(value: &str) -> Vec<u8> {
+        let utf16: Vec<i16> = value.encode_utf16().map(|unit| unit as i16).collect();
+        let mut data = words(&[utf16.len() as i32]);
+        data.extend(shorts(&utf16));
+        data
+    }
+    //Everything in an object after its header, with no source map, getters or properties
+    fn object(header: &[i32], code: &[i16], constants: &[i16]) -> Vec<u8> {
+        let mut data = words(header);
+        data.extend(words(&[0, code.len() as i32]));
+        data.extend(shorts(code));
+        data.extend(words(&[constants.len() as i32 / 2]));
+        data.extend(shorts(constants));
+        data.extend(words(&[0, 0]));
+        let mut object = b"TJS2".to_vec();
+        object.extend(words(&[data.len() as i32]));
+        object.extend(data);
+        object
+    }
+
+    //No bytes, shorts, longs, reals or octets
+    let mut pools = words(&[0, 0, 1, 42, 0, 0, 2]);
+    pools.extend(string("hello"));
+    pools.extend(string("greet"));
+    pools.extend(words(&[0]));
+    let mut objects = words(&[0, 2]);
+    objects.extend(object(&[-1, -1, 0, 0, 0, 3, 0, -1, -1, -1, -1, -1],
+        &[1, 1, 0, 100, 2, 1, 1, 1, 1, 118, 2, 119], &[3, 0, 3, 1, 2, 1]));
+    objects.extend(object(&[0, 1, 1, 1, 0, 1, 1, -1, -1, -1, -1, -1],
+        &[17, 2, 1, 0, 0, 118, 0, 119], &[8, 0]));
+
+    let mut tjs = b"TJS2100\x00".to_vec();
+    let size = 12 + 8 + pools.len() + 8 + objects.len();
+    tjs.extend(words(&[size as i32]));
+    tjs.extend(b"DATA");
+    tjs.extend(words(&[pools.len() as i32 + 8]));
+    tjs.extend(pools);
+    tjs.extend(b"OBJS");
+    tjs.extend(words(&[objects.len() as i32]));
+    tjs.extend(objects);
+    tjs
+}
+
+///Gives the size and RGBA pixels of a PNG that was written without filters
+fn decode_png(png: &[u8]) -> (u32, u32, Vec<u8>) {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut pos = 8;
+    let (mut width, mut height, mut data) = (0, 0, Vec::new());
+    while pos < png.len() {
+        let size = u32::from_be_bytes([png[pos], png[pos + 1], png[pos + 2], png[pos + 3]]) as usize;
+        let chunk = &png[pos + 8..pos + 8 + size];
+        match &png[pos + 4..pos + 8] {
+            b"IHDR" => {
+                width = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                height = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                assert_eq!(&chunk[8..], &[8, 6, 0, 0, 0]);
+            },
+            b"IDAT" => data.extend(chunk),
+            _ => {},
+        }
+        pos += size + 12;
+    }
+
+    let mut rows = Vec::new();
+    ZlibDecoder::new(&data[..]).read_to_end(&mut rows).unwrap();
+    let pixels = rows.chunks(width as usize * 4 + 1).flat_map(|row| {
+        assert_eq!(row[0], 0);
+        row[1..].to_vec()
+    }).collect();
+    (width, height, pixels)
+}
+
+#[test]
+fn detects_xp3() {
+    let folder = test_folder("detects");
+    let path = make_archive(&folder);
+    let formats = binaryflare::guess_format(&path).unwrap();
+    assert_eq!(formats.len(), 1);
+    assert!(matches!(formats[0], Format::XP3Archive));
+
+    let other = folder.join("other.txt");
+    File::create(&other).unwrap().write_all(TEXT).unwrap();
+    assert!(binaryflare::guess_format(&other).unwrap().is_empty());
+    assert!(Archive::open(&other).unwrap().is_none());
+}
+
+#[test]
+fn ranks_candidates() {
+    let folder = test_folder("ranks");
+    let path = make_archive(&folder);
+    let candidates = binaryflare::identify(&path).unwrap();
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].format, Format::XP3Archive);
+    assert_eq!(candidates[0].confidence, 100);
+    assert!(candidates[0].extension_match);
+
+    //Hidden behind an exe is a little less sure
+    let exe = make_exe(&[], &fs::read(&path).unwrap());
+    let exe_path = folder.join("game.bin");
+    fs::write(&exe_path, &exe).unwrap();
+    let candidates = binaryflare::identify(&exe_path).unwrap();
+    let found: Vec<(Format, u8)> = candidates.iter()
+        .map(|candidate| (candidate.format, candidate.confidence))
+        .collect();
+    assert_eq!(found, [(Format::PEExecutable, 100), (Format::XP3Archive, 90)]);
+    assert!(!candidates[0].extension_match);
+
+    //Just the magic with an index that points nowhere is still an XP3, only a broken one
+    let mut broken = fs::read(&path).unwrap();
+    broken.truncate(19);
+    let broken_path = folder.join("broken.dat");
+    fs::write(&broken_path, &broken).unwrap();
+    let candidates = binaryflare::identify(&broken_path).unwrap();
+    assert_eq!(candidates[0].confidence, 60);
+    broken.truncate(11);
+    fs::write(&broken_path, &broken).unwrap();
+    assert_eq!(binaryflare::identify(&broken_path).unwrap()[0].confidence, 60);
+}
+
+#[test]
+fn carves_embedded_archives() {
+    let folder = test_folder("carves");
+    let archive = fs::read(make_archive(&folder)).unwrap();
+
+    //Two archives glued between junk, with some magic that doesn't lead anywhere
+    let mut blob = b"MZjunk".to_vec();
+    blob.extend(&archive[..11]);
+    blob.extend(vec![0xff; 101]);
+    let first = blob.len() as u64;
+    blob.extend(&archive);
+    blob.extend(vec![0; 77]);
+    let second = blob.len() as u64;
+    blob.extend(&archive);
+    let path = folder.join("blob.bin");
+    fs::write(&path, &blob).unwrap();
+
+    let hits = binaryflare::carve(&path).unwrap();
+    let offsets: Vec<u64> = hits.iter().map(|hit| hit.offset).collect();
+    assert_eq!(offsets, [first, second]);
+    assert!(hits.iter().all(|hit| hit.format == Format::XP3Archive && hit.confidence == 100));
+
+    let carved = Archive::open_at(&path, second, Format::XP3Archive, FlareOptions::default());
+    assert_eq!(carved.entries().unwrap().len(), 2);
+    assert_eq!(read_entry(&carved, "raw.bin"), raw_bytes());
+    let mut sink = MemorySink::new();
+    let report = carved.extract_to(&mut sink).unwrap();
+    assert_eq!(sink.files()[Path::new("text/hello.txt")], TEXT);
+    //Offsets are from the start of the whole file
+    assert!(report.entries.iter().all(|entry| entry.offset > second));
+}
+
+#[test]
+fn flares_exe_resources() {
+    let folder = test_folder("exe");
+    let archive = fs::read(make_archive(&folder)).unwrap();
+
+    //An icon group with one image, version 1.2.3.4 and some named data
+    let mut group = vec![0, 0, 1, 0, 1, 0, 16, 16, 0, 0, 1, 0, 32, 0];
+    group.extend(&[0xff, 0, 0, 0, 7, 0]);
+    let image = [0x28; 40];
+    let mut version = vec![0; 92];
+    put(&mut version, 40, 0xfeef_04bd, 4);
+    put(&mut version, 48, (1 << 16) | 2, 4);
+    put(&mut version, 52, (3 << 16) | 4, 4);
+    let resources: &[(u32, &str, u32, &[u8])] = &[
+        (3, "7", 1033, &image),
+        (10, "SCRIPT", 1041, b"rcdata!"),
+        (14, "MAINICON", 1033, &group),
+        (16, "1", 1033, &version),
+    ];
+    let path = folder.join("game.exe");
+    fs::write(&path, make_exe(resources, &archive)).unwrap();
+    let formats = binaryflare::guess_format(&path).unwrap();
+    assert_eq!(formats, [Format::XP3Archive, Format::PEExecutable]);
+
+    //The archive in the overlay is read by the XP3 format instead of being saved
+    let exe = Archive::open_with(&path, Format::PEExecutable, FlareOptions::default());
+    let mut sink = MemorySink::new();
+    let report = exe.extract_to(&mut sink).unwrap();
+    let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["resources/GROUP_ICON/MAINICON_1033.ico", "resources/RCDATA/SCRIPT_1041.bin",
+        "resources/VERSION/1_1033.bin"]);
+    assert_eq!(report.entries[2].attributes["file_version"], "1.2.3.4");
+    let files = sink.into_files();
+    assert_eq!(files[Path::new("resources/RCDATA/SCRIPT_1041.bin")], b"rcdata!");
+    let icon = &files[Path::new("resources/GROUP_ICON/MAINICON_1033.ico")];
+    assert_eq!(icon.len(), 6 + 16 + image.len());
+    assert_eq!(&icon[..6], &group[..6]);
+    assert_eq!(&icon[22..], &image[..]);
+    assert_eq!(read_entry(&exe, "resources/RCDATA/SCRIPT_1041.bin"), b"rcdata!");
+
+    let xp3 = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    assert_eq!(read_entry(&xp3, "raw.bin"), raw_bytes());
+
+    //Any other overlay is saved as it is
+    fs::write(&path, make_exe(&[], b"payload")).unwrap();
+    let exe = Archive::open_with(&path, Format::PEExecutable, FlareOptions::default());
+    assert_eq!(read_entry(&exe, "overlay.bin"), b"payload");
+}
+
+#[test]
+fn keeps_flaring_exes_with_broken_resources() {
+    let folder = test_folder("exe_loop");
+    let archive = fs::read(make_archive(&folder)).unwrap();
+    let path = folder.join("game.exe");
+    let resources: &[(u32, &str, u32, &[u8])] = &[(10, "SCRIPT", 1041, b"rcdata!")];
+
+    //Point the only type back at the root directory, which is at the start of the section
+    let mut exe = make_exe(resources, &archive);
+    put(&mut exe, 0x200 + 20, 1 << 31, 4);
+    fs::write(&path, &exe).unwrap();
+    let pe = Archive::open_with(&path, Format::PEExecutable, FlareOptions::default());
+    let report = pe.extract_to(&mut MemorySink::new()).unwrap();
+    assert!(report.entries.is_empty());
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "resources");
+    assert!(report.failed[0].1.contains("more than once"));
+    assert_eq!(pe.verify().unwrap(), ["resources"]);
+    //The archive in the overlay is still there
+    let xp3 = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    assert_eq!(read_entry(&xp3, "raw.bin"), raw_bytes());
+
+    //A resource whose data isn't in the file is the only one that's lost
+    let resources: &[(u32, &str, u32, &[u8])] = &[
+        (10, "SCRIPT", 1041, b"rcdata!"),
+        (10, "OTHER", 1041, b"other"),
+    ];
+    let mut exe = make_exe(resources, &[]);
+    let data_entry = 16 + 8 + 16 + 8 * 2 + 24 * 2;
+    put(&mut exe, 0x200 + data_entry + 4, 0x1000_0000, 4);
+    fs::write(&path, &exe).unwrap();
+    let pe = Archive::open_with(&path, Format::PEExecutable, FlareOptions::default());
+    let report = pe.extract_to(&mut MemorySink::new()).unwrap();
+    let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["resources/RCDATA/OTHER_1041.bin"]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "resources/RCDATA/SCRIPT_1041.bin");
+
+    //Headers that don't make sense still have the whole file searched for an XP3 archive
+    let mut exe = vec![0; 0x40];
+    exe[..2].copy_from_slice(b"MZ");
+    exe.extend(&archive);
+    fs::write(&path, &exe).unwrap();
+    let xp3 = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    assert_eq!(read_entry(&xp3, "raw.bin"), raw_bytes());
+}
+
+#[test]
+fn decodes_tlg_images() {
+    let folder = test_folder("tlg");
+    let path = folder.join("image.tlg");
+    for compressed in &[false, true] {
+        fs::write(&path, make_tlg5(*compressed)).unwrap();
+        assert_eq!(binaryflare::guess_format(&path).unwrap(), [Format::TLGImage]);
+        let image = Archive::open(&path).unwrap().unwrap();
+        assert_eq!(decode_png(&read_entry(&image, "image.png")), (2, 2, [30, 20, 10, 40].repeat(4)));
+    }
+
+    fs::write(&path, make_tlg6([3, 6, 9, 12])).unwrap();
+    let image = Archive::open_with(&path, Format::TLGImage, FlareOptions::default());
+    assert_eq!(decode_png(&read_entry(&image, "image.png")), (1, 1, vec![9, 6, 3, 12]));
+    //Listing doesn't decode the image, so it gives the size that's stored
+    let entries = image.entries().unwrap();
+    assert_eq!(entries[0].original_size, make_tlg6([3, 6, 9, 12]).len() as u64);
+
+    //The wrapper keeps its tags as attributes
+    let tags = b"4:mode=3:add,";
+    let mut wrapped = b"TLG0.0\x00sds\x1a".to_vec();
+    let raw = make_tlg5(true);
+    wrapped.extend(&(raw.len() as u32).to_le_bytes());
+    wrapped.extend(&raw);
+    wrapped.extend(b"tags");
+    wrapped.extend(&(tags.len() as u32).to_le_bytes());
+    wrapped.extend(tags);
+    fs::write(&path, wrapped).unwrap();
+    let image = Archive::open(&path).unwrap().unwrap();
+    let mut sink = MemorySink::new();
+    let report = image.extract_to(&mut sink).unwrap();
+    assert_eq!(report.entries[0].attributes["tag:mode"], "add");
+    assert_eq!(report.entries[0].attributes["version"], "TLG5");
+    let png = &sink.into_files()[Path::new("image.png")];
+    assert_eq!(decode_png(png).2, [30, 20, 10, 40].repeat(4));
+
+    //Pixels past the end of the data are an error rather than a guess
+    let mut truncated = make_tlg5(false);
+    truncated.truncate(truncated.len() - 2);
+    fs::write(&path, truncated).unwrap();
+    let image = Archive::open_with(&path, Format::TLGImage, FlareOptions::default());
+    assert!(image.verify().is_err());
+
+    //A single row that's as wide as an image can be needs too much memory for its buffers
+    let mut tlg5 = make_tlg5(false);
+    tlg5[12..16].copy_from_slice(&(1_u32 << 27).to_le_bytes());
+    tlg5[16..20].copy_from_slice(&1_u32.to_le_bytes());
+    let mut tlg6 = make_tlg6([0; 4]);
+    tlg6[15..19].copy_from_slice(&(1_u32 << 27).to_le_bytes());
+    for image in &[tlg5, tlg6] {
+        fs::write(&path, image).unwrap();
+        let image = Archive::open_with(&path, Format::TLGImage, FlareOptions::default());
+        match image.verify() {
+            Err(FlareError::Malformed { reason, .. }) => assert!(reason.contains("buffers")),
+            other => panic!("The image was decoded as {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn flares_psb_trees() {
+    let folder = test_folder("psb");
+    let image = make_tlg5(false);
+    //A pimg with one layer, using every name in order
+    let names = ["0.tlg", "height", "id", "layer_id", "layers", "name", "opacity", "width"];
+    let layer = psb_values(&[vec![0x04], vec![0x15, 1], vec![0x05, 0xff], vec![0x05, 2]]);
+    let mut layer_object = vec![0x21];
+    layer_object.extend(psb_array(&[3, 5, 6, 7]));
+    layer_object.extend(layer);
+    let mut layers = vec![0x20];
+    layers.extend(psb_values(&[layer_object]));
+    let mut root = vec![0x21];
+    root.extend(psb_array(&[0, 1, 2, 4]));
+    root.extend(psb_values(&[vec![0x19, 0], vec![0x05, 2], vec![0x15, 0], layers]));
+    let psb = make_psb(&names, &["pimg", "base"], &root, &[&image]);
+
+    let path = folder.join("image.pimg");
+    fs::write(&path, &psb).unwrap();
+    assert_eq!(binaryflare::guess_format(&path).unwrap(), [Format::PSBContainer]);
+    let pimg = Archive::open(&path).unwrap().unwrap();
+    let mut sink = MemorySink::new();
+    let report = pimg.extract_to(&mut sink).unwrap();
+    let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["tree.json", "resources/0.tlg", "layers/0_base.png"]);
+    assert_eq!(report.entries[0].attributes["id"], "pimg");
+    assert_eq!(report.entries[2].attributes["opacity"], "-1");
+
+    let files = sink.into_files();
+    let tree: serde_json::Value = serde_json::from_slice(&files[Path::new("tree.json")]).unwrap();
+    assert_eq!(tree["0.tlg"], "#resource#0");
+    assert_eq!(tree["height"], 2);
+    assert_eq!(tree["layers"][0]["name"], "base");
+    assert_eq!(tree["layers"][0]["layer_id"], 0);
+    assert_eq!(files[Path::new("resources/0.tlg")], image);
+    let (_, _, pixels) = decode_png(&files[Path::new("layers/0_base.png")]);
+    assert_eq!(pixels, [30, 20, 10, 40].repeat(4));
+
+    //Compressed PSBs are read the same way
+    let mut encoder = ZlibEncoder::new(b"mdf\x00".to_vec(), Compression::default());
+    encoder.get_mut().extend(&(psb.len() as u32).to_le_bytes());
+    encoder.write_all(&psb).unwrap();
+    fs::write(&path, encoder.finish().unwrap()).unwrap();
+    let pimg = Archive::open(&path).unwrap().unwrap();
+    assert_eq!(read_entry(&pimg, "resources/0.tlg"), image);
+    assert_eq!(decode_png(&read_entry(&pimg, "layers/0_base.png")).2, [30, 20, 10, 40].repeat(4));
+}
+
+#[test]
+fn keeps_flaring_psbs_with_broken_layers() {
+    let folder = test_folder("psb_broken");
+    let path = folder.join("image.pimg");
+    let mut image = make_tlg5(false);
+    image.truncate(image.len() - 2);
+    let names = ["0.tlg", "layer_id", "layers"];
+    let mut layer = vec![0x21];
+    layer.extend(psb_array(&[1]));
+    layer.extend(psb_values(&[vec![0x04]]));
+    let mut layers = vec![0x20];
+    layers.extend(psb_values(&[layer]));
+    let mut root = vec![0x21];
+    root.extend(psb_array(&[0, 2]));
+    root.extend(psb_values(&[vec![0x19, 0], layers]));
+    let psb = make_psb(&names, &[], &root, &[&image]);
+
+    //The layer that can't be decoded is the only thing that's lost
+    fs::write(&path, &psb).unwrap();
+    let pimg = Archive::open_with(&path, Format::PSBContainer, FlareOptions::default());
+    let report = pimg.extract_to(&mut MemorySink::new()).unwrap();
+    let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["tree.json", "resources/0.tlg"]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "layers/0.png");
+    assert_eq!(pimg.verify().unwrap(), ["layers/0.png"]);
+
+    //Offsets that can't be anywhere in a file are malformed, for resources and values alike
+    let huge_array = |extra: &[u8]| {
+        let mut array = extra.to_vec();
+        array.extend(&[0x0d, 1, 0x14]);
+        array.extend(&u64::MAX.to_le_bytes());
+        array
+    };
+    let mut resources = psb.clone();
+    let end = resources.len() as u32;
+    resources[24..28].copy_from_slice(&end.to_le_bytes());
+    resources.extend(huge_array(&[]));
+    let mut values = psb.clone();
+    let end = values.len() as u32;
+    values[36..40].copy_from_slice(&end.to_le_bytes());
+    values.extend(huge_array(&[0x20]));
+    for psb in &[resources, values] {
+        fs::write(&path, psb).unwrap();
+        let pimg = Archive::open_with(&path, Format::PSBContainer, FlareOptions::default());
+        match pimg.entries() {
+            Err(FlareError::Malformed { reason, .. }) => assert!(reason.contains("past the end")),
+            other => panic!("The offset was read as {:?}", other.map(|items| items.len())),
+        }
+    }
+}
+
+#[test]
+fn disassembles_tjs2_bytecode() {
+    let folder = test_folder("tjs");
+    let path = folder.join("startup.tjs");
+    fs::write(&path, make_tjs2()).unwrap();
+    assert_eq!(binaryflare::guess_format(&path).unwrap(), [Format::TJS2Bytecode]);
+
+    let script = Archive::open(&path).unwrap().unwrap();
+    let names: Vec<String> = script.entries().unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, ["0000_global.txt", "0001_greet.txt"]);
+
+    let top_level = String::from_utf8(read_entry(&script, "0000_global.txt")).unwrap();
+    assert!(top_level.contains("// context: top level\n"));
+    assert!(top_level.contains("*2 = object 1 (greet)\n"));
+    assert!(top_level.contains("00000000 const %1, *0 // *0 = \"hello\"\n"));
+    assert!(top_level.contains("00000003 calld %2, %1, *1, (%1) // *1 = \"greet\"\n"));
+    assert!(top_level.contains("00000009 srv %2\n00000011 ret\n"));
+
+    let greet = String::from_utf8(read_entry(&script, "0001_greet.txt")).unwrap();
+    assert!(greet.contains("// parent: 0 (global)\n"));
+    assert!(greet.contains("00000000 jmp 00000002\n00000002 const %0, *0 // *0 = 42\n"));
+}
+
+#[test]
+fn decrypts_simple_crypt_text() {
+    let folder = test_folder("simple_crypt");
+    let path = folder.join("scenario.ks");
+    let text = "*start\r\n[cm]こんにちは\r\n";
+    for mode in &[simple_crypt::Mode::Xor, simple_crypt::Mode::SwapBits,
+        simple_crypt::Mode::Compressed] {
+        let encrypted = simple_crypt::encode(text, *mode);
+        assert_eq!(simple_crypt::decode(&encrypted).unwrap(), (*mode, String::from(text)));
+        fs::write(&path, &encrypted).unwrap();
+        assert_eq!(binaryflare::guess_format(&path).unwrap(), [Format::SimpleCryptText]);
+        let script = Archive::open(&path).unwrap().unwrap();
+        assert_eq!(read_entry(&script, "text.txt"), text.as_bytes());
+    }
+
+    let options = FlareOptions {
+        text_encoding: TextEncoding::Utf16,
+        ..FlareOptions::default()
+    };
+    let script = Archive::open_with(&path, Format::SimpleCryptText, options);
+    let utf16 = read_entry(&script, "text.txt");
+    assert_eq!(&utf16[..2], [0xff, 0xfe]);
+    //Text saved as UTF-16 is read back in the same as UTF-8
+    assert_eq!(simple_crypt::plain_text(&utf16), text);
+}
+
+#[test]
+fn resolves_hashed_names() {
+    let folder = test_folder("hashed");
+    let path = folder.join("data.xp3");
+    let files: [(&str, &[u8]); 5] = [("", b"first"), ("", b"second"), ("", b"same"), ("", b"same"),
+        ("0A1B2C3D", b"listed")];
+    fs::write(&path, make_hashed_archive(&files, "scenario/first.ks", "image/second.txt")).unwrap();
+
+    let archive = Archive::open(&path).unwrap().unwrap();
+    let names: Vec<String> = archive.entries().unwrap().into_iter().map(|entry| entry.name).collect();
+    let same = format!("unnamed/{:08x}", adler32(b"same"));
+    assert_eq!(names, [String::from("scenario/first.ks"), String::from("image/second.txt"),
+        same.clone(), format!("{}_1", same), String::from("0A1B2C3D")]);
+    assert_eq!(read_entry(&archive, "image/second.txt"), b"second");
+
+    //The name list can use the stored hash or the adlr hash
+    let mut options = FlareOptions::default();
+    options.names.extend(&format!("0a1b2c3d\tsound/listed.txt\n{:08x},other/same.txt\n",
+        adler32(b"same")));
+    let archive = Archive::open_with(&path, Format::XP3Archive, options);
+    let names: Vec<String> = archive.entries().unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names[2..], ["other/same.txt", "other/same_1.txt", "sound/listed.txt"]);
+    assert_eq!(read_entry(&archive, "other/same_1.txt"), b"same");
+    let mut sink = MemorySink::new();
+    let report = archive.extract_to(&mut sink).unwrap();
+    let entries: Vec<(&str, Option<&String>)> = report.entries.iter()
+        .map(|entry| (entry.name.as_str(), entry.attributes.get("stored_name")))
+        .collect();
+    let empty = String::new();
+    let hash = String::from("0A1B2C3D");
+    assert_eq!(entries[2..], [("other/same.txt", Some(&empty)), ("other/same_1.txt", Some(&empty)),
+        ("sound/listed.txt", Some(&hash))]);
+    assert_eq!(sink.into_files()[Path::new("sound/listed.txt")], b"listed");
+}
+
+#[test]
+fn lists_entries() {
+    let folder = test_folder("lists");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+    let entries = archive.entries().unwrap();
+
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["text/hello.txt", "raw.bin"]);
+    assert_eq!(entries[0].original_size, TEXT.len() as u64);
+    assert_eq!(entries[0].compressed_segments, [true]);
+    assert_eq!(entries[1].compressed_segments, [false]);
+}
+
+#[test]
+fn reads_single_entries() {
+    let folder = test_folder("reads");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+
+    assert_eq!(read_entry(&archive, "text/hello.txt"), TEXT);
+    assert_eq!(read_entry(&archive, "raw.bin"), raw_bytes());
+    match archive.entry("missing.txt") {
+        Err(FlareError::NotFound { ref name }) => assert_eq!(name, "missing.txt"),
+        _ => panic!("a missing entry should be NotFound"),
+    }
+}
+
+#[test]
+fn reads_encrypted_entries() {
+    let folder = test_folder("encrypted");
+    let options = FlareOptions {
+        crypt: crypt::from_name("xor-key:ff"),
+        ..FlareOptions::default()
+    };
+    let archive = Archive::open_with(&make_archive(&folder), Format::XP3Archive, options);
+
+    let decrypted: Vec<u8> = TEXT.iter().map(|byte| byte ^ 0xff).collect();
+    assert_eq!(read_entry(&archive, "text/hello.txt"), decrypted);
+}
+
+#[test]
+fn refuses_what_cant_be_packed() {
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    let name = "a".repeat(u16::MAX as usize + 1);
+    assert_eq!(writer.add_file(&name, TEXT).unwrap_err().kind(), ErrorKind::InvalidInput);
+    writer.add_file(&name[1..], TEXT).unwrap();
+
+    let folder = test_folder("unpackable");
+    let missing = folder.join("missing");
+    assert!(binaryflare::formats::pack_folder(&missing, &folder.join("data.xp3")).is_err());
+}
+
+#[test]
+fn extracts_to_a_folder() {
+    let folder = test_folder("extracts");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+    let save_folder = folder.join("out");
+
+    let report = archive.extract(&save_folder).unwrap();
+    assert_eq!(report.matched, 2);
+    assert!(report.bad_checksums.is_empty());
+    let paths: Vec<&Path> = report.entries.iter().map(|entry| entry.path.as_path()).collect();
+    assert_eq!(paths, [save_folder.join("text").join("hello.txt"), save_folder.join("raw.bin")]);
+    assert_eq!(report.entries[0].name, "text/hello.txt");
+    assert_eq!(report.entries[0].size, TEXT.len() as u64);
+    assert_eq!(report.entries[1].size, raw_bytes().len() as u64);
+    assert!(report.entries[0].offset < report.entries[1].offset);
+    assert_eq!(report.entries[1].attributes["segments"], "1");
+    assert_eq!(fs::read(save_folder.join("text").join("hello.txt")).unwrap(), TEXT);
+    assert_eq!(fs::read(save_folder.join("raw.bin")).unwrap(), raw_bytes());
+}
+
+#[test]
+fn keeps_xp3_item_chunks() {
+    let folder = test_folder("item_chunks");
+    let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let chunks = XP3ItemChunks {
+        modified: Some(modified),
+        raw: vec![(*b"test", vec![1, 2, 0xab]), ([0xe3, 0x81, 0x82, 0], vec![3])],
+    };
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_file_with("text/hello.txt", TEXT, &chunks).unwrap();
+    let path = folder.join("chunks.xp3");
+    fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+
+    let archive = Archive::open(&path).unwrap().unwrap();
+    let save_folder = folder.join("out");
+    let report = archive.extract(&save_folder).unwrap();
+    let attributes = &report.entries[0].attributes;
+    assert_eq!(attributes["time"], "126444736000000000");
+    assert_eq!(attributes["chunk:test"], "0102ab");
+    assert_eq!(attributes["chunk:0xe3818200"], "03");
+    let saved = save_folder.join("text").join("hello.txt");
+    assert_eq!(fs::metadata(&saved).unwrap().modified().unwrap(), modified);
+
+    //The attributes give back the same chunks
+    let chunks = XP3ItemChunks::from_attributes(attributes).unwrap();
+    assert_eq!(chunks.modified, Some(modified));
+    assert_eq!(chunks.raw, [([0xe3, 0x81, 0x82, 0], vec![3]), (*b"test", vec![1, 2, 0xab])]);
+    let mut broken = attributes.clone();
+    broken.insert(String::from("chunk:toolong"), String::from("00"));
+    assert!(XP3ItemChunks::from_attributes(&broken).is_err());
+
+    //Packing the folder keeps the times and the chunks saved next to the files
+    assert_eq!(report.entries[1].name, ".xp3_chunks.json");
+    let packed = folder.join("packed.xp3");
+    assert_eq!(binaryflare::formats::pack_folder(&save_folder, &packed).unwrap(), 1);
+    let repacked = Archive::open(&packed).unwrap().unwrap().extract(&folder.join("again")).unwrap();
+    assert_eq!(repacked.entries.len(), 2);
+    assert_eq!(&repacked.entries[0].attributes, attributes);
+}
+
+#[test]
+fn reports_bad_checksums() {
+    let folder = test_folder("checksums");
+    let path = make_archive(&folder);
+
+    //Change a byte of the raw item so it no longer matches its hash
+    let mut bytes = fs::read(&path).unwrap();
+    let raw = raw_bytes();
+    let start = bytes.windows(raw.len()).position(|window| window == &raw[..]).unwrap();
+    bytes[start] ^= 1;
+    fs::write(&path, &bytes).unwrap();
+
+    let options = FlareOptions {
+        bad_checksum: ChecksumPolicy::Delete,
+        ..FlareOptions::default()
+    };
+    let archive = Archive::open_with(&path, Format::XP3Archive, options);
+    let save_folder = folder.join("out");
+    let report = archive.extract(&save_folder).unwrap();
+
+    assert_eq!(report.bad_checksums, [save_folder.join("raw.bin")]);
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.entries[0].name, "text/hello.txt");
+    assert!(!save_folder.join("raw.bin").exists());
+    assert!(save_folder.join("text").join("hello.txt").exists());
+}
+
+#[test]
+fn streams_segments_bigger_than_the_copy_buffer() {
+    //Much bigger than the 64 KiB that's copied at a time, and different in every piece
+    let big: Vec<u8> = (0..300_000_u32).map(|i| ((i / 7) ^ (i >> 9)) as u8).collect();
+    let raw = raw_bytes();
+    let archive = make_segmented_archive(&[(true, &big), (false, &raw), (true, TEXT)]);
+    let folder = test_folder("segments");
+    let path = folder.join("data.xp3");
+    fs::write(&path, &archive).unwrap();
+
+    let mut data = big.clone();
+    data.extend(&raw);
+    data.extend(TEXT);
+    let xp3 = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    let mut sink = MemorySink::new();
+    let report = xp3.extract_to(&mut sink).unwrap();
+    assert!(report.failed.is_empty());
+    assert!(report.bad_checksums.is_empty());
+    assert_eq!(sink.files()[Path::new("big.bin")], data);
+    assert_eq!(read_entry(&xp3, "big.bin"), data);
+
+    //A segment that inflates into more than its size is stopped right after its size
+    let segm = archive.windows(4).rposition(|name| name == b"segm").unwrap() + 12;
+    let mut longer = archive.clone();
+    longer[segm + 12..segm + 20].copy_from_slice(&1000_u64.to_le_bytes());
+    //And one that inflates into less is caught too
+    let mut shorter = archive.clone();
+    shorter[segm + 12..segm + 20].copy_from_slice(&400_000_u64.to_le_bytes());
+    for (archive, size) in &[(longer, "1001"), (shorter, "300000")] {
+        fs::write(&path, archive).unwrap();
+        let xp3 = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+        let report = xp3.extract_to(&mut MemorySink::new()).unwrap();
+        assert!(report.entries.is_empty());
+        assert_eq!(report.failed.len(), 1);
+        let (name, err) = &report.failed[0];
+        assert_eq!(name, "big.bin");
+        assert!(err.contains(&format!("but was {}", size)), "{}", err);
+        assert_eq!(xp3.verify().unwrap(), ["big.bin"]);
+    }
+}
+
+#[test]
+fn keeps_flaring_past_broken_items() {
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_file("zeros.bin", &[0; 1000]).unwrap();
+    writer.add_file("raw.bin", &raw_bytes()).unwrap();
+    let mut bytes = writer.finish().unwrap().into_inner();
+
+    //Break the zlib header of the compressed item, which comes right after the 19 byte header
+    assert_eq!(bytes[19], 0x78);
+    bytes[19] = 0;
+    let folder = test_folder("broken_items");
+    let path = folder.join("data.xp3");
+    fs::write(&path, &bytes).unwrap();
+
+    let archive = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    let save_folder = folder.join("out");
+    let report = archive.extract(&save_folder).unwrap();
+
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.entries[0].name, "raw.bin");
+    assert_eq!(fs::read(save_folder.join("raw.bin")).unwrap(), raw_bytes());
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "zeros.bin");
+    assert!(!save_folder.join("zeros.bin").exists());
+    assert!(report.warnings.is_empty());
+
+    //Verifying doesn't stop at the broken item either
+    assert_eq!(archive.verify().unwrap(), ["zeros.bin"]);
+}
+
+#[test]
+fn only_stops_for_errors_that_break_the_sink() {
+    let folder = test_folder("sink_errors");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+
+    //A name that the sink can't use only loses that file
+    let mut sink = FailingSink {
+        files: MemorySink::new(),
+        fail: "text/hello.txt",
+        kind: ErrorKind::InvalidFilename,
+    };
+    let report = archive.extract_to(&mut sink).unwrap();
+    assert_eq!(report.entries.len(), 1);
+    assert_eq!(report.entries[0].name, "raw.bin");
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "text/hello.txt");
+    assert_eq!(sink.files.files().len(), 1);
+
+    //A full disk stops everything
+    sink = FailingSink {
+        files: MemorySink::new(),
+        fail: "text/hello.txt",
+        kind: ErrorKind::StorageFull,
+    };
+    assert!(archive.extract_to(&mut sink).is_err());
+}
+
+#[test]
+fn follows_the_overwrite_policy() {
+    let folder = test_folder("overwrite");
+    let path = make_archive(&folder);
+    let save_folder = folder.join("out");
+    let extract = |overwrite| {
+        let options = FlareOptions {
+            overwrite,
+            ..FlareOptions::default()
+        };
+        Archive::open_with(&path, Format::XP3Archive, options).extract(&save_folder)
+    };
+    extract(OverwritePolicy::Replace).unwrap();
+    fs::write(save_folder.join("raw.bin"), b"old").unwrap();
+
+    let report = extract(OverwritePolicy::Skip).unwrap();
+    assert_eq!(report.existing.len(), 2);
+    assert_eq!(fs::read(save_folder.join("raw.bin")).unwrap(), b"old");
+
+    let report = extract(OverwritePolicy::Rename).unwrap();
+    assert_eq!(report.renamed.len(), 2);
+    assert_eq!(fs::read(save_folder.join("raw (1).bin")).unwrap(), raw_bytes());
+
+    match extract(OverwritePolicy::Fail) {
+        Err(FlareError::OutputExists { .. }) => (),
+        _ => panic!("an existing file should stop the flare"),
+    }
+
+    extract(OverwritePolicy::Replace).unwrap();
+    assert_eq!(fs::read(save_folder.join("raw.bin")).unwrap(), raw_bytes());
+}
+
+#[test]
+fn verifies_checksums() {
+    let folder = test_folder("verifies");
+    let path = make_archive(&folder);
+    assert!(Archive::open(&path).unwrap().unwrap().verify().unwrap().is_empty());
+
+    //A wrong key changes every byte so nothing matches
+    let options = FlareOptions {
+        crypt: crypt::from_name("xor-key:01"),
+        ..FlareOptions::default()
+    };
+    let bad_items = Archive::open_with(&path, Format::XP3Archive, options).verify().unwrap();
+    assert_eq!(bad_items, ["text/hello.txt", "raw.bin"]);
+}
+
+#[test]
+fn extracts_into_memory() {
+    let folder = test_folder("memory");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+
+    let mut sink = MemorySink::new();
+    let report = archive.extract_to(&mut sink).unwrap();
+    assert_eq!(report.entries[0].path, Path::new("text/hello.txt"));
+
+    let files = sink.into_files();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[Path::new("text/hello.txt")], TEXT);
+    assert_eq!(files[Path::new("raw.bin")], raw_bytes());
+}
+
+#[test]
+fn extracts_into_a_tar() {
+    let folder = test_folder("tar");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+
+    let mut sink = TarSink::new(Vec::new());
+    archive.extract_to(&mut sink).unwrap();
+    let long_name = format!("{}/long.txt", "folder".repeat(20));
+    sink.save(Path::new(&long_name), 4, &mut &b"long"[..]).unwrap();
+    //Files that fail leave nothing behind, so the files after them can still be read
+    assert!(sink.save(Path::new("short.bin"), 600, &mut &b"short"[..]).is_err());
+    assert!(sink.save(Path::new("long.bin"), 2, &mut &b"long"[..]).is_err());
+    assert!(sink.save(Path::new("broken.bin"), 8, &mut (&b"part"[..]).chain(BrokenReader)).is_err());
+    assert!(!sink.exists(Path::new("short.bin")));
+    assert!(!sink.exists(Path::new("broken.bin")));
+    sink.save(Path::new("after.txt"), 5, &mut &b"after"[..]).unwrap();
+    assert!(sink.remove(Path::new("raw.bin")).is_err());
+
+    //Big files are read into a temporary file first instead of memory
+    let mut big_sink = TarSink::new(io::sink());
+    let size = 17 << 20;
+    big_sink.save(Path::new("big.bin"), size, &mut io::repeat(1).take(size)).unwrap();
+    assert!(big_sink.save(Path::new("broken.bin"), size,
+        &mut io::repeat(1).take(1 << 20).chain(BrokenReader)).is_err());
+    let tar = sink.into_inner().unwrap();
+
+    //Read back the name, type and data of every entry
+    let mut entries: Vec<(String, u8, Vec<u8>)> = Vec::new();
+    let mut offset = 0;
+    while tar[offset] != 0 {
+        let header = &tar[offset..offset + 512];
+        let name_end = header[..100].iter().position(|&byte| byte == 0).unwrap_or(100);
+        let name = String::from_utf8(header[..name_end].to_vec()).unwrap();
+        let size_text = std::str::from_utf8(&header[124..135]).unwrap();
+        let size = usize::from_str_radix(size_text, 8).unwrap();
+        offset += 512;
+        entries.push((name, header[156], tar[offset..offset + size].to_vec()));
+        offset += size.div_ceil(512) * 512;
+    }
+    assert_eq!(tar.len(), offset + 1024);
+
+    assert_eq!(entries.len(), 5);
+    assert_eq!(entries[0], (String::from("text/hello.txt"), b'0', TEXT.to_vec()));
+    assert_eq!(entries[1], (String::from("raw.bin"), b'0', raw_bytes()));
+    assert_eq!(entries[2].1, b'L');
+    assert_eq!(entries[2].2, [long_name.as_bytes(), &[0]].concat());
+    assert_eq!(entries[3].2, b"long");
+    assert_eq!(entries[4], (String::from("after.txt"), b'0', b"after".to_vec()));
+}
+
+#[test]
+fn extracts_into_a_zip() {
+    let folder = test_folder("zip");
+    let archive = Archive::open(&make_archive(&folder)).unwrap().unwrap();
+
+    let mut sink = ZipSink::new(Vec::new());
+    archive.extract_to(&mut sink).unwrap();
+    //Files that fail leave nothing behind
+    assert!(sink.save(Path::new("short.bin"), 600, &mut &b"short"[..]).is_err());
+    assert!(sink.save(Path::new("broken.bin"), 8, &mut (&b"part"[..]).chain(BrokenReader)).is_err());
+    assert!(!sink.exists(Path::new("broken.bin")));
+    sink.save(Path::new("after.txt"), 5, &mut &b"after"[..]).unwrap();
+    let zip = sink.into_inner().unwrap();
+    assert!(!zip.windows(9).any(|name| name == b"short.bin"));
+    assert!(!zip.windows(10).any(|name| name == b"broken.bin"));
+
+    assert_eq!(&zip[..4], b"PK\x03\x04");
+    assert_eq!(&zip[30..44], b"text/hello.txt");
+    //The end of the central directory is the last 22 bytes, with the number of files in it
+    let end = &zip[zip.len() - 22..];
+    assert_eq!(&end[..4], b"PK\x05\x06");
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+    let directory = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+    assert_eq!(&zip[directory..directory + 4], b"PK\x01\x02");
+}
+
+#[test]
+fn spools_a_copy_of_saved_files() {
+    let folder = test_folder("spool");
+    let mut sink = SpoolSink::new(Box::new(MemorySink::new()), folder.clone());
+    sink.save(Path::new("text/hello.txt"), TEXT.len() as u64, &mut &TEXT[..]).unwrap();
+    assert!(sink.exists(Path::new("text/hello.txt")));
+    assert_eq!(sink.location(Path::new("text/hello.txt")), Path::new("text/hello.txt"));
+    assert_eq!(fs::read(folder.join("text").join("hello.txt")).unwrap(), TEXT);
+
+    sink.remove(Path::new("text/hello.txt")).unwrap();
+    assert!(!sink.exists(Path::new("text/hello.txt")));
+    assert!(!folder.join("text").join("hello.txt").exists());
+}
+
+#[test]
+fn limits_zips_without_zip64() {
+    let mut sink = ZipSink::new(io::sink());
+    let long_name = "a".repeat(70_000);
+    assert!(sink.save(Path::new(&long_name), 0, &mut io::empty()).is_err());
+    for index in 0..65_535 {
+        sink.save(Path::new(&index.to_string()), 0, &mut io::empty()).unwrap();
+    }
+    assert!(sink.save(Path::new("one_more"), 0, &mut io::empty()).is_err());
+    sink.finish().unwrap();
+}
+
+#[test]
+fn reports_truncated_archives() {
+    let folder = test_folder("truncated");
+    let path = make_archive(&folder);
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+    let archive = Archive::open_with(&path, Format::XP3Archive, FlareOptions::default());
+    assert!(archive.entries().is_err());
+}
+
+#[test]
+fn reads_nothing_past_the_end() {
+    let mut stream = SliceStream::new(&[1, 2, 3][..], true);
+    stream.seek(SeekFrom::Start(10)).unwrap();
+    assert_eq!(stream.read_borrowed(0).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(stream.read_slice(0).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+    //An empty Hxv4 table that starts after the end of the archive
+    let folder = test_folder("past_the_end");
+    let path = folder.join("data.xp3");
+    let files: [(&str, &[u8]); 2] = [("", b"first"), ("", b"second")];
+    let mut archive = make_hashed_archive(&files, "first.txt", "second.txt");
+    let hxv4 = archive.windows(4).position(|name| name == b"Hxv4").unwrap() + 12;
+    archive[hxv4..hxv4 + 8].copy_from_slice(&0x10_0000_u64.to_le_bytes());
+    archive[hxv4 + 8..hxv4 + 14].copy_from_slice(&[0; 6]);
+    fs::write(&path, archive).unwrap();
+    assert!(Archive::open(&path).unwrap().unwrap().entries().is_err());
+}
+
+#[test]
+fn reads_slices_without_copying() {
+    let data: Vec<u8> = (0..32).collect();
+    let mut stream = SliceStream::new(&data[..], true);
+    assert_eq!(Stream::read::<u32>(&mut stream).unwrap(), 0x0302_0100);
+    stream.little_endian(false);
+    assert_eq!(Stream::read::<u16>(&mut stream).unwrap(), 0x0405);
+
+    //Slices and sub streams point straight into the data
+    let slice = stream.read_slice(4).unwrap();
+    assert_eq!(slice.as_ptr(), data[6..].as_ptr());
+    let mut sub = stream.sub_stream(8).unwrap();
+    assert_eq!(sub.len(), 8);
+    assert!(!sub.is_little_endian());
+    assert_eq!(Stream::read::<u8>(&mut sub).unwrap(), 10);
+    match sub.read_borrowed(7).unwrap() {
+        Cow::Borrowed(bytes) => assert_eq!(bytes.as_ptr(), data[11..].as_ptr()),
+        Cow::Owned(_) => panic!("The bytes were copied"),
+    }
+    assert_eq!(Stream::read::<u8>(&mut sub).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(stream.pos(), 18);
+    assert_eq!(stream.remaining(), &data[18..]);
+
+    //A failed read doesn't move
+    assert!(stream.read_slice(100).is_err());
+    assert_eq!(stream.pos(), 18);
+    stream.seek(SeekFrom::End(-2)).unwrap();
+    let mut rest = Vec::new();
+    Read::read_to_end(&mut stream, &mut rest).unwrap();
+    assert_eq!(rest, [30, 31]);
+    assert!(stream.seek(SeekFrom::Current(-100)).is_err());
+
+    //Files are read the same whether they can be mapped or not
+    let folder = test_folder("file_stream");
+    let path = folder.join("data.bin");
+    fs::write(&path, &data).unwrap();
+    let mut file = FileStream::open(&path, true).unwrap();
+    assert!(matches!(file, FileStream::Mapped(_)));
+    file.seek(SeekFrom::Start(4)).unwrap();
+    assert_eq!(Stream::read::<u32>(&mut file).unwrap(), 0x0706_0504);
+    assert_eq!(&*file.read_borrowed(4).unwrap(), &data[8..12]);
+    assert_eq!(file.len(), 32);
+    fs::write(&path, b"").unwrap();
+    let mut empty = FileStream::open(&path, true).unwrap();
+    assert_eq!(empty.len(), 0);
+    assert_eq!(Stream::read::<u8>(&mut empty).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn filters_items_by_name() {
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    for name in &["scenario/first.ks", "image/bg.png", "scenario/skip.ks", "scenario\\second.ks",
+        "sound/bgm.ogg"] {
+        writer.add_file(name, name.as_bytes()).unwrap();
+    }
+    let folder = test_folder("filters");
+    let path = folder.join("data.xp3");
+    fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+
+    let mut options = FlareOptions::default();
+    options.filter.include("scenario/").unwrap();
+    options.filter.include("*.png").unwrap();
+    options.filter.exclude("*skip*").unwrap();
+    assert!(options.filter.matches("scenario\\first.ks"));
+    assert!(!options.filter.matches("scenario/skip.ks"));
+    assert!(!options.filter.matches("sound/bgm.ogg"));
+
+    let archive = Archive::open_with(&path, Format::XP3Archive, options);
+    let mut sink = MemorySink::new();
+    let report = archive.extract_to(&mut sink).unwrap();
+    let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["scenario/first.ks", "image/bg.png", "scenario\\second.ks"]);
+    assert_eq!((report.matched, report.skipped), (3, 2));
+    assert_eq!(sink.files().len(), 3);
+    assert_eq!(archive.verify().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn keeps_member_paths_inside_the_save_folder() {
+    let save_folder = Path::new("save");
+    let reserved = if cfg!(windows) { ("_CON.txt", true) } else { ("CON.txt", false) };
+    let cases = [
+        ("../../etc/passwd", "__/__/etc/passwd", true),
+        ("/absolute/file.txt", "absolute/file.txt", true),
+        ("folder\\sub\\file.txt", "folder/sub/file.txt", false),
+        ("folder/../..\\file.txt", "folder/__/__/file.txt", true),
+        ("./a//b", "a/b", true),
+        ("", "_", true),
+        ("/", "_", true),
+        ("nul\0char", "nul_char", true),
+        ("CON.txt", reserved.0, reserved.1),
+    ];
+    for (name, expected, rewritten) in &cases {
+        let (path, was_rewritten) = file_utils::member_path(save_folder, name);
+        assert_eq!(path, save_folder.join(expected), "{:?}", name);
+        assert_eq!(was_rewritten, *rewritten, "{:?}", name);
+        assert!(path.starts_with(save_folder));
+        assert!(path.components().all(|part| matches!(part, Component::Normal(_))), "{:?}", name);
+    }
+
+    //An archive can't save anything outside of the folder it's flared into
+    let folder = test_folder("traversal");
+    let mut writer = XP3Writer::new(Cursor::new(Vec::new())).unwrap();
+    writer.add_file("../../escape.txt", b"escaped").unwrap();
+    let path = folder.join("evil.xp3");
+    fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+    let save_folder = folder.join("out");
+    let report = Archive::open(&path).unwrap().unwrap().extract(&save_folder).unwrap();
+    let saved = save_folder.join("__").join("__").join("escape.txt");
+    assert_eq!(report.entries[0].path, saved);
+    assert_eq!(report.renamed, [(String::from("../../escape.txt"), saved.clone())]);
+    assert_eq!(fs::read(&saved).unwrap(), b"escaped");
+    assert!(!folder.parent().unwrap().join("escape.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn walks_folders() {
+    use std::os::unix::fs::symlink;
+
+    let folder = test_folder("walks");
+    fs::create_dir_all(folder.join("a").join("b")).unwrap();
+    for file in &["top.txt", "a/middle.txt", "a/b/bottom.txt"] {
+        fs::write(folder.join(file), TEXT).unwrap();
+    }
+    symlink(&folder, folder.join("a").join("loop")).unwrap();
+    symlink(folder.join("top.txt"), folder.join("a").join("link.txt")).unwrap();
+
+    let relative = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+        files.iter().map(|file| file.strip_prefix(&folder).unwrap().to_path_buf()).collect()
+    };
+
+    let (files, errors) = file_utils::walk_files(&folder, None, SymlinkPolicy::Skip);
+    assert!(errors.is_empty());
+    assert_eq!(relative(files), [Path::new("a/b/bottom.txt"), Path::new("a/middle.txt"),
+        Path::new("top.txt")]);
+
+    let (files, _) = file_utils::walk_files(&folder, Some(0), SymlinkPolicy::Skip);
+    assert_eq!(relative(files), [Path::new("top.txt")]);
+
+    //The loop only gets walked once
+    let (files, errors) = file_utils::walk_files(&folder, None, SymlinkPolicy::Follow);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(relative(files), [Path::new("a/b/bottom.txt"), Path::new("a/link.txt"),
+        Path::new("a/middle.txt"), Path::new("top.txt")]);
+}
+
+#[test]
+fn gives_file_stems() {
+    assert_eq!(file_utils::file_stem(Path::new("data/scenario.xp3")).as_deref(), Some("scenario"));
+    assert_eq!(file_utils::file_stem(Path::new("..")), None);
+    assert_eq!(file_utils::file_stem(Path::new("/")), None);
+}